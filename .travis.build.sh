@@ -13,7 +13,7 @@ fi
 
 cargo fmt --all -- --check
 cargo build --all
-cargo test --all
+cargo test --all --all-features
 
 cd wasmjit-harness
 cargo run -- -q
@@ -0,0 +1,135 @@
+//! Precompiled module artifacts, so the compilation of a contract can be
+//! cached on disk and skipped on the next load.
+
+use crate::error::Error;
+use crate::executor::{build_isa, Module};
+use cranelift_codegen::ir;
+use cranelift_entity::PrimaryMap;
+use cranelift_wasm::DefinedFuncIndex;
+use hmac_sha256::Hash;
+use ontio_wasmjit_environ::{
+    BuildOption, HostCallCharge, Module as ModuleInfo, OwnedDataInitializer, Relocations, Traps,
+    Tunables,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Identifies the artifact format, the code is only valid for the compiler
+/// which generated it.
+pub(crate) const ARTIFACT_VERSION: &str =
+    concat!("ontio-wasmjit-artifact/", env!("CARGO_PKG_VERSION"));
+
+/// Distinguishes the temporary files of the threads of a process.
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Sha256 of the settings the code is generated with: the target isa with its
+/// shared flags, and the `tunables`, which select the isa flags.
+fn settings_hash(tunables: &Tunables) -> Result<[u8; 32], Error> {
+    let isa = build_isa(tunables);
+    let tunables = bincode::serialize(tunables)
+        .map_err(|e| Error::Internal(format!("failed to serialize tunables: {}", e)))?;
+    let mut hasher = Hash::new();
+    hasher.update(isa.triple().to_string().as_bytes());
+    hasher.update(isa.flags().to_string().as_bytes());
+    hasher.update(&tunables);
+    Ok(hasher.finalize())
+}
+
+#[derive(Serialize)]
+pub(crate) struct ArtifactRef<'a> {
+    pub info: &'a ModuleInfo,
+    pub tunables: &'a Tunables,
+    pub data_initializers: &'a [OwnedDataInitializer],
+    pub bodies: Vec<&'a [u8]>,
+    pub jt_offsets: &'a PrimaryMap<DefinedFuncIndex, ir::JumpTableOffsets>,
+    pub relocs: &'a Relocations,
+    pub traps: &'a Traps,
+    pub host_call_charge: HostCallCharge,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Artifact {
+    pub info: ModuleInfo,
+    pub tunables: Tunables,
+    pub data_initializers: Vec<OwnedDataInitializer>,
+    pub bodies: Vec<Vec<u8>>,
+    pub jt_offsets: PrimaryMap<DefinedFuncIndex, ir::JumpTableOffsets>,
+    pub relocs: Relocations,
+    pub traps: Traps,
+    pub host_call_charge: HostCallCharge,
+}
+
+/// Serialize `artifact` after a header of the artifact version and the hash
+/// of its compilation settings.
+pub(crate) fn serialize(artifact: &ArtifactRef) -> Result<Vec<u8>, Error> {
+    let settings = settings_hash(artifact.tunables)?;
+    bincode::serialize(&(ARTIFACT_VERSION, settings, artifact))
+        .map_err(|e| Error::Internal(format!("failed to serialize module: {}", e)))
+}
+
+/// Deserialize an artifact, refusing it if it was written by another version
+/// or if the settings it was compiled with are not those this build would use
+/// for its tunables.
+pub(crate) fn deserialize(bytes: &[u8]) -> Result<Artifact, Error> {
+    let (version, settings, artifact): (String, [u8; 32], Artifact) =
+        bincode::deserialize(bytes)
+            .map_err(|e| Error::Internal(format!("failed to deserialize module: {}", e)))?;
+    if version != ARTIFACT_VERSION {
+        return Err(Error::Internal(format!(
+            "incompatible module artifact: {}, expected {}",
+            version, ARTIFACT_VERSION
+        )));
+    }
+    if settings != settings_hash(&artifact.tunables)? {
+        return Err(Error::Internal(String::from(
+            "stale module artifact: compiled with other isa flags or tunables",
+        )));
+    }
+    Ok(artifact)
+}
+
+/// Compile `wasm` and write the serialized module to `path`.
+///
+/// The artifact is written to a temporary file next to `path` and renamed
+/// over it, so `path` never holds a partially written artifact. The name of
+/// the temporary file is unique to the call, so concurrent writers of `path`
+/// do not write into each other's file.
+pub fn compile_to_file(
+    wasm: &[u8],
+    build_option: BuildOption,
+    tunables: Tunables,
+    path: &Path,
+) -> Result<(), Error> {
+    let module = Module::compile_with_tunables(wasm, build_option, tunables)?;
+    let bytes = module.serialize()?;
+
+    let io_error = |e| Error::Internal(format!("failed to write {}: {}", path.display(), e));
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    let counter = TMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    tmp_name.push(format!(".tmp.{}.{}", process::id(), counter));
+    let tmp_path = path.with_file_name(tmp_name);
+
+    // a file left at this name by another process is not ours to remove
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&tmp_path)
+        .map_err(io_error)?;
+    let written = file.write_all(&bytes).and_then(|_| file.sync_all());
+    if let Err(e) = written.and_then(|_| fs::rename(&tmp_path, path)) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(io_error(e));
+    }
+    Ok(())
+}
+
+/// Load a module written by `compile_to_file`, see `Module::deserialize`.
+pub fn load_from_file(path: &Path) -> Result<Module, Error> {
+    let bytes = fs::read(path)
+        .map_err(|e| Error::Internal(format!("failed to read {}: {}", path.display(), e)))?;
+    Module::deserialize(&bytes)
+}
@@ -3,10 +3,12 @@ use cranelift_wasm::DefinedMemoryIndex;
 use hmac_sha256::Hash;
 use ontio_wasmjit_runtime::builtins::{check_host_panic, wasmjit_result_err_trap};
 use ontio_wasmjit_runtime::{
-    wasmjit_unwind, ExecMetrics, Instance, VMContext, VMFunctionBody, VMFunctionImport,
+    wasmjit_trap, wasmjit_unwind, ExecMetrics, Instance, TrapCode, VMContext, VMFunctionBody,
+    VMFunctionImport,
 };
 use std::any::Any;
 use std::panic;
+use std::ptr;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
@@ -35,6 +37,9 @@ pub const PER_UNIT_CODE_LEN: u64 = 1024;
 
 pub const SHA256_GAS: u64 = 10;
 
+/// Upper bound of the data a contract can hand back through `env.ret`.
+pub const MAX_RETURN_DATA_LEN: u32 = 64 * 1024;
+
 #[derive(Default)]
 pub struct ChainCtx {
     height: u32,
@@ -49,6 +54,8 @@ pub struct ChainCtx {
     output: Vec<u8>,
     service_index: u64,
     from_return: bool,
+    return_data_set: bool,
+    events: Vec<Vec<u8>>,
 }
 
 impl ChainCtx {
@@ -123,6 +130,36 @@ impl ChainCtx {
         self.from_return
     }
 
+    /// whether the contract has already set its return data, through `env.ret`
+    /// or `ontio_return`
+    pub fn is_return_data_set(&self) -> bool {
+        self.return_data_set
+    }
+
+    /// set the return data of the execution, which can only be set once
+    pub(crate) fn set_return_data(&mut self, data: Vec<u8>) -> Result<(), String> {
+        if self.return_data_set {
+            return Err(String::from("wasmjit: return data already set"));
+        }
+        self.set_output(data);
+        self.return_data_set = true;
+        Ok(())
+    }
+
+    /// events emitted through `ontio_notify`, in emission order
+    pub fn events(&self) -> &[Vec<u8>] {
+        &self.events
+    }
+
+    pub fn take_events(&mut self) -> Vec<Vec<u8>> {
+        std::mem::replace(&mut self.events, Vec::new())
+    }
+
+    pub(crate) fn reset_call_state(&mut self) {
+        self.from_return = false;
+        self.return_data_set = false;
+    }
+
     pub fn service_index(&self) -> u64 {
         self.service_index
     }
@@ -151,6 +188,8 @@ impl ChainCtx {
             output: Vec::new(),
             service_index,
             from_return: false,
+            return_data_set: false,
+            events: Vec::new(),
         }
     }
 }
@@ -161,10 +200,15 @@ pub unsafe extern "C" fn ontio_runtime_check_gas(vmctx: *mut VMContext, costs: u
     if !instance.check_gas(costs) {
         let msg = String::from("wasmjit: gas exhausted");
         instance.set_trap_kind(wasmjit_result_err_trap);
-        wasmjit_unwind(msg)
+        wasmjit_trap(TrapCode::OutOfGas, msg)
     }
 }
 
+/// Get the memory range `[start, start + len)` of the default memory.
+///
+/// Following the bulk memory semantics a zero length range is valid as long as
+/// `start` does not go past the end of the memory, so `start == memory.len()`
+/// is a no-op instead of a trap.
 pub fn get_memory_and_check_bound(
     instance: &mut Instance,
     start: usize,
@@ -191,7 +235,7 @@ where
     check_host_panic(instance, |instance| {
         if gas != 0 && !instance.check_gas(gas) {
             let msg = String::from("wasmjit: gas exhausted");
-            unsafe { wasmjit_unwind(msg) }
+            unsafe { wasmjit_trap(TrapCode::OutOfGas, msg) }
         }
         func(instance)
     })
@@ -390,6 +434,22 @@ pub unsafe extern "C" fn ontio_sha256(vmctx: *mut VMContext, data_ptr: u32, l: u
     });
 }
 
+/// Implementation of ontio_notify api.
+///
+/// The fee of an event is not fixed by the chain, the api charges no gas
+/// itself. An embedder charges it as the cost of the import, see
+/// `Resolver::resolve_cost`.
+#[no_mangle]
+pub unsafe extern "C" fn ontio_notify(vmctx: *mut VMContext, data_ptr: u32, l: u32) {
+    check_host_panic((&mut *vmctx).instance(), |instance| {
+        let memory = get_memory_and_check_bound(instance, data_ptr as usize, l as usize)?;
+        let event = memory.to_vec();
+        let chain = convert_chainctx(instance.host_state());
+        chain.events.push(event);
+        Ok(())
+    });
+}
+
 /// Implementation of ontio_return api
 #[no_mangle]
 pub unsafe extern "C" fn ontio_return(vmctx: *mut VMContext, data_ptr: u32, l: u32) -> ! {
@@ -403,7 +463,7 @@ pub unsafe extern "C" fn ontio_return(vmctx: *mut VMContext, data_ptr: u32, l: u
         let host = instance.host_state();
         let chain = convert_chainctx(host);
 
-        chain.set_output(output_buffer);
+        chain.set_return_data(output_buffer)?;
         chain.set_from_return();
         Ok(())
     });
@@ -411,9 +471,33 @@ pub unsafe extern "C" fn ontio_return(vmctx: *mut VMContext, data_ptr: u32, l: u
     wasmjit_unwind(String::new())
 }
 
+/// Implementation of env.ret api.
+///
+/// Copy `[data_ptr, data_ptr + l)` into the output buffer, where the embedder
+/// reads it after the execution. Unlike `ontio_return` the execution goes on
+/// after the call. The return data can only be set once per execution, a
+/// second call traps, as does a later `ontio_return` and data longer than
+/// `MAX_RETURN_DATA_LEN`.
+#[no_mangle]
+pub unsafe extern "C" fn env_ret(vmctx: *mut VMContext, data_ptr: u32, l: u32) {
+    check_host_panic((&mut *vmctx).instance(), |instance| {
+        if l > MAX_RETURN_DATA_LEN {
+            return Err(format!(
+                "wasmjit: return data length {} exceeds limit {}",
+                l, MAX_RETURN_DATA_LEN
+            ));
+        }
+        let memory = get_memory_and_check_bound(instance, data_ptr as usize, l as usize)?;
+        let output_buffer = memory.to_vec();
+
+        let chain = convert_chainctx(instance.host_state());
+        chain.set_return_data(output_buffer)?;
+        Ok(())
+    });
+}
+
 /*
 const SIGNATURES: [(&str, &[ValueType], Option<ValueType>); 24] = [
-    ("ontio_notify", &[ValueType::I32; 2], None),
     ("ontio_call_contract", &[ValueType::I32; 3], Some(ValueType::I32)),
     ("ontio_contract_create", &[ValueType::I32; 14], Some(ValueType::I32)),
     ("ontio_contract_migrate", &[ValueType::I32; 14], Some(ValueType::I32)),
@@ -432,48 +516,71 @@ impl Resolver for ChainResolver {
         match field {
             "ontio_timestamp" => Some(VMFunctionImport {
                 body: ontio_timestamp as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             "ontio_block_height" => Some(VMFunctionImport {
                 body: ontio_block_height as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             "ontio_input_length" => Some(VMFunctionImport {
                 body: ontio_input_length as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             "ontio_call_output_length" => Some(VMFunctionImport {
                 body: ontio_call_output_length as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             "ontio_get_input" => Some(VMFunctionImport {
                 body: ontio_get_input as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             "ontio_current_blockhash" => Some(VMFunctionImport {
                 body: ontio_current_blockhash as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             "ontio_current_txhash" => Some(VMFunctionImport {
                 body: ontio_current_txhash as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             "ontio_self_address" => Some(VMFunctionImport {
                 body: ontio_self_address as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             "ontio_caller_address" => Some(VMFunctionImport {
                 body: ontio_caller_address as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             "ontio_entry_address" => Some(VMFunctionImport {
                 body: ontio_entry_address as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             "ontio_check_witness" => Some(VMFunctionImport {
                 body: ontio_check_witness as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             "ontio_sha256" => Some(VMFunctionImport {
                 body: ontio_sha256 as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             "ontio_get_call_output" => Some(VMFunctionImport {
                 body: ontio_get_call_output as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             "ontio_panic" => Some(VMFunctionImport {
                 body: ontio_panic as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
+            }),
+            "ontio_notify" => Some(VMFunctionImport {
+                body: ontio_notify as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             "ontio_return" => Some(VMFunctionImport {
                 body: ontio_return as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
+            }),
+            "ret" => Some(VMFunctionImport {
+                body: env_ret as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             _ => None,
         }
@@ -4,14 +4,21 @@ use capstone::Capstone;
 use std::fmt::Write;
 
 pub fn print_disassembly(code: &[u8]) {
+    println!("Disassembly of {} bytes:", code.len());
+    print!("{}", disassemble(code, 0x0).unwrap());
+}
+
+/// Disassemble the x86_64 `code` located at `address`, one instruction per
+/// line with its address and bytes.
+pub fn disassemble(code: &[u8], address: u64) -> Result<String, String> {
     let cs = Capstone::new()
         .x86()
         .mode(arch::x86::ArchMode::Mode64)
         .build()
-        .unwrap();
+        .map_err(|e| e.to_string())?;
 
-    println!("Disassembly of {} bytes:", code.len());
-    let insns = cs.disasm_all(&code, 0x0).unwrap();
+    let mut output = String::new();
+    let insns = cs.disasm_all(&code, address).map_err(|e| e.to_string())?;
     for i in insns.iter() {
         let mut line = String::new();
 
@@ -41,6 +48,8 @@ pub fn print_disassembly(code: &[u8]) {
             write!(&mut line, "{}", s).unwrap();
         }
 
-        println!("{}", line);
+        writeln!(&mut output, "{}", line).unwrap();
     }
+
+    Ok(output)
 }
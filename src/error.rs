@@ -1,6 +1,7 @@
+use cranelift_codegen::ir;
 use failure::Fail;
 use ontio_wasmjit_environ::CompileError;
-use ontio_wasmjit_runtime::InstantiationError;
+use ontio_wasmjit_runtime::{InstantiationError, Trap};
 
 #[derive(Fail, Debug)]
 pub enum Error {
@@ -15,7 +16,38 @@ pub enum Error {
     Link(String),
 
     #[fail(display = "wasm trap: {}", _0)]
-    Trap(String),
+    Trap(Trap),
+
+    #[fail(display = "signature mismatch: expected {}, found {}", expected, found)]
+    SignatureMismatch { expected: String, found: String },
+
+    #[fail(display = "parse error: {}", _0)]
+    Parse(ParseError),
+}
+
+/// The input of `execute_any` is neither a valid text nor a valid binary module.
+#[derive(Fail, Debug)]
+pub enum ParseError {
+    /// The input has no wasm magic number and does not assemble as WAT.
+    #[fail(display = "invalid WAT text: {}", _0)]
+    Text(String),
+
+    /// The input has the wasm magic number but is not a well formed binary.
+    #[fail(display = "invalid binary module: {}", _0)]
+    Binary(String),
+}
+
+impl Error {
+    pub(crate) fn signature_mismatch(expected: &[ir::Type], found: &[ir::Type]) -> Self {
+        let format = |types: &[ir::Type]| {
+            let types: Vec<_> = types.iter().map(ToString::to_string).collect();
+            format!("({})", types.join(", "))
+        };
+        Error::SignatureMismatch {
+            expected: format(expected),
+            found: format(found),
+        }
+    }
 }
 
 impl From<InstantiationError> for Error {
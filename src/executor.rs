@@ -1,98 +1,504 @@
-use crate::chain_api::ChainCtx;
+use crate::chain_api::{ChainCtx, ChainResolver, H256};
 use crate::resolver::Resolver;
 use crate::trampoline::make_trampoline;
-use crate::{error::Error, linker, utils};
+use crate::value::{Value, WasmArgs, WasmParams, WasmResults};
+use crate::{artifact, disassm, error::Error, linker, utils};
 
 use cranelift_codegen::ir;
 use cranelift_codegen::isa;
 use cranelift_codegen::settings;
 use cranelift_codegen::settings::Configurable;
-use cranelift_entity::PrimaryMap;
-use cranelift_wasm::DefinedFuncIndex;
+use cranelift_entity::{BoxedSlice, EntityRef, PrimaryMap};
+use cranelift_wasm::{
+    DefinedFuncIndex, DefinedMemoryIndex, DefinedTableIndex, FuncIndex, GlobalIndex,
+    ModuleTranslationState, WasmError, WasmResult,
+};
 use ontio_wasmjit_environ::{
-    compile_module, BuildOption, CompileError, Module as ModuleInfo, ModuleEnvironment,
-    OwnedDataInitializer, Relocations, Traps, Tunables,
+    compile_module, translate_function, BuildOption, CompileError, ExportKind, ExportType,
+    Features, FunctionBodyData, HostCallCharge, Module as ModuleInfo, ModuleEnvironment,
+    ModuleTranslation, OwnedDataInitializer, OwnedModuleTranslation, Relocations,
+    SignatureRegistry, Traps, Tunables, WASM_PAGE_SIZE,
 };
 use ontio_wasmjit_runtime::builtins::{wasmjit_result_err_trap, wasmjit_result_kind};
 use ontio_wasmjit_runtime::{
-    get_mut_trap_registry, wasmjit_call, wasmjit_call_trampoline, InstanceHandle,
-    TrapRegistrationGuard, VMFunctionBody,
+    get_mut_trap_registry, prefault, wasmjit_call, wasmjit_call_trampoline, ExecMetrics,
+    ExportFunc, FrameInfo, HostCallCost, HostCallHook, InstanceHandle, InstanceSnapshot,
+    MemoryAccess, MemoryBudget, Trap, TrapCode, TrapRegistrationGuard, VMFunctionBody,
+    VMFunctionImport,
 };
 
 use dynasmrt::mmap::MutableBuffer;
 use dynasmrt::ExecutableBuffer;
+use hmac_sha256::Hash;
 use lru::LruCache;
 use once_cell::sync::Lazy;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, MutexGuard};
+use std::any::Any;
+use std::cell::RefCell;
 use std::convert::TryFrom;
-use std::{mem, sync::Arc, usize};
+use std::io::Read;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use std::{cmp, mem, panic, sync::Arc, thread, usize};
 use target_lexicon::PointerWidth;
 
 static MODULE_CACHE: Lazy<Mutex<LruCache<[u8; 20], Arc<Module>>>> =
     Lazy::new(|| Mutex::new(LruCache::new(20)));
 
+/// A global of a module, by its index in the global index space or by the
+/// name it is exported under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlobalRef<'a> {
+    Index(u32),
+    Name(&'a str),
+}
+
+impl From<u32> for GlobalRef<'_> {
+    fn from(index: u32) -> Self {
+        GlobalRef::Index(index)
+    }
+}
+
+impl<'a> From<&'a str> for GlobalRef<'a> {
+    fn from(name: &'a str) -> Self {
+        GlobalRef::Name(name)
+    }
+}
+
 pub struct Instance {
     #[allow(unused)]
     module: Arc<Module>,
     handle: InstanceHandle,
+    /// Instances whose exports are imported, directly or through another
+    /// linked instance. They must outlive this one, and are locked during its
+    /// calls.
+    linked: Vec<Arc<Mutex<Instance>>>,
+}
+
+/// The `linked` instances locked for a call, since the code calling their
+/// exports bypasses their `Mutex`. A linked instance already locked, by
+/// another thread or by the caller itself, fails the call with an
+/// `Error::Link` rather than waiting, which could deadlock.
+///
+/// Until the call returns they run with the `ExecMetrics` of the caller, so
+/// the gas, the steps and the call depth used by the library code are
+/// charged to the call, and they are restored to their own ones on drop.
+struct LinkedCall<'a> {
+    guards: Vec<MutexGuard<'a, Instance>>,
+    exec_metrics: Vec<Arc<ExecMetrics>>,
+}
+
+impl<'a> LinkedCall<'a> {
+    fn enter(
+        linked: &'a [Arc<Mutex<Instance>>],
+        exec_metrics: &Arc<ExecMetrics>,
+    ) -> Result<Self, Error> {
+        let mut guards = linked
+            .iter()
+            .map(|instance| {
+                instance.try_lock().ok_or_else(|| {
+                    Error::Link(String::from("a linked instance is in use by another call"))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let exec_metrics = guards
+            .iter_mut()
+            .map(|guard| {
+                let instance = guard.handle.instance_mut();
+                instance.set_trap_kind(wasmjit_result_err_trap);
+                mem::replace(&mut instance.exec_metrics, exec_metrics.clone())
+            })
+            .collect();
+        Ok(Self {
+            guards,
+            exec_metrics,
+        })
+    }
+
+    /// The kind of the error ending the call, `caller_kind` unless the code
+    /// of a linked instance failed otherwise than by a trap.
+    fn trap_kind(&mut self, caller_kind: wasmjit_result_kind) -> wasmjit_result_kind {
+        self.guards
+            .iter_mut()
+            .map(|guard| guard.handle.trap_kind())
+            .find(|&kind| kind != wasmjit_result_err_trap)
+            .unwrap_or(caller_kind)
+    }
+
+    /// The linked instance whose code holds `pc`.
+    fn instance_at(&self, pc: Option<usize>) -> Option<&Instance> {
+        let pc = pc?;
+        self.guards
+            .iter()
+            .map(|guard| &**guard)
+            .find(|instance| instance.module.code_contains(pc))
+    }
+
+    fn instance_at_mut(&mut self, pc: Option<usize>) -> Option<&mut Instance> {
+        let pc = pc?;
+        self.guards
+            .iter_mut()
+            .map(|guard| &mut **guard)
+            .find(|instance| instance.module.code_contains(pc))
+    }
+}
+
+impl Drop for LinkedCall<'_> {
+    fn drop(&mut self) {
+        for (guard, exec_metrics) in self.guards.iter_mut().zip(self.exec_metrics.drain(..)) {
+            guard.handle.instance_mut().exec_metrics = exec_metrics;
+        }
+    }
 }
 
 unsafe impl Send for Instance {}
 
+/// Everything a transaction receipt needs from an execution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionReceipt {
+    /// Value returned by the export function.
+    pub result: Option<i64>,
+    /// Gas consumed by the execution.
+    pub gas_used: u64,
+    /// Events emitted through `ontio_notify`, in emission order.
+    pub events: Vec<Vec<u8>>,
+    /// Data set through `ontio_return` or `env.ret`.
+    pub return_data: Vec<u8>,
+    /// Sha256 of the linear memories and mutable globals after the
+    /// execution, see `Instance::state_hash`.
+    pub state_hash: H256,
+}
+
 impl Instance {
-    pub fn execute(
+    pub fn execute<A: WasmArgs>(
         &mut self,
         chain: ChainCtx,
         func: &str,
-        args: Vec<i64>,
+        args: A,
     ) -> Result<Option<i64>, Error> {
+        self.set_host_state(Box::new(chain));
+        self.call(func, args)
+    }
+
+    /// Execute an export function like `execute`, and collect the outcome of
+    /// the execution into an `ExecutionReceipt`.
+    pub fn try_execute_full<A: WasmArgs>(
+        &mut self,
+        chain: ChainCtx,
+        func: &str,
+        args: A,
+    ) -> Result<ExecutionReceipt, Error> {
+        let gas_before = chain.gas_left();
+        let result = self.execute(chain, func, args)?;
+
+        let state_hash = self.state_hash();
+        let host = self.host_state();
+        Ok(ExecutionReceipt {
+            result,
+            gas_used: gas_before.saturating_sub(host.gas_left()),
+            events: host.take_events(),
+            return_data: host.take_output(),
+            state_hash,
+        })
+    }
+
+    /// Sha256 of the content of the linear memories in index order, followed
+    /// by the little endian bits of the mutable globals in index order.
+    pub fn state_hash(&self) -> H256 {
+        let instance = self.handle.instance();
+        let mut hasher = Hash::new();
+        let mut index = 0;
+        while let Some(memory) = instance.memory_slice(DefinedMemoryIndex::new(index)) {
+            hasher.update(memory);
+            index += 1;
+        }
+        for (index, global) in self.module.info.globals.iter() {
+            if !global.mutability {
+                continue;
+            }
+            match self.get_global(index.as_u32()) {
+                Ok(Value::I32(val)) => hasher.update(&val.to_le_bytes()),
+                Ok(Value::I64(val)) => hasher.update(&val.to_le_bytes()),
+                Ok(Value::F32(val)) => hasher.update(&val.to_bits().to_le_bytes()),
+                Ok(Value::F64(val)) => hasher.update(&val.to_bits().to_le_bytes()),
+                Err(_) => (),
+            }
+        }
+        hasher.finalize()
+    }
+
+    /// Call an export function with the current host state.
+    ///
+    /// The instance can be called any number of times, the linear memory and
+    /// globals are kept between calls. Only the per call state of the host
+    /// (the return flag of `ontio_return` and `env.ret`) is reset.
+    ///
+    /// The arguments are checked against the parameter types of the function,
+    /// a mismatch is reported as `Error::SignatureMismatch`.
+    pub fn call<A: WasmArgs>(&mut self, func: &str, args: A) -> Result<Option<i64>, Error> {
         let invoke = self
             .handle
             .lookup(func)
             .ok_or_else(|| Error::Internal(format!("can not find export function: {}", func)))?;
+        self.call_export(invoke, args)
+    }
 
+    /// Call the export function at `index` like `call`.
+    ///
+    /// The exports are indexed in their declaration order in the export
+    /// section of the module, the order `Module::exports` iterates them in.
+    pub fn call_export_by_index<A: WasmArgs>(
+        &mut self,
+        index: usize,
+        args: A,
+    ) -> Result<Option<i64>, Error> {
+        let invoke = self.handle.lookup_by_index(index).ok_or_else(|| {
+            Error::Internal(format!("can not find export function at index: {}", index))
+        })?;
+        self.call_export(invoke, args)
+    }
+
+    /// Call the defined function `index` like `call`, the index of an export
+    /// is resolved once with `Module::defined_export`.
+    pub fn call_defined<A: WasmArgs>(
+        &mut self,
+        index: DefinedFuncIndex,
+        args: A,
+    ) -> Result<Option<i64>, Error> {
+        if index.index() >= self.module.func_ranges.len() {
+            return Err(Error::Internal(format!(
+                "can not find defined function: {}",
+                index.index()
+            )));
+        }
+        let func = self.module.info.func_index(index);
+        let invoke = self.handle.lookup_by_declaration(func);
+        self.call_export(invoke, args)
+    }
+
+    fn call_export<A: WasmArgs>(
+        &mut self,
+        invoke: ExportFunc,
+        args: A,
+    ) -> Result<Option<i64>, Error> {
+        let args_vec = args.encode(&normal_params(&invoke.signature))?;
+        if let Some(ret) = invoke
+            .signature
+            .returns
+            .iter()
+            .find(|ret| ret.value_type.is_vector())
+        {
+            return Err(Error::Internal(format!(
+                "unsupported result type of export function: {}, see call_typed",
+                ret.value_type
+            )));
+        }
+        let trampoline = self.export_trampoline(&invoke)?;
+        let slots = self.call_trampoline(&invoke, &trampoline, args_vec)?;
+
+        if invoke.signature.returns.is_empty() {
+            return Ok(None);
+        }
+        let ty = invoke.signature.returns[0].value_type;
+        Ok(Some(scalar_result(slots[0], ty)))
+    }
+
+    /// Call an export function like `call`, decoding its results as `R`,
+    /// which may be or include a `v128`, see `WasmResult` for its byte order.
+    ///
+    /// The result types are checked against the signature of the function,
+    /// a mismatch is reported as `Error::SignatureMismatch`. The pinned
+    /// cranelift-wasm translates at most one result per function.
+    pub fn call_typed<A: WasmArgs, R: WasmResults>(
+        &mut self,
+        func: &str,
+        args: A,
+    ) -> Result<R, Error> {
+        let invoke = self
+            .handle
+            .lookup(func)
+            .ok_or_else(|| Error::Internal(format!("can not find export function: {}", func)))?;
+        let args_vec = args.encode(&normal_params(&invoke.signature))?;
+        check_results::<R>(&invoke.signature)?;
+        let trampoline = self.export_trampoline(&invoke)?;
+        let slots = self.call_trampoline(&invoke, &trampoline, args_vec)?;
+        Ok(R::from_slots(&slots))
+    }
+
+    /// Execute an export function like `execute`, decoding its results like
+    /// `call_typed`.
+    pub fn execute_typed<A: WasmArgs, R: WasmResults>(
+        &mut self,
+        chain: ChainCtx,
+        func: &str,
+        args: A,
+    ) -> Result<R, Error> {
         self.set_host_state(Box::new(chain));
+        self.call_typed(func, args)
+    }
 
-        let isa_builder = isa::lookup_by_name("x86_64").unwrap();
-        let mut flag_builder = settings::builder();
-        let _ = flag_builder.set("probestack_enabled", "false");
-        let isa = isa_builder.finish(settings::Flags::new(flag_builder));
+    /// Compile the entry trampoline of an export function, which passes the
+    /// arguments and the results through an array of slots.
+    fn export_trampoline(&self, invoke: &ExportFunc) -> Result<Trampoline, Error> {
+        let returns_vector = invoke
+            .signature
+            .returns
+            .iter()
+            .any(|ret| ret.value_type.is_vector());
+        let slot_words = if returns_vector { 2 } else { 1 };
+        let isa = build_isa(&self.module.tunables);
         let func = make_trampoline(
             &*isa,
             invoke.address,
             &invoke.signature,
-            mem::size_of::<u64>(),
+            slot_words * mem::size_of::<u64>(),
         )
         .map_err(Error::Internal)?;
 
         let mut trampoline = MutableBuffer::new(func.len()).unwrap();
         trampoline.set_len(func.len());
         trampoline.copy_from_slice(&func);
-        let tranpoline = trampoline.make_exec().unwrap();
-
-        let address = &tranpoline[0] as *const u8 as *const VMFunctionBody;
-        let mut args_vec = args;
-        args_vec.push(0); // place holder for return value
-        if let Err(err) = unsafe {
-            wasmjit_call_trampoline(invoke.vmctx, address, args_vec.as_mut_ptr() as *mut u8)
-        } {
+        Ok(Trampoline {
+            code: trampoline.make_exec().unwrap(),
+            slot_words,
+        })
+    }
+
+    /// Call an export function through its entry `trampoline` with the
+    /// already checked argument slots, and return its raw result slots, see
+    /// `WasmResult`.
+    fn call_trampoline(
+        &mut self,
+        invoke: &ExportFunc,
+        trampoline: &Trampoline,
+        args_vec: Vec<i64>,
+    ) -> Result<Vec<u128>, Error> {
+        let linked = self.linked.clone();
+        let exec_metrics = self.handle.instance().exec_metrics.clone();
+        let mut linked_call = LinkedCall::enter(&linked, &exec_metrics)?;
+        self.host_state().reset_call_state();
+
+        let words = trampoline.slot_words;
+        let returns = invoke.signature.returns.len();
+        // the results are stored over the arguments, at least one slot is
+        // kept as the place holder of the return value
+        let slots = cmp::max(args_vec.len() + 1, returns);
+        let mut values = vec![0u64; slots * words];
+        for (i, arg) in args_vec.iter().enumerate() {
+            values[i * words] = *arg as u64;
+        }
+
+        let address = &trampoline.code[0] as *const u8 as *const VMFunctionBody;
+        let values_vec = values.as_mut_ptr() as *mut u8;
+        let vmctx = invoke.vmctx;
+        let depth_left = exec_metrics.depth_left.load(Ordering::Relaxed);
+        let result = on_native_stack(self.module.tunables.native_stack_bytes, || unsafe {
+            wasmjit_call_trampoline(vmctx, address, values_vec)
+        })?;
+        if let Err(err) = result {
+            // the unwound frames never gave their call depth back
+            exec_metrics.depth_left.store(depth_left, Ordering::Relaxed);
             if !self.host_state().is_from_return() {
-                let trap_kind = self.handle.trap_kind();
+                let trap_kind = linked_call.trap_kind(self.handle.trap_kind());
                 if trap_kind == wasmjit_result_err_trap {
-                    return Err(Error::Trap(err));
+                    self.charge_trap(&err, &mut linked_call);
+                    return Err(self.trap_error(err, &linked_call));
                 } else {
-                    return Err(Error::Internal(err));
+                    return Err(Error::Internal(err.message));
                 }
             }
         }
+        Ok((0..returns)
+            .map(|i| {
+                let low = values[i * words];
+                let high = if words == 2 { values[i * words + 1] } else { 0 };
+                u128::from(low) | u128::from(high) << 64
+            })
+            .collect())
+    }
 
-        if invoke.signature.returns.is_empty() {
-            return Ok(None);
+    /// Resolve the export function `func` once into a `TypedFunc`, whose
+    /// calls skip the export lookup, the signature check and the trampoline
+    /// compilation.
+    ///
+    /// The parameter and result types `A` and `R` are checked against the
+    /// signature of the function here, a mismatch is reported as
+    /// `Error::SignatureMismatch`.
+    pub fn typed_func<A: WasmParams, R: WasmResults>(
+        &mut self,
+        func: &str,
+    ) -> Result<TypedFunc<A, R>, Error> {
+        let invoke = self
+            .handle
+            .lookup(func)
+            .ok_or_else(|| Error::Internal(format!("can not find export function: {}", func)))?;
+        let params = normal_params(&invoke.signature);
+        if params != A::types() {
+            return Err(Error::signature_mismatch(&params, &A::types()));
         }
-        if invoke.signature.returns[0].value_type == ir::types::I32 {
-            return Ok(Some(args_vec[0] as i32 as i64));
+        check_results::<R>(&invoke.signature)?;
+        let trampoline = self.export_trampoline(&invoke)?;
+        Ok(TypedFunc {
+            instance: self,
+            invoke,
+            trampoline,
+            marker: PhantomData,
+        })
+    }
+
+    /// Call an export function like `call`, trapping with `TrapCode::Timeout`
+    /// once `timeout` has elapsed.
+    ///
+    /// The timeout is checked along with the gas, at loop back-edges and
+    /// calls, so it only applies to modules compiled with gas metering. A
+    /// module compiled with `Tunables::deterministic` never consults the
+    /// wall clock, its execution is bounded by its gas only.
+    pub fn call_with_timeout<A: WasmArgs>(
+        &mut self,
+        func: &str,
+        args: A,
+        timeout: Duration,
+    ) -> Result<Option<i64>, Error> {
+        if self.module.tunables.deterministic {
+            return self.call(func, args);
+        }
+
+        let exec_metrics = self.host_state().get_exec_metrics();
+        exec_metrics.timed_out.store(false, Ordering::Relaxed);
+        let deadline = Watchdog::arm(Instant::now() + timeout, exec_metrics.clone())?;
+
+        let result = self.call(func, args);
+        Watchdog::disarm(deadline);
+        exec_metrics.timed_out.store(false, Ordering::Relaxed);
+
+        result
+    }
+
+    /// Call an export function like `call`, trapping with `TrapCode::Cancelled`
+    /// once `cancel` is set, possibly by another thread during the execution.
+    ///
+    /// Like the timeout, the flag is only checked with the gas, so it needs a
+    /// module compiled with gas metering. It is ignored by a module compiled
+    /// with `Tunables::deterministic`, whose result must not depend on when
+    /// the flag was set.
+    pub fn call_with_cancel<A: WasmArgs>(
+        &mut self,
+        func: &str,
+        args: A,
+        cancel: Arc<AtomicBool>,
+    ) -> Result<Option<i64>, Error> {
+        if self.module.tunables.deterministic {
+            return self.call(func, args);
         }
-        Ok(Some(args_vec[0] as i64))
+
+        self.handle.set_cancel_flag(Some(cancel));
+        let result = self.call(func, args);
+        self.handle.set_cancel_flag(None);
+
+        result
     }
 
     pub fn invoke(&mut self, cctx: Box<ChainCtx>) -> Result<(), Error> {
@@ -100,42 +506,115 @@ impl Instance {
             .handle
             .lookup("invoke")
             .ok_or_else(|| Error::Internal("can not find export function: invoke".to_string()))?;
-        let param = invoke.signature.params.len();
-        let ret = invoke.signature.returns.len();
-        if param != 1
-            || ret != 0
-            || invoke.signature.params[0].purpose != ir::ArgumentPurpose::VMContext
-        {
+        let params = normal_params(&invoke.signature);
+        if !params.is_empty() {
+            return Err(Error::signature_mismatch(&[], &params));
+        }
+        if !invoke.signature.returns.is_empty() {
             return Err(Error::Internal(
                 "invalid invoke function signature".to_string(),
             ));
         }
 
         self.set_host_state(cctx);
-        let result = unsafe { wasmjit_call(invoke.vmctx, invoke.address) };
+        let linked = self.linked.clone();
+        let exec_metrics = self.handle.instance().exec_metrics.clone();
+        let mut linked_call = LinkedCall::enter(&linked, &exec_metrics)?;
+        let result = on_native_stack(self.module.tunables.native_stack_bytes, || unsafe {
+            wasmjit_call(invoke.vmctx, invoke.address)
+        })?;
 
-        let trap_kind = self.handle.trap_kind();
+        let trap_kind = linked_call.trap_kind(self.handle.trap_kind());
         let normal_return = self.host_state().is_from_return();
 
         match result {
             Ok(_) => Ok(()),
             Err(_) if normal_return => Ok(()),
-            Err(message) => {
+            Err(trap) => {
                 if trap_kind == wasmjit_result_err_trap {
-                    Err(Error::Trap(message))
+                    self.charge_trap(&trap, &mut linked_call);
+                    Err(self.trap_error(trap, &linked_call))
                 } else {
-                    Err(Error::Internal(message))
+                    Err(Error::Internal(trap.message))
                 }
             }
         }
     }
 
+    /// Charge the gas of the operators run in the scope of a trapping
+    /// operator up to it, which the trap left before its scope was charged.
+    fn charge_trap(&mut self, trap: &Trap, linked_call: &mut LinkedCall) {
+        let module_offset = match trap.module_offset {
+            Some(module_offset) => module_offset,
+            None => return,
+        };
+        let instance = match linked_call.instance_at_mut(trap.pc) {
+            Some(instance) => instance,
+            None => self,
+        };
+        if let Some(gas) = instance.module.info.trap_pending_gas(module_offset) {
+            // gas the trap does not cover leaves none, the trap is unchanged
+            instance.handle.instance_mut().charge_gas(u64::from(gas));
+        }
+    }
+
+    /// Symbolize a trap of the generated code. For an out of bounds memory
+    /// access, also record its size and, when a guard page caught it, the
+    /// faulting offset in linear memory. This only enriches the reported
+    /// error, the trap itself and the gas charged are unchanged.
+    ///
+    /// A trap in the code of a linked instance is symbolized against the
+    /// module and the memory of that instance.
+    fn trap_error(&self, trap: Trap, linked_call: &LinkedCall) -> Error {
+        let instance = linked_call.instance_at(trap.pc).unwrap_or(self);
+        let mut trap = instance.module.symbolize(trap);
+        if trap.code == TrapCode::MemoryOutOfBounds {
+            let size = trap
+                .module_offset
+                .and_then(|offset| instance.module.info.memory_access_size(offset));
+            if let Some(size) = size {
+                let offset = trap
+                    .fault_address
+                    .and_then(|address| instance.handle.memory_offset_of(address));
+                trap.memory_access = Some(MemoryAccess { offset, size });
+            }
+        }
+        Error::Trap(trap)
+    }
+
     pub fn set_host_state(&mut self, host_state: Box<ChainCtx>) {
         let instance = self.handle.instance_mut();
         instance.exec_metrics = host_state.exec_metrics.clone();
         self.handle.set_host_state(host_state);
     }
 
+    /// Set the gas available to the next calls, shared with the `ChainCtx` of
+    /// the host state. Only modules compiled with `BuildOption::gas_metering`
+    /// consume it.
+    pub fn set_gas(&mut self, gas: u64) {
+        self.handle.instance_mut().set_gas(gas);
+    }
+
+    /// The gas left after the calls made since `set_gas`, so the consumed gas
+    /// is the initial amount minus this one.
+    ///
+    /// The operators are charged when their scope is left: at the branches,
+    /// calls, returns, block ends and `unreachable`. The cost of an imported
+    /// function is charged before its calls only by a module compiled with a
+    /// `HostCallCharge`, see `Resolver::resolve_cost`.
+    ///
+    /// After a trap the remaining gas reflects the operators executed up to
+    /// the trap, the trapping one included, the same amount on every run: the
+    /// operators of the trapping scope are charged by the runtime from the
+    /// location of the trap. An exhausted call leaves 0.
+    pub fn gas_remaining(&self) -> u64 {
+        self.handle
+            .instance()
+            .exec_metrics
+            .gas_left
+            .load(Ordering::Relaxed)
+    }
+
     pub fn host_state(&mut self) -> &mut ChainCtx {
         self.handle.host_state().downcast_mut::<ChainCtx>().unwrap()
     }
@@ -143,6 +622,271 @@ impl Instance {
     pub fn trap_kind(&mut self) -> wasmjit_result_kind {
         self.handle.trap_kind()
     }
+
+    /// Run `f` with `ctx` as the host context, the host functions it reaches
+    /// get it back with `Instance::host_context` on their vmctx instance.
+    ///
+    /// The context is borrowed for the whole scope and taken back when `f`
+    /// returns or unwinds, so host functions never see a dangling context.
+    pub fn with_host_context<C: Any, R>(
+        &mut self,
+        ctx: &mut C,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> R {
+        struct Restore(*mut InstanceHandle, Option<*mut dyn Any>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                unsafe { (*self.0).set_host_context(self.1) };
+            }
+        }
+
+        let ctx = ctx as &mut dyn Any as *mut dyn Any;
+        let previous = unsafe { self.handle.set_host_context(Some(ctx)) };
+        let _restore = Restore(&mut self.handle, previous);
+        f(self)
+    }
+
+    /// Call an export function like `call`, with `ctx` as the host context.
+    pub fn call_with_context<C: Any, A: WasmArgs>(
+        &mut self,
+        ctx: &mut C,
+        func: &str,
+        args: A,
+    ) -> Result<Option<i64>, Error> {
+        self.with_host_context(ctx, |instance| instance.call(func, args))
+    }
+
+    /// Trap with `TrapCode::Reentrancy` when a host function calls back into
+    /// this instance while it is executing. Off by default, for the embedders
+    /// which rely on reentrancy.
+    pub fn set_reentrancy_guard(&mut self, enable: bool) {
+        self.handle.set_reentrancy_guard(enable);
+    }
+
+    /// Set the hook observing the calls of imported functions, `None` removes
+    /// it. Only modules compiled with `BuildOption::trace_host_calls` report
+    /// their calls, other modules never consult the hook.
+    pub fn set_host_call_hook(&mut self, hook: Option<HostCallHook>) {
+        self.handle.set_host_call_hook(hook);
+    }
+
+    /// Remove the settings made on this instance: the host call hook, the
+    /// reentrancy guard, the cancel flag and the host context. The costs
+    /// counted below the gas factor are dropped as well, as by `set_gas`.
+    pub(crate) fn clear_settings(&mut self) {
+        self.handle.instance_mut().clear_gas_counter();
+        self.handle.set_host_call_hook(None);
+        self.handle.set_reentrancy_guard(false);
+        self.handle.set_cancel_flag(None);
+        unsafe { self.handle.set_host_context(None) };
+    }
+
+    /// Returns the number of elements of the table `index`, `None` if the
+    /// module does not define it.
+    pub fn table_size(&self, index: u32) -> Option<u32> {
+        self.handle.table_size(DefinedTableIndex::from_u32(index))
+    }
+
+    /// Grow the table `index` by `delta` null elements and return its previous
+    /// size, like `table.grow` does, `None` if it is not defined or would
+    /// exceed its maximum. Indirect calls through the new elements trap until
+    /// they are set.
+    ///
+    /// The `table.grow` and `table.size` operators are not implemented, the
+    /// pinned cranelift-wasm has no hook to translate them, so tables can only
+    /// grow from the host.
+    pub fn table_grow(&mut self, index: u32, delta: u32) -> Option<u32> {
+        let index = DefinedTableIndex::from_u32(index);
+        let size = self.handle.table_size(index)?;
+        self.handle.table_grow(index, delta)?;
+        Some(size)
+    }
+
+    /// The current size in wasm pages of the default memory, as grown so far,
+    /// `None` if the module has no memory.
+    pub fn memory_size_pages(&self) -> Option<u32> {
+        self.handle
+            .instance()
+            .memory_pages(DefinedMemoryIndex::new(0))
+    }
+
+    /// The number of wasm pages the default memory can grow to: the maximum
+    /// declared by the module, capped by `Tunables::max_memory_pages`. `None`
+    /// if the module has no memory.
+    pub fn memory_max_pages(&self) -> Option<u32> {
+        self.handle
+            .instance()
+            .memory_max_pages(DefinedMemoryIndex::new(0))
+    }
+
+    /// Return the range `[offset, offset + len)` of the default memory, against
+    /// its current length. A module without memory has an empty one.
+    fn memory_range(&self, offset: usize, len: usize) -> Result<Range<usize>, TrapCode> {
+        let memory_len = self
+            .handle
+            .instance()
+            .memory_slice(DefinedMemoryIndex::new(0))
+            .map_or(0, <[u8]>::len);
+        match offset.checked_add(len) {
+            Some(end) if end <= memory_len => Ok(offset..end),
+            _ => Err(TrapCode::MemoryOutOfBounds),
+        }
+    }
+
+    /// Copy `len` bytes at `offset` out of the default memory.
+    pub fn read_memory(&self, offset: usize, len: usize) -> Result<Vec<u8>, TrapCode> {
+        let range = self.memory_range(offset, len)?;
+        match self
+            .handle
+            .instance()
+            .memory_slice(DefinedMemoryIndex::new(0))
+        {
+            Some(memory) => Ok(memory[range].to_vec()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Copy `data` into the default memory at `offset`. Nothing is written if
+    /// the range does not fit in the memory.
+    pub fn write_memory(&mut self, offset: usize, data: &[u8]) -> Result<(), TrapCode> {
+        let range = self.memory_range(offset, data.len())?;
+        let memory = self
+            .handle
+            .instance_mut()
+            .memory_slice_mut(DefinedMemoryIndex::new(0));
+        if let Some(memory) = memory {
+            memory[range].copy_from_slice(data);
+        }
+        Ok(())
+    }
+
+    /// Write `image` at the start of the default memory, growing it as needed.
+    fn load_memory_image(&mut self, image: &[u8]) -> Result<(), Error> {
+        let memory_index = DefinedMemoryIndex::new(0);
+        let memory_len = match self.handle.instance().memory_slice(memory_index) {
+            Some(memory) => memory.len(),
+            None if image.is_empty() => return Ok(()),
+            None => {
+                let msg = "memory image for a module without memory";
+                return Err(Error::Internal(msg.to_string()));
+            }
+        };
+        if image.len() > memory_len {
+            let page_size = WASM_PAGE_SIZE as usize;
+            let delta = (image.len() - memory_len + page_size - 1) / page_size;
+            let grown = u32::try_from(delta)
+                .ok()
+                .and_then(|delta| self.handle.memory_grow(memory_index, delta));
+            if grown.is_none() {
+                return Err(Error::Internal(format!(
+                    "memory image of {} bytes exceeds the memory limit",
+                    image.len()
+                )));
+            }
+        }
+        self.write_memory(0, image)
+            .map_err(|code| Error::Internal(format!("failed to write memory image: {}", code)))
+    }
+
+    /// Lookup the export function `field`, for linking it into another
+    /// instance.
+    pub(crate) fn lookup(&mut self, field: &str) -> Option<ExportFunc> {
+        self.handle.lookup(field)
+    }
+
+    fn global_index(&self, global: GlobalRef) -> Result<GlobalIndex, Error> {
+        let info = &self.module.info;
+        let index = match global {
+            GlobalRef::Index(index) => GlobalIndex::from_u32(index),
+            GlobalRef::Name(name) => *info
+                .global_exports
+                .get(name)
+                .ok_or_else(|| Error::Internal(format!("no global exported as {}", name)))?,
+        };
+        if index.index() >= info.globals.len() {
+            return Err(Error::Internal(format!("no global {}", index.index())));
+        }
+        Ok(index)
+    }
+
+    /// Read a global, exported or not, so the state of the instance can be
+    /// inspected between calls.
+    pub fn get_global<'a>(&self, global: impl Into<GlobalRef<'a>>) -> Result<Value, Error> {
+        let index = self.global_index(global.into())?;
+        let ty = self.module.info.globals[index].ty;
+        let def = self.handle.global_definition(index).unwrap();
+        let value = unsafe {
+            match ty {
+                ir::types::I32 => Value::I32(*def.as_i32()),
+                ir::types::I64 => Value::I64(*def.as_i64()),
+                ir::types::F32 => Value::F32(*def.as_f32()),
+                ir::types::F64 => Value::F64(*def.as_f64()),
+                _ => return Err(Error::Internal(format!("unsupported global type {}", ty))),
+            }
+        };
+        Ok(value)
+    }
+
+    /// Write a global, exported or not, to inject state before a call. The
+    /// value must have the declared type of the global, and immutable globals
+    /// can not be written.
+    pub fn set_global<'a>(
+        &mut self,
+        global: impl Into<GlobalRef<'a>>,
+        value: Value,
+    ) -> Result<(), Error> {
+        let index = self.global_index(global.into())?;
+        let decl = &self.module.info.globals[index];
+        if !decl.mutability {
+            return Err(Error::Internal(format!(
+                "global {} is immutable",
+                index.index()
+            )));
+        }
+        if decl.ty != value.ty() {
+            return Err(Error::signature_mismatch(&[decl.ty], &[value.ty()]));
+        }
+        let def = self.handle.global_definition_mut(index).unwrap();
+        unsafe {
+            match value {
+                Value::I32(val) => *def.as_i32_mut() = val,
+                Value::I64(val) => *def.as_i64_mut() = val,
+                Value::F32(val) => *def.as_f32_mut() = val,
+                Value::F64(val) => *def.as_f64_mut() = val,
+            }
+        }
+        Ok(())
+    }
+
+    /// The instances linked to this one, see `Linker`.
+    pub(crate) fn linked(&self) -> &[Arc<Mutex<Instance>>] {
+        &self.linked
+    }
+
+    /// The module this instance was instantiated from.
+    pub fn module(&self) -> &Arc<Module> {
+        &self.module
+    }
+
+    /// Warm the machine code of the module, see `Module::warm`.
+    pub fn warm(&self, lock: bool) -> Result<(), Error> {
+        self.module.warm(lock)
+    }
+
+    /// Take a snapshot of the linear memory, table sizes and mutable globals,
+    /// to roll the instance back later with `restore`.
+    pub fn snapshot(&mut self) -> InstanceSnapshot {
+        self.handle.instance_mut().snapshot()
+    }
+
+    /// Roll back the linear memory (including its size), the table sizes and
+    /// the mutable globals to a snapshot taken on an instance of its module.
+    pub fn restore(&mut self, snapshot: &InstanceSnapshot) -> Result<(), Error> {
+        self.handle
+            .instance_mut()
+            .restore(snapshot)
+            .map_err(Error::Internal)
+    }
 }
 
 pub fn build_module(wasm: &[u8], build_option: BuildOption) -> Result<Arc<Module>, Error> {
@@ -163,100 +907,468 @@ pub fn build_module(wasm: &[u8], build_option: BuildOption) -> Result<Arc<Module
     }
 }
 
+/// Translate `wasm` without compiling it, checking the module structure and
+/// the resource limits of `tunables`.
+///
+/// The function bodies are located but their code is only validated by the
+/// compilation, so this is the cheap entry point to fuzz the parser.
+pub fn parse_only(wasm: &[u8], tunables: Tunables) -> WasmResult<ModuleTranslation> {
+    translate_module(wasm, tunables, None)
+}
+
+/// Translate `wasm` like `parse_only`, but report every violation of the
+/// tunables in the module structure and resource limits instead of the first
+/// one, see `ModuleEnvironment::translate_collecting`.
+pub fn parse_collecting(
+    wasm: &[u8],
+    tunables: Tunables,
+) -> Result<ModuleTranslation, Vec<WasmError>> {
+    ModuleEnvironment::new(target_config(), tunables).translate_collecting(wasm)
+}
+
+fn translate_module(
+    wasm: &[u8],
+    tunables: Tunables,
+    signature_registry: Option<&Arc<SignatureRegistry>>,
+) -> WasmResult<ModuleTranslation> {
+    let mut environ = ModuleEnvironment::new(target_config(), tunables);
+    if let Some(registry) = signature_registry {
+        environ = environ.with_signature_registry(registry.clone());
+    }
+    environ.translate(wasm)
+}
+
+/// Check whether `wasm` is acceptable under `tunables` with the proposals
+/// `features`, which replace `tunables.features`, without compiling it.
+///
+/// Every violation found is reported: those of the module structure and
+/// resource limits, see `parse_collecting`, or if there are none the first
+/// one of each function body, whose translation to cranelift IR checks the
+/// operators the same way the compilation does. The translation is
+/// discarded, so this is cheaper than compiling but still linear in the size
+/// of the module.
+pub fn validate(
+    wasm: &[u8],
+    mut tunables: Tunables,
+    features: Features,
+) -> Result<(), Vec<WasmError>> {
+    tunables.features = features;
+    let translation = parse_collecting(wasm, tunables)?;
+    let errors: Vec<_> = translation
+        .function_body_inputs
+        .iter()
+        .filter_map(|(index, input)| {
+            translate_function(
+                &translation.module,
+                &translation.translate_state,
+                index,
+                input,
+                translation.target_config,
+                BuildOption::new().gas_metering(true),
+                &translation.tunables,
+            )
+            .err()
+        })
+        .map(|error| match error {
+            CompileError::Wasm(error) => error,
+            CompileError::Function {
+                name,
+                error: WasmError::Unsupported(message),
+            } => WasmError::Unsupported(format!("{} in function {}", message, name)),
+            CompileError::Function { error, .. } => error,
+            error => WasmError::Unsupported(error.to_string()),
+        })
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Translate only the defined function `index` of `wasm` and return its
+/// cranelift IR in textual form, for debugging miscompiles.
+///
+/// The other functions are neither translated nor compiled, so the IR is
+/// available even if the module fails to compile. Only built with the
+/// `dump-ir` feature.
+#[cfg(feature = "dump-ir")]
+pub fn dump_function_ir(
+    wasm: &[u8],
+    build_option: BuildOption,
+    tunables: Tunables,
+    index: DefinedFuncIndex,
+) -> Result<String, Error> {
+    let translation =
+        parse_only(wasm, tunables).map_err(|e| Error::Compile(CompileError::Wasm(e)))?;
+    let input = translation
+        .function_body_inputs
+        .get(index)
+        .ok_or_else(|| Error::Internal(format!("no defined function {}", index.index())))?;
+    let func = translate_function(
+        &translation.module,
+        &translation.translate_state,
+        index,
+        input,
+        translation.target_config,
+        build_option,
+        &translation.tunables,
+    )
+    .map_err(Error::Compile)?;
+    Ok(func.to_string())
+}
+
 /// Compiled module for instantiate
 #[allow(unused)]
 pub struct Module {
     info: Arc<ModuleInfo>,
+    tunables: Tunables,
     data_initializers: Vec<OwnedDataInitializer>,
-    func_offsets: PrimaryMap<DefinedFuncIndex, usize>,
+    /// Range of the code of each function in `executable`.
+    func_ranges: PrimaryMap<DefinedFuncIndex, Range<usize>>,
     jt_offsets: PrimaryMap<DefinedFuncIndex, ir::JumpTableOffsets>,
     executable: ExecutableBuffer,
     relocs: Relocations,
     traps: Traps,
+    /// How the code charges the gas costs of the host calls.
+    host_call_charge: HostCallCharge,
     trap_registration_guards: Vec<TrapRegistrationGuard>,
 }
 
 impl Module {
     pub fn instantiate(self: Arc<Self>, resolver: &mut dyn Resolver) -> Result<Instance, Error> {
-        let module_info = self.info.clone();
-        let imports =
-            {
-                let mut imports = PrimaryMap::new();
-                for (module, func) in module_info.imported_funcs.values() {
-                    imports.push(resolver.resolve(module, func).unwrap_or_else(|| {
-                        panic!("can not resolve import func:{}/{}", module, func)
-                    }));
-                }
+        let (imports, costs) = self.resolve_imports(resolver)?;
+        self.instantiate_with_imports(imports, costs, Vec::new(), None)
+    }
 
-                imports.into_boxed_slice()
+    /// Instantiate the module like `instantiate`, taking the pages of its
+    /// memories from `budget`, see `MemoryBudget`. An initial memory which
+    /// does not fit the budget fails the instantiation, a `memory.grow`
+    /// beyond it returns -1.
+    pub fn instantiate_with_budget(
+        self: Arc<Self>,
+        resolver: &mut dyn Resolver,
+        budget: Arc<MemoryBudget>,
+    ) -> Result<Instance, Error> {
+        let (imports, costs) = self.resolve_imports(resolver)?;
+        self.instantiate_with_imports(imports, costs, Vec::new(), Some(budget))
+    }
+
+    /// Resolve the imports with `resolver`, an import it does not resolve is
+    /// an `Error::Link` naming it, like a missing import of a `Linker`.
+    fn resolve_imports(
+        &self,
+        resolver: &mut dyn Resolver,
+    ) -> Result<
+        (
+            BoxedSlice<FuncIndex, VMFunctionImport>,
+            BoxedSlice<FuncIndex, Option<HostCallCost>>,
+        ),
+        Error,
+    > {
+        let mut imports = PrimaryMap::new();
+        let mut costs = PrimaryMap::new();
+        for (module, func) in self.info.imported_funcs.values() {
+            let import = resolver
+                .resolve(module, func)
+                .ok_or_else(|| Error::Link(format!("unknown import {}.{}", module, func)))?;
+            imports.push(import);
+            costs.push(resolver.resolve_cost(module, func));
+        }
+
+        Ok((imports.into_boxed_slice(), costs.into_boxed_slice()))
+    }
+
+    /// Instantiate the module with resolved `imports` and the gas `costs` of
+    /// their calls, keeping alive the `linked` instances they point into.
+    ///
+    /// A cost the code can not charge is an `Error::Link`: any cost if the
+    /// module was compiled without a `HostCallCharge`, a cost computed from
+    /// the arguments if it was compiled to charge fixed costs.
+    pub(crate) fn instantiate_with_imports(
+        self: Arc<Self>,
+        imports: BoxedSlice<FuncIndex, VMFunctionImport>,
+        costs: BoxedSlice<FuncIndex, Option<HostCallCost>>,
+        linked: Vec<Arc<Mutex<Instance>>>,
+        memory_budget: Option<Arc<MemoryBudget>>,
+    ) -> Result<Instance, Error> {
+        for (index, cost) in costs.iter() {
+            let charged = match (cost, self.host_call_charge) {
+                (None, _) => true,
+                (Some(_), HostCallCharge::None) => false,
+                (Some(HostCallCost::Arguments(_)), HostCallCharge::Fixed) => false,
+                (Some(_), _) => true,
             };
+            if !charged {
+                let (module, field) = &self.info.imported_funcs[index];
+                return Err(Error::Link(format!(
+                    "the cost of import {}.{} can not be charged, the module is compiled with {:?}",
+                    module, field, self.host_call_charge
+                )));
+            }
+        }
 
         let data_initializers: Vec<_> = self.data_initializers.iter().map(|e| e.into()).collect();
         let functions: PrimaryMap<_, _> = self
-            .func_offsets
-            .iter()
-            .map(|(_index, offset)| &self.executable[*offset] as *const u8 as *const VMFunctionBody)
+            .func_ranges
+            .values()
+            .map(|range| &self.executable[range.start] as *const u8 as *const VMFunctionBody)
             .collect();
 
         let chain = ChainCtx::default();
-        let instance = InstanceHandle::new(
+        let mut instance = InstanceHandle::new(
             self.info.clone(),
             functions.into_boxed_slice(),
             imports,
             &data_initializers,
             chain.exec_metrics.clone(),
             Box::new(chain),
+            memory_budget,
         )?;
+        instance.instance_mut().set_host_call_costs(costs);
 
         Ok(Instance {
             module: self.clone(),
             handle: instance,
+            linked,
         })
     }
 
+    /// Instantiate the module like `instantiate`, then write `image` at the
+    /// start of the default memory. The image is written after the data
+    /// segments so it overrides the bytes they overlap, and the memory grows
+    /// to fit it up to its maximum.
+    pub fn instantiate_with_memory(
+        self: Arc<Self>,
+        resolver: &mut dyn Resolver,
+        image: &[u8],
+    ) -> Result<Instance, Error> {
+        let mut instance = self.instantiate(resolver)?;
+        instance.load_memory_image(image)?;
+        Ok(instance)
+    }
+
+    /// Fault in the pages of the machine code, and lock them in memory if
+    /// `lock`, so that the first call does not pay for paging the code in.
+    /// Nothing is done until called, and it can be called again safely.
+    pub fn warm(&self, lock: bool) -> Result<(), Error> {
+        prefault(&self.executable, lock)
+            .map_err(|e| Error::Internal(format!("failed to warm the code: {}", e)))
+    }
+
+    /// Iterate over the exports of the module as `(name, kind, type)`, so the
+    /// arguments of a function can be checked before calling it.
+    pub fn exports(&self) -> impl Iterator<Item = (&str, ExportKind, ExportType)> {
+        self.info.export_types()
+    }
+
+    /// Iterate over the imported functions of the module as `(module, field,
+    /// signature)`, so the embedder can check it resolves them all before
+    /// instantiating.
+    pub fn imports(&self) -> impl Iterator<Item = (&str, &str, &ir::Signature)> {
+        self.info.import_types()
+    }
+
+    /// The defined function exported as `name`, so it can be called with
+    /// `Instance::call_defined` without looking the name up again. An export
+    /// of an imported function is an error.
+    pub fn defined_export(&self, name: &str) -> Result<DefinedFuncIndex, Error> {
+        self.info
+            .defined_export(name)
+            .map_err(|e| Error::Internal(e.to_string()))
+    }
+
+    pub(crate) fn info(&self) -> &ModuleInfo {
+        &self.info
+    }
+
+    /// Total size in bytes of the data initializers, as declared, see
+    /// `ModuleTranslation::data_bytes`.
+    pub fn data_bytes(&self) -> u64 {
+        self.data_initializers
+            .iter()
+            .map(|init| init.data.len() as u64)
+            .sum()
+    }
+
     pub fn compile(wasm: &[u8], build_option: BuildOption) -> Result<Module, Error> {
-        let config = isa::TargetFrontendConfig {
-            default_call_conv: isa::CallConv::SystemV,
-            pointer_width: PointerWidth::U64,
-        };
+        Self::compile_with_tunables(wasm, build_option, Tunables::default())
+    }
 
-        let isa_builder = isa::lookup_by_name("x86_64").unwrap();
-        let mut flag_builder = settings::builder();
-        let _ = flag_builder.set("probestack_enabled", "false");
-        let isa = isa_builder.finish(settings::Flags::new(flag_builder));
+    /// Read the whole module from `reader` and compile it, the result is the
+    /// one of `compile_with_tunables` on the same bytes.
+    ///
+    /// This is not a streaming compilation: cranelift-wasm only translates a
+    /// module from a complete slice, so nothing is translated before the end
+    /// of input and the whole module is buffered.
+    pub fn read_and_compile<R: Read>(
+        mut reader: R,
+        build_option: BuildOption,
+        tunables: Tunables,
+    ) -> Result<Module, Error> {
+        let mut wasm = Vec::new();
+        reader
+            .read_to_end(&mut wasm)
+            .map_err(|e| Error::Internal(format!("failed to read module: {}", e)))?;
+        Self::compile_with_tunables(&wasm, build_option, tunables)
+    }
 
-        let module_environ = ModuleEnvironment::new(config, Tunables::default());
-        let result = module_environ
-            .translate(&wasm)
-            .map_err(|e| Error::Compile(CompileError::Wasm(e)))?;
+    pub fn compile_with_tunables(
+        wasm: &[u8],
+        build_option: BuildOption,
+        tunables: Tunables,
+    ) -> Result<Module, Error> {
+        Self::compile_phases(wasm, build_option, tunables, None, None)
+    }
 
-        let (compilation, relocs, _address_transform, _value_ranges, _stack_slots, traps) =
-            compile_module(
-                &result.module,
+    /// Compile the module like `compile_with_tunables`, interning its
+    /// signatures in the shared `registry`.
+    ///
+    /// The instances of modules compiled with the same registry agree on the
+    /// ids of identical signatures, so an indirect call through a table of
+    /// another instance checks the signature of the callee correctly. The
+    /// ids are not serialized, a deserialized module falls back to ids local
+    /// to each instance.
+    pub fn compile_with_registry(
+        wasm: &[u8],
+        build_option: BuildOption,
+        tunables: Tunables,
+        registry: &Arc<SignatureRegistry>,
+    ) -> Result<Module, Error> {
+        Self::compile_phases(wasm, build_option, tunables, Some(registry), None)
+    }
+
+    /// Compile the module like `compile_with_tunables`, recording the
+    /// duration of the `parse` and `codegen` phases.
+    pub fn compile_timed(
+        wasm: &[u8],
+        build_option: BuildOption,
+        tunables: Tunables,
+    ) -> Result<(Module, Timings), Error> {
+        let mut timings = Timings::default();
+        let module = Self::compile_phases(wasm, build_option, tunables, None, Some(&mut timings))?;
+        Ok((module, timings))
+    }
+
+    fn compile_phases(
+        wasm: &[u8],
+        build_option: BuildOption,
+        tunables: Tunables,
+        signature_registry: Option<&Arc<SignatureRegistry>>,
+        mut timings: Option<&mut Timings>,
+    ) -> Result<Module, Error> {
+        let isa = build_isa(&tunables);
+
+        let result = timed(timings.as_mut().map(|t| &mut t.parse), || {
+            translate_module(wasm, tunables, signature_registry)
+                .map_err(|e| Error::Compile(CompileError::Wasm(e)))
+        })?;
+
+        timed(timings.as_mut().map(|t| &mut t.codegen), || {
+            let data_initializers = result
+                .data_initializers
+                .into_iter()
+                .map(|e| e.into_owned())
+                .collect();
+            Self::codegen(
+                &*isa,
+                result.module,
                 &result.translate_state,
                 result.function_body_inputs,
-                &*isa,
+                data_initializers,
+                result.tunables,
+                build_option,
+            )
+        })
+    }
+
+    /// Compile an owned translation, see `OwnedModuleTranslation`. The
+    /// translation is kept, so it can be compiled again without the module
+    /// bytes, each compilation is instantiated as usual.
+    pub fn compile_translation(
+        translation: &OwnedModuleTranslation,
+        build_option: BuildOption,
+    ) -> Result<Module, Error> {
+        let isa = build_isa(&translation.tunables);
+        Self::codegen(
+            &*isa,
+            translation.module.clone(),
+            &translation.translate_state,
+            translation.function_bodies(),
+            translation.data_initializers.clone(),
+            translation.tunables.clone(),
+            build_option,
+        )
+    }
+
+    fn codegen(
+        isa: &dyn isa::TargetIsa,
+        mut info: ModuleInfo,
+        translate_state: &ModuleTranslationState,
+        function_body_inputs: PrimaryMap<DefinedFuncIndex, FunctionBodyData>,
+        data_initializers: Vec<OwnedDataInitializer>,
+        tunables: Tunables,
+        mut build_option: BuildOption,
+    ) -> Result<Module, Error> {
+        let host_call_charge = build_option.get_host_call_charge();
+        let (compilation, relocs, _address_transform, _value_ranges, _stack_slots, traps, sites) =
+            compile_module(
+                &info,
+                translate_state,
+                function_body_inputs,
+                isa,
                 false,
                 build_option,
+                &tunables,
             )
             .map_err(Error::Compile)?;
+        info.trap_sites = sites;
+
+        let jt_offsets = compilation.get_jt_offsets();
+        let bodies: Vec<_> = (&compilation)
+            .into_iter()
+            .map(|code| &code.body[..])
+            .collect();
+
+        Self::link(
+            info,
+            tunables,
+            data_initializers,
+            &bodies,
+            jt_offsets,
+            relocs,
+            traps,
+            host_call_charge,
+        )
+    }
 
-        let total_code_size = (&compilation).into_iter().map(|code| code.body.len()).sum();
+    /// Lay out the code of the functions in executable memory and apply the
+    /// relocations.
+    pub(crate) fn link(
+        info: ModuleInfo,
+        tunables: Tunables,
+        data_initializers: Vec<OwnedDataInitializer>,
+        bodies: &[&[u8]],
+        jt_offsets: PrimaryMap<DefinedFuncIndex, ir::JumpTableOffsets>,
+        relocs: Relocations,
+        traps: Traps,
+        host_call_charge: HostCallCharge,
+    ) -> Result<Module, Error> {
+        let total_code_size = bodies.iter().map(|body| body.len()).sum();
         let mut exec = MutableBuffer::new(total_code_size)
             .map_err(|_| Error::Internal("allocate mmap memory failed".to_string()))?;
         let mut finished_functions = PrimaryMap::new();
-        let mut func_offsets = PrimaryMap::with_capacity(compilation.len());
-        for code in &compilation {
+        let mut func_ranges = PrimaryMap::with_capacity(bodies.len());
+        for body in bodies {
             let curr_size = exec.len();
-            exec.set_len(curr_size + code.body.len());
-            exec[curr_size..].copy_from_slice(&code.body);
-            func_offsets.push(curr_size);
+            exec.set_len(curr_size + body.len());
+            exec[curr_size..].copy_from_slice(body);
+            func_ranges.push(curr_size..exec.len());
             finished_functions.push(&exec[curr_size] as *const u8 as *const VMFunctionBody);
         }
 
-        let jt_offsets = compilation.get_jt_offsets();
-
-        linker::link_module(&result.module, &finished_functions, &jt_offsets, &relocs)?;
+        linker::link_module(&info, &finished_functions, &jt_offsets, &relocs)?;
 
         let mut trap_registration_guards = vec![];
         register_traps(&finished_functions, &traps, &mut trap_registration_guards);
@@ -266,21 +1378,117 @@ impl Module {
             .map_err(|_| Error::Internal("failed to set memory executable".to_string()))?;
 
         Ok(Module {
-            info: Arc::new(result.module),
-            data_initializers: result
-                .data_initializers
-                .into_iter()
-                .map(|e| e.into_owned())
-                .collect(),
-            func_offsets,
+            info: Arc::new(info),
+            tunables,
+            data_initializers,
+            func_ranges,
             executable,
             jt_offsets,
             relocs,
             traps,
+            host_call_charge,
             trap_registration_guards,
         })
     }
 
+    /// Serialize the compiled module, to be loaded later by `deserialize`
+    /// without compiling it again.
+    pub fn serialize(&self) -> Result<Vec<u8>, Error> {
+        let bodies: Vec<_> = self
+            .func_ranges
+            .values()
+            .map(|range| &self.executable[range.clone()])
+            .collect();
+        artifact::serialize(&artifact::ArtifactRef {
+            info: &self.info,
+            tunables: &self.tunables,
+            data_initializers: &self.data_initializers,
+            bodies,
+            jt_offsets: &self.jt_offsets,
+            relocs: &self.relocs,
+            traps: &self.traps,
+            host_call_charge: self.host_call_charge,
+        })
+    }
+
+    /// Load a module serialized by `serialize`.
+    ///
+    /// The artifact holds machine code which is executed as is, so it must
+    /// come from a trusted source, such as a cache written by this node.
+    pub fn deserialize(bytes: &[u8]) -> Result<Module, Error> {
+        let artifact = artifact::deserialize(bytes)?;
+        let bodies: Vec<_> = artifact.bodies.iter().map(|body| &body[..]).collect();
+        Self::link(
+            artifact.info,
+            artifact.tunables,
+            artifact.data_initializers,
+            &bodies,
+            artifact.jt_offsets,
+            artifact.relocs,
+            artifact.traps,
+            artifact.host_call_charge,
+        )
+    }
+
+    /// Whether the machine code of the module holds the address `pc`.
+    fn code_contains(&self, pc: usize) -> bool {
+        let code_start = self.executable.as_ptr() as usize;
+        pc >= code_start && pc < code_start + self.executable.len()
+    }
+
+    /// Fill in the frame of a trap raised by the code of this module: the
+    /// function holding the trapping instruction.
+    fn symbolize(&self, mut trap: Trap) -> Trap {
+        let code_start = self.executable.as_ptr() as usize;
+        let code_offset = match trap.pc {
+            Some(pc) if pc >= code_start => pc - code_start,
+            _ => return trap,
+        };
+        let func_index = self
+            .func_ranges
+            .iter()
+            .find(|(_, range)| range.contains(&code_offset))
+            .map(|(index, _)| index);
+        trap.frame = func_index.map(|func_index| {
+            let func_name = self
+                .info
+                .func_name(self.info.func_index(func_index))
+                .map(ToString::to_string);
+            FrameInfo {
+                func_index,
+                func_name,
+                module_offset: trap.module_offset,
+            }
+        });
+        trap
+    }
+
+    /// Total size in bytes of the machine code of the module.
+    pub fn code_size(&self) -> usize {
+        self.executable.len()
+    }
+
+    /// The `(offset, len)` in bytes of the machine code of each defined
+    /// function, within the `code_size` bytes of the module. The functions
+    /// are laid out back to back in index order.
+    pub fn function_code_ranges(&self) -> PrimaryMap<DefinedFuncIndex, (usize, usize)> {
+        self.func_ranges
+            .values()
+            .map(|range| (range.start, range.len()))
+            .collect()
+    }
+
+    /// Disassemble the machine code of the defined function `index`, as it is
+    /// executed: relocations applied, at its address in executable memory.
+    pub fn disassemble(&self, index: DefinedFuncIndex) -> Result<String, Error> {
+        let range = self
+            .func_ranges
+            .get(index)
+            .ok_or_else(|| Error::Internal(format!("no defined function {}", index.index())))?;
+        let code = &self.executable[range.clone()];
+        disassm::disassemble(code, code.as_ptr() as u64).map_err(Error::Internal)
+    }
+
     pub fn dump(&self) {
         println!("relocations result");
         for (func, reloc) in self.relocs.iter() {
@@ -299,6 +1507,386 @@ impl Drop for Module {
     }
 }
 
+/// Duration of each phase of the pipeline, see `Module::compile_timed` and
+/// `execute_timed`. A phase which did not run is left at zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    /// Translation of the module, `parse_only`.
+    pub parse: Duration,
+    /// Compilation of the function bodies and their layout in executable
+    /// memory.
+    pub codegen: Duration,
+    /// Instantiation of the compiled module.
+    pub instantiate: Duration,
+    /// The call of the export function.
+    pub execute: Duration,
+}
+
+/// Run `f`, adding its duration to `slot` if any. Without a slot the clock is
+/// never read.
+fn timed<R>(slot: Option<&mut Duration>, f: impl FnOnce() -> R) -> R {
+    match slot {
+        Some(slot) => {
+            let start = Instant::now();
+            let result = f();
+            *slot += start.elapsed();
+            result
+        }
+        None => f(),
+    }
+}
+
+/// Compile `wasm`, instantiate it with the chain api and execute `func` like
+/// `Instance::execute`, recording the duration of each phase.
+///
+/// The module cache of `build_module` is bypassed so the compilation phases
+/// are always measured.
+pub fn execute_timed<A: WasmArgs>(
+    wasm: &[u8],
+    build_option: BuildOption,
+    tunables: Tunables,
+    chain: ChainCtx,
+    func: &str,
+    args: A,
+) -> Result<(Option<i64>, Timings), Error> {
+    let (module, mut timings) = Module::compile_timed(wasm, build_option, tunables)?;
+    let mut instance = timed(Some(&mut timings.instantiate), || {
+        Arc::new(module).instantiate(&mut ChainResolver)
+    })?;
+    let result = timed(Some(&mut timings.execute), || {
+        instance.execute(chain, func, args)
+    })?;
+    Ok((result, timings))
+}
+
+/// The wasm parameter types of a function, without the vmctx.
+fn normal_params(signature: &ir::Signature) -> Vec<ir::Type> {
+    signature
+        .params
+        .iter()
+        .filter(|param| param.purpose == ir::ArgumentPurpose::Normal)
+        .map(|param| param.value_type)
+        .collect()
+}
+
+/// Check the result types `R` against the results of `signature`.
+fn check_results<R: WasmResults>(signature: &ir::Signature) -> Result<(), Error> {
+    let returns: Vec<_> = signature.returns.iter().map(|ret| ret.value_type).collect();
+    if returns != R::types() {
+        return Err(Error::signature_mismatch(&returns, &R::types()));
+    }
+    Ok(())
+}
+
+/// Decode a scalar result slot into the `i64` returned by `Instance::call`:
+/// an `i32` is sign extended, an `i64` keeps its whole bit pattern whatever
+/// its sign, a float is its bit pattern zero extended.
+///
+/// The results are stored over the arguments, a 32 bits result leaves the
+/// high half of its slot as the argument was, so it is masked here. An `i64`
+/// is stored whole, a 32 bits target legalizes the store into two stores of
+/// its halves in little-endian order, so the slot reads the same on all the
+/// targets.
+fn scalar_result(slot: u128, ty: ir::Type) -> i64 {
+    match ty {
+        ir::types::I32 => i64::from(slot as u32 as i32),
+        ir::types::F32 => i64::from(slot as u32),
+        _ => slot as u64 as i64,
+    }
+}
+
+/// The compiled entry trampoline of an export function.
+struct Trampoline {
+    code: ExecutableBuffer,
+    /// Number of 64 bits words of each argument and result slot: two when
+    /// a result is a vector, one otherwise.
+    slot_words: usize,
+}
+
+/// An export function resolved once by `Instance::typed_func`, with the
+/// statically known parameter types `A` and result type `R`.
+///
+/// It borrows its instance for its whole life, the instance stays reachable
+/// through `instance` between calls, e.g. to set the host state.
+pub struct TypedFunc<'a, A, R> {
+    instance: &'a mut Instance,
+    invoke: ExportFunc,
+    trampoline: Trampoline,
+    marker: PhantomData<fn(A) -> R>,
+}
+
+impl<'a, A: WasmParams, R: WasmResults> TypedFunc<'a, A, R> {
+    /// Call the function with the current host state, like `Instance::call`.
+    pub fn call(&mut self, args: A) -> Result<R, Error> {
+        let slots =
+            self.instance
+                .call_trampoline(&self.invoke, &self.trampoline, args.into_raw())?;
+        Ok(R::from_slots(&slots))
+    }
+
+    /// The instance the function belongs to.
+    pub fn instance(&mut self) -> &mut Instance {
+        self.instance
+    }
+}
+
+/// A request to the `Watchdog` thread.
+enum Deadline {
+    /// Set the `timed_out` flag of the metrics once the instant has passed.
+    Arm(u64, Instant, Arc<ExecMetrics>),
+    /// Forget a deadline, and acknowledge once it can no longer fire.
+    Disarm(u64, mpsc::Sender<()>),
+}
+
+/// A thread setting the `timed_out` flag of the calls made with a timeout
+/// once their deadline has passed, shared by all the timed calls so a call
+/// does not spawn a thread.
+struct Watchdog {
+    requests: mpsc::Sender<Deadline>,
+    next_id: u64,
+}
+
+static WATCHDOG: Lazy<Mutex<Option<Watchdog>>> = Lazy::new(|| Mutex::new(None));
+
+impl Watchdog {
+    fn spawn() -> Result<Self, Error> {
+        let (requests, queue) = mpsc::channel();
+        thread::Builder::new()
+            .name("wasmjit-watchdog".to_string())
+            .spawn(move || Watchdog::watch(queue))
+            .map_err(|e| Error::Internal(format!("failed to spawn the watchdog: {}", e)))?;
+        Ok(Watchdog {
+            requests,
+            next_id: 0,
+        })
+    }
+
+    fn watch(requests: mpsc::Receiver<Deadline>) {
+        // the armed deadlines, the earliest first
+        let mut armed: Vec<(Instant, u64, Arc<ExecMetrics>)> = Vec::new();
+        loop {
+            let request = match armed.first() {
+                Some(&(deadline, ..)) => {
+                    let now = Instant::now();
+                    let wait = if deadline > now {
+                        requests.recv_timeout(deadline - now)
+                    } else {
+                        Err(RecvTimeoutError::Timeout)
+                    };
+                    match wait {
+                        Ok(request) => request,
+                        Err(RecvTimeoutError::Timeout) => {
+                            let (_, _, exec_metrics) = armed.remove(0);
+                            exec_metrics.timed_out.store(true, Ordering::Relaxed);
+                            continue;
+                        }
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                None => match requests.recv() {
+                    Ok(request) => request,
+                    Err(_) => return,
+                },
+            };
+            match request {
+                Deadline::Arm(id, deadline, exec_metrics) => {
+                    let at = armed
+                        .iter()
+                        .position(|&(other, ..)| other > deadline)
+                        .unwrap_or_else(|| armed.len());
+                    armed.insert(at, (deadline, id, exec_metrics));
+                }
+                Deadline::Disarm(id, ack) => {
+                    armed.retain(|&(_, other, _)| other != id);
+                    let _ = ack.send(());
+                }
+            }
+        }
+    }
+
+    /// Time out the call using `exec_metrics` at `deadline`, returning the id
+    /// to disarm it with.
+    fn arm(deadline: Instant, exec_metrics: Arc<ExecMetrics>) -> Result<u64, Error> {
+        let mut watchdog = WATCHDOG.lock();
+        if watchdog.is_none() {
+            *watchdog = Some(Watchdog::spawn()?);
+        }
+        let watchdog = watchdog.as_mut().unwrap();
+        let id = watchdog.next_id;
+        watchdog.next_id += 1;
+        watchdog
+            .requests
+            .send(Deadline::Arm(id, deadline, exec_metrics))
+            .map_err(|_| Error::Internal(String::from("the watchdog exited")))?;
+        Ok(id)
+    }
+
+    /// Disarm the deadline `id`. When this returns it no longer fires, so the
+    /// `timed_out` flag it would set can be cleared.
+    fn disarm(id: u64) {
+        let (ack, acked) = mpsc::channel();
+        let sent = match WATCHDOG.lock().as_ref() {
+            Some(watchdog) => watchdog.requests.send(Deadline::Disarm(id, ack)).is_ok(),
+            None => false,
+        };
+        // a watchdog which is gone no longer fires either
+        if sent {
+            let _ = acked.recv();
+        }
+    }
+}
+
+/// A call handed to a `CallWorker`.
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A thread with a native stack of `stack_bytes`, running the calls of the
+/// thread owning it one at a time, so a call does not spawn a thread.
+struct CallWorker {
+    stack_bytes: usize,
+    jobs: Option<mpsc::Sender<Job>>,
+    done: mpsc::Receiver<thread::Result<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl CallWorker {
+    fn spawn(stack_bytes: usize) -> Result<Self, Error> {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let (report, done) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("wasmjit-call".to_string())
+            .stack_size(stack_bytes)
+            .spawn(move || {
+                for job in queue {
+                    let result = panic::catch_unwind(panic::AssertUnwindSafe(job));
+                    if report.send(result).is_err() {
+                        break;
+                    }
+                }
+            })
+            .map_err(|e| Error::Internal(format!("failed to spawn the call thread: {}", e)))?;
+        Ok(CallWorker {
+            stack_bytes,
+            jobs: Some(jobs),
+            done,
+            thread: Some(thread),
+        })
+    }
+
+    /// Run `job` on the worker and wait until it is done, returning its panic
+    /// if it panicked. When this returns the worker no longer holds `job`:
+    /// either it reported the job done, or it is gone and dropped the job.
+    fn run(&mut self, job: Job) -> Result<thread::Result<()>, Error> {
+        let gone = || Error::Internal(String::from("the call thread exited"));
+        let jobs = self.jobs.as_ref().ok_or_else(gone)?;
+        // a job which can not be sent is dropped here, it never ran
+        jobs.send(job).map_err(|_| gone())?;
+        self.done.recv().map_err(|_| gone())
+    }
+}
+
+impl Drop for CallWorker {
+    fn drop(&mut self) {
+        // disconnect the queue, so the worker ends its loop
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+thread_local! {
+    /// The worker of the calls with a dedicated native stack made by this
+    /// thread, kept for the next calls with the same stack size.
+    static CALL_WORKER: RefCell<Option<CallWorker>> = RefCell::new(None);
+}
+
+/// Run `call` on a dedicated thread with a native stack of `stack_bytes`, or
+/// on the calling thread when `None`. The thread is reused by the next calls
+/// of the calling thread with the same stack size.
+fn on_native_stack<R: Send + 'static>(
+    stack_bytes: Option<usize>,
+    call: impl FnOnce() -> R,
+) -> Result<R, Error> {
+    let stack_bytes = match stack_bytes {
+        Some(stack_bytes) => stack_bytes,
+        None => return Ok(call()),
+    };
+
+    struct AssertSend<T>(T);
+    unsafe impl<T> Send for AssertSend<T> {}
+
+    let mut worker = match CALL_WORKER.with(|worker| worker.borrow_mut().take()) {
+        Some(worker) if worker.stack_bytes == stack_bytes => worker,
+        _ => CallWorker::spawn(stack_bytes)?,
+    };
+
+    let mut result = None;
+    let slot = AssertSend(&mut result as *mut Option<R>);
+    let call = AssertSend(call);
+    let job: Box<dyn FnOnce() + Send + '_> = Box::new(move || {
+        let (AssertSend(slot), AssertSend(call)) = (slot, call);
+        unsafe { *slot = Some(call()) };
+    });
+    // SAFETY: the job borrows `call` and `result` from this frame, so it must
+    // not run past it. `run` only returns once the worker is done with the
+    // job, see `CallWorker::run`, and nothing in between can unwind: a panic
+    // of the job is caught on the worker and only resumed below. The caller
+    // is blocked meanwhile, so nothing else runs on the instance.
+    let job: Job = unsafe { mem::transmute(job) };
+    let outcome = worker.run(job)?;
+
+    CALL_WORKER.with(|slot| *slot.borrow_mut() = Some(worker));
+    match outcome {
+        Ok(()) => Ok(result.expect("the call thread reported an unfinished call")),
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+fn target_config() -> isa::TargetFrontendConfig {
+    isa::TargetFrontendConfig {
+        default_call_conv: isa::CallConv::SystemV,
+        pointer_width: PointerWidth::U64,
+    }
+}
+
+/// The x86 extensions turned off under `Tunables::deterministic`, so the code
+/// generated is the same whatever the defaults of the cranelift version or the
+/// features of the host compiling it. Cranelift has no fused multiply-add to
+/// contract the float operations into, there is no flag to pin for it.
+pub(crate) const DETERMINISTIC_ISA_FLAGS: &[&str] = &[
+    "has_sse3",
+    "has_ssse3",
+    "has_sse41",
+    "has_sse42",
+    "has_popcnt",
+    "has_bmi1",
+    "has_bmi2",
+    "has_lzcnt",
+];
+
+/// Build the target isa with the cranelift flags selected by `tunables`.
+pub(crate) fn build_isa(tunables: &Tunables) -> Box<dyn isa::TargetIsa> {
+    let mut isa_builder = isa::lookup_by_name("x86_64").unwrap();
+    if tunables.deterministic {
+        for flag in DETERMINISTIC_ISA_FLAGS {
+            let _ = isa_builder.set(flag, "false");
+        }
+    }
+    let mut flag_builder = settings::builder();
+    let _ = flag_builder.set("probestack_enabled", "false");
+    let _ = flag_builder.set("opt_level", tunables.opt_level.as_str());
+    if tunables.nan_canonicalization {
+        let _ = flag_builder.enable("enable_nan_canonicalization");
+    }
+    if tunables.avoid_div_traps {
+        let _ = flag_builder.enable("avoid_div_traps");
+    }
+    if tunables.features.simd {
+        let _ = flag_builder.enable("enable_simd");
+    }
+    isa_builder.finish(settings::Flags::new(flag_builder))
+}
+
 fn register_traps(
     allocated_functions: &PrimaryMap<DefinedFuncIndex, *const VMFunctionBody>,
     traps: &Traps,
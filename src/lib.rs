@@ -9,17 +9,23 @@
 )]
 
 use crate::chain_api::{ChainCtx, ChainResolver};
-use crate::error::Error;
+use crate::error::{Error, ParseError};
 use crate::executor::build_module;
-use ontio_wasmjit_environ::BuildOption;
+use cranelift_wasm::WasmError;
+use ontio_wasmjit_environ::{BuildOption, CompileError};
+use std::borrow::Cow;
+use std::str;
 
+pub mod artifact;
 pub mod chain_api;
 pub mod error;
 pub mod executor;
 mod linker;
+pub mod pool;
 pub mod resolver;
 mod trampoline;
 pub mod utils;
+pub mod value;
 
 pub mod disassm;
 
@@ -47,6 +53,85 @@ pub fn execute2(
     instance.execute(chain, func, args)
 }
 
+/// The magic number opening a binary wasm module.
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+/// Return the binary module of `input`, which is assembled as WAT text unless
+/// it starts with the wasm magic number.
+pub fn read_module(input: &[u8]) -> Result<Cow<[u8]>, Error> {
+    if input.starts_with(WASM_MAGIC) {
+        return Ok(Cow::Borrowed(input));
+    }
+    let text_error = |e: &dyn std::fmt::Display| Error::Parse(ParseError::Text(e.to_string()));
+    let text = str::from_utf8(input).map_err(|e| text_error(&e))?;
+    wat::parse_str(text)
+        .map(Cow::Owned)
+        .map_err(|e| text_error(&e))
+}
+
+/// Execute like `execute`, taking the module either as WAT text or as binary
+/// wasm, told apart by the wasm magic number.
+///
+/// A binary which is not well formed is reported as `ParseError::Binary`,
+/// a text which does not assemble as `ParseError::Text`.
+pub fn execute_any(
+    input: &[u8],
+    chain: ChainCtx,
+    func: &str,
+    args: Vec<i64>,
+) -> Result<Option<i64>, Error> {
+    let binary = input.starts_with(WASM_MAGIC);
+    let wasm = read_module(input)?;
+    execute_bytes(&wasm, chain, func, args).map_err(|err| match err {
+        Error::Compile(CompileError::Wasm(WasmError::InvalidWebAssembly { message, offset }))
+            if binary =>
+        {
+            Error::Parse(ParseError::Binary(format!(
+                "{} at offset {}",
+                message, offset
+            )))
+        }
+        err => err,
+    })
+}
+
+/// Execute like `execute`, taking the module as binary wasm, so it is never
+/// round tripped through the WAT assembler.
+///
+/// Unlike `execute2`, a module which fails to validate, compile or
+/// instantiate is returned as an error instead of panicking.
+pub fn execute_bytes(
+    wasm: &[u8],
+    chain: ChainCtx,
+    func: &str,
+    args: Vec<i64>,
+) -> Result<Option<i64>, Error> {
+    let module = build_module(wasm, BuildOption::new().gas_metering(true))?;
+
+    let mut resolver = ChainResolver;
+    let mut instance = module.instantiate(&mut resolver)?;
+
+    instance.execute(chain, func, args)
+}
+
+/// A chain context for the tests, at timestamp 1 with `gas_left` gas.
+#[cfg(test)]
+fn test_chain(gas_left: u64) -> ChainCtx {
+    let exec_metrics =
+        ontio_wasmjit_runtime::ExecMetrics::new(u64::max_value(), 1, gas_left, 100000u64);
+    ChainCtx::new(
+        1,
+        1u32,
+        [1u8; 32],
+        [1u8; 32],
+        Vec::new(),
+        Vec::new(),
+        Vec::new(),
+        exec_metrics,
+        0,
+    )
+}
+
 #[test]
 fn test_memory_grow() {
     use ontio_wasmjit_runtime::ExecMetrics;
@@ -262,3 +347,3523 @@ fn test_check_gas() {
         }
     }
 }
+
+#[test]
+fn test_env_ret() {
+    let wat = r#"
+        (module
+          (import "env" "ret" (func $ret (param i32 i32)))
+          (import "env" "ontio_return" (func $return (param i32 i32)))
+          (func (export "invoke") (result i32)
+            (call $ret (i32.const 0) (i32.const 5))
+            (i32.const 7))
+          (func (export "invoke_twice")
+            (call $ret (i32.const 0) (i32.const 5))
+            (call $ret (i32.const 0) (i32.const 2)))
+          (func (export "invoke_huge")
+            (call $ret (i32.const 0) (i32.const 0x10001)))
+          (func (export "invoke_then_return")
+            (call $ret (i32.const 0) (i32.const 5))
+            (call $return (i32.const 0) (i32.const 2)))
+          (memory (;0;) 2)
+          (data (i32.const 0) "hello")
+        )"#;
+
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = build_module(&wasm, BuildOption::new().gas_metering(true)).unwrap();
+    let mut resolver = ChainResolver;
+    let mut instance = module.instantiate(&mut resolver).unwrap();
+
+    let res = instance
+        .execute(test_chain(u64::max_value()), "invoke", Vec::new())
+        .unwrap();
+    assert_eq!(res, Some(7));
+    assert!(instance.host_state().is_return_data_set());
+    assert_eq!(instance.host_state().take_output(), b"hello".to_vec());
+
+    assert!(instance
+        .execute(test_chain(u64::max_value()), "invoke_twice", Vec::new())
+        .is_err());
+    assert_eq!(instance.host_state().take_output(), b"hello".to_vec());
+
+    assert!(instance
+        .execute(test_chain(u64::max_value()), "invoke_huge", Vec::new())
+        .is_err());
+    assert!(instance.host_state().take_output().is_empty());
+
+    // ontio_return can not overwrite the data set by env.ret either
+    assert!(instance
+        .execute(
+            test_chain(u64::max_value()),
+            "invoke_then_return",
+            Vec::new()
+        )
+        .is_err());
+    assert_eq!(instance.host_state().take_output(), b"hello".to_vec());
+}
+
+#[test]
+fn test_opt_level() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::{OptLevel, Tunables};
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func (export "fac") (param i64) (result i64)
+            (local i64)
+            (local.set 1 (i64.const 1))
+            (block
+              (loop
+                (br_if 1 (i64.eqz (local.get 0)))
+                (local.set 1 (i64.mul (local.get 0) (local.get 1)))
+                (local.set 0 (i64.sub (local.get 0) (i64.const 1)))
+                (br 0)))
+            (local.get 1))
+        )"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    for &opt_level in [OptLevel::None, OptLevel::Speed, OptLevel::SpeedAndSize].iter() {
+        let mut tunables = Tunables::default();
+        tunables.opt_level = opt_level;
+        let module = Module::compile_with_tunables(&wasm, BuildOption::new(), tunables).unwrap();
+        let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+        let res = instance
+            .execute(ChainCtx::default(), "fac", vec![20])
+            .unwrap();
+        assert_eq!(res, Some(2_432_902_008_176_640_000));
+    }
+}
+
+#[test]
+fn test_instance_reuse() {
+    let wat = r#"
+        (module
+          (global $calls (mut i32) (i32.const 0))
+          (func (export "bump") (result i32)
+            (i32.store (i32.const 16) (i32.add (i32.load (i32.const 16)) (i32.const 1)))
+            (global.set $calls (i32.add (global.get $calls) (i32.const 1)))
+            (i32.add (i32.load (i32.const 16)) (global.get $calls)))
+          (func (export "add") (param i32 i32) (result i32)
+            (i32.add (local.get 0) (local.get 1)))
+          (memory (;0;) 1)
+        )"#;
+
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = build_module(&wasm, BuildOption::new()).unwrap();
+    let mut instance = module.instantiate(&mut ChainResolver).unwrap();
+
+    assert_eq!(instance.call("bump", Vec::new()).unwrap(), Some(2));
+    assert_eq!(instance.call("add", vec![3, 4]).unwrap(), Some(7));
+    assert_eq!(instance.call("bump", Vec::new()).unwrap(), Some(4));
+    assert_eq!(instance.call("bump", Vec::new()).unwrap(), Some(6));
+    assert!(instance.call("missing", Vec::new()).is_err());
+    assert_eq!(instance.call("bump", Vec::new()).unwrap(), Some(8));
+}
+
+#[test]
+fn test_zero_length_at_memory_boundary() {
+    let wat = r#"
+        (module
+          (import "env" "ret" (func $ret (param i32 i32)))
+          (func (export "at_end")
+            (call $ret (i32.const 65536) (i32.const 0)))
+          (func (export "past_end")
+            (call $ret (i32.const 65537) (i32.const 0)))
+          (memory (;0;) 1)
+        )"#;
+
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = build_module(&wasm, BuildOption::new()).unwrap();
+    let mut instance = module.instantiate(&mut ChainResolver).unwrap();
+
+    assert_eq!(instance.call("at_end", Vec::new()).unwrap(), None);
+    assert!(instance.host_state().take_output().is_empty());
+    assert!(instance.call("past_end", Vec::new()).is_err());
+}
+
+#[test]
+fn test_static_table_bounds() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::Tunables;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (type $t (func (result i32)))
+          (func $f (result i32) (i32.const 11))
+          (func $g (result i32) (i32.const 22))
+          (func (export "fixed") (param i32) (result i32)
+            (call_indirect (type $t) (local.get 0)))
+          (table 2 2 funcref)
+          (elem (i32.const 0) $f $g)
+        )"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    for &bound in [0u32, 0x1000].iter() {
+        let mut tunables = Tunables::default();
+        tunables.static_table_bound = bound;
+        let module = Module::compile_with_tunables(&wasm, BuildOption::new(), tunables).unwrap();
+        let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+
+        assert_eq!(instance.call("fixed", vec![0]).unwrap(), Some(11));
+        assert_eq!(instance.call("fixed", vec![1]).unwrap(), Some(22));
+        assert!(instance.call("fixed", vec![2]).is_err());
+        assert!(instance.call("fixed", vec![-1]).is_err());
+    }
+}
+
+#[test]
+fn test_snapshot_restore() {
+    let wat = r#"
+        (module
+          (global $g (mut i32) (i32.const 5))
+          (func (export "mutate") (result i32)
+            (drop (memory.grow (i32.const 2)))
+            (i32.store (i32.const 8) (i32.const 42))
+            (i32.store (i32.const 131072) (i32.const 43))
+            (global.set $g (i32.const 6))
+            (memory.size))
+          (func (export "size") (result i32)
+            (memory.size))
+          (func (export "load") (param i32) (result i32)
+            (i32.load (local.get 0)))
+          (func (export "global") (result i32)
+            (global.get $g))
+          (memory (;0;) 1 10)
+          (data (i32.const 8) "\01")
+        )"#;
+
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = build_module(&wasm, BuildOption::new()).unwrap();
+    let mut instance = module.instantiate(&mut ChainResolver).unwrap();
+
+    let snapshot = instance.snapshot();
+    assert_eq!(instance.call("mutate", Vec::new()).unwrap(), Some(3));
+    assert_eq!(instance.call("load", vec![8]).unwrap(), Some(42));
+    assert_eq!(instance.call("global", Vec::new()).unwrap(), Some(6));
+
+    instance.restore(&snapshot).unwrap();
+    assert_eq!(instance.call("size", Vec::new()).unwrap(), Some(1));
+    assert_eq!(instance.call("load", vec![8]).unwrap(), Some(1));
+    assert_eq!(instance.call("global", Vec::new()).unwrap(), Some(5));
+    assert!(instance.call("load", vec![131072]).is_err());
+
+    // the pages grown again start zeroed
+    assert_eq!(instance.call("mutate", Vec::new()).unwrap(), Some(3));
+    instance.restore(&snapshot).unwrap();
+    assert_eq!(instance.call("mutate", Vec::new()).unwrap(), Some(3));
+    assert_eq!(instance.call("load", vec![65536]).unwrap(), Some(0));
+}
+
+#[test]
+fn test_deterministic_tunables() {
+    use crate::executor::{build_isa, Module, DETERMINISTIC_ISA_FLAGS};
+    use cranelift_codegen::isa;
+    use cranelift_codegen::settings::Configurable;
+    use ontio_wasmjit_environ::Tunables;
+
+    // the pinned flags are all known to cranelift
+    let mut isa_builder = isa::lookup_by_name("x86_64").unwrap();
+    for flag in DETERMINISTIC_ISA_FLAGS {
+        assert!(isa_builder.set(flag, "false").is_ok(), "{}", flag);
+    }
+
+    let tunables = Tunables::deterministic(true);
+    assert!(tunables.deterministic);
+    assert!(tunables.nan_canonicalization);
+    assert!(tunables.reject_float);
+    assert!(tunables.avoid_div_traps);
+    let isa = build_isa(&tunables);
+    assert!(isa.flags().enable_nan_canonicalization());
+    assert!(isa.flags().avoid_div_traps());
+
+    let tunables = Tunables::deterministic(false);
+    assert!(!tunables.deterministic);
+    assert!(!tunables.nan_canonicalization);
+    assert!(!tunables.reject_float);
+    assert!(!tunables.avoid_div_traps);
+    let isa = build_isa(&tunables);
+    assert!(!isa.flags().enable_nan_canonicalization());
+    assert!(!isa.flags().avoid_div_traps());
+
+    let float_op = wat::parse_str(
+        r#"
+        (module
+          (func (export "invoke") (result i32)
+            (i32.trunc_f32_s (f32.const 1.5))))"#,
+    )
+    .unwrap();
+    let float_sig = wat::parse_str(
+        r#"
+        (module
+          (func (export "invoke") (param f64)))"#,
+    )
+    .unwrap();
+    let float_global = wat::parse_str(
+        r#"
+        (module
+          (global f32 (f32.const 0)))"#,
+    )
+    .unwrap();
+    for wasm in [float_op, float_sig, float_global].iter() {
+        assert!(
+            Module::compile_with_tunables(wasm, BuildOption::new(), Tunables::default()).is_ok()
+        );
+        assert!(Module::compile_with_tunables(
+            wasm,
+            BuildOption::new(),
+            Tunables::deterministic(true)
+        )
+        .is_err());
+    }
+}
+
+#[test]
+fn test_execution_receipt() {
+    use crate::resolver::Resolver;
+    use crate::value::Value;
+    use ontio_wasmjit_environ::{GasCostTable, HostCallCharge};
+    use ontio_wasmjit_runtime::{HostCallCost, VMFunctionImport};
+    use std::sync::Arc;
+
+    /// Charges 10 gas per started KiB of an event.
+    struct NotifyFee;
+    impl Resolver for NotifyFee {
+        fn resolve(&mut self, module: &str, field: &str) -> Option<VMFunctionImport> {
+            ChainResolver.resolve(module, field)
+        }
+        fn resolve_cost(&mut self, _module: &str, field: &str) -> Option<HostCallCost> {
+            match field {
+                "ontio_notify" => Some(HostCallCost::Arguments(Arc::new(|args: &[u64]| {
+                    (args[1] / 1024 + 1) * 10
+                }))),
+                _ => None,
+            }
+        }
+    }
+
+    let wat = r#"
+        (module
+          (import "env" "ontio_notify" (func $notify (param i32 i32)))
+          (import "env" "ret" (func $ret (param i32 i32)))
+          (global $count (mut i32) (i32.const 0))
+          (func (export "invoke") (result i32)
+            (local i32)
+            (block
+              (loop
+                (br_if 1 (i32.ge_u (local.get 0) (i32.const 10)))
+                (local.set 0 (i32.add (local.get 0) (i32.const 1)))
+                (br 0)))
+            (call $notify (i32.const 0) (i32.const 5))
+            (call $notify (i32.const 5) (i32.const 5))
+            (call $ret (i32.const 10) (i32.const 2))
+            (i32.store8 (i32.const 100) (i32.const 1))
+            (global.set $count (local.get 0))
+            (local.get 0))
+          (memory (;0;) 1)
+          (data (i32.const 0) "hello" "world" "ok")
+        )"#;
+
+    let gas_limit = 1_000_000;
+    let chain = test_chain(gas_limit);
+
+    // the operators are free, only the events are charged
+    let table = GasCostTable {
+        arithmetic: 0,
+        memory: 0,
+        call: 0,
+        control: 0,
+        ..GasCostTable::default()
+    };
+    let wasm = wat::parse_str(wat).unwrap();
+    let build_option = BuildOption::new()
+        .gas_metering(true)
+        .gas_cost_table(table)
+        .host_call_charge(HostCallCharge::Arguments);
+    let module = build_module(&wasm, build_option).unwrap();
+    let mut instance = module.instantiate(&mut NotifyFee).unwrap();
+    let receipt = instance
+        .try_execute_full(chain, "invoke", Vec::new())
+        .unwrap();
+
+    assert_eq!(receipt.result, Some(10));
+    assert_eq!(receipt.gas_used, 2 * 10);
+    assert_eq!(receipt.events, vec![b"hello".to_vec(), b"world".to_vec()]);
+    assert_eq!(receipt.return_data, b"ok".to_vec());
+
+    let mut state = vec![0u8; 0x10000];
+    state[..12].copy_from_slice(b"helloworldok");
+    state[100] = 1;
+    state.extend_from_slice(&10i32.to_le_bytes());
+    assert_eq!(receipt.state_hash, hmac_sha256::Hash::hash(&state));
+    assert_eq!(receipt.state_hash, instance.state_hash());
+
+    // the mutable globals are part of the state
+    instance.set_global(0, Value::I32(11)).unwrap();
+    assert_ne!(receipt.state_hash, instance.state_hash());
+}
+
+#[test]
+fn test_call_timeout() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::Tunables;
+    use ontio_wasmjit_runtime::TrapCode;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let wat = r#"
+        (module
+          (func (export "spin")
+            (loop (br 0)))
+          (func (export "done")))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let module = Module::compile_with_tunables(
+        &wasm,
+        BuildOption::new().gas_metering(true),
+        Tunables::default(),
+    )
+    .unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    instance.set_host_state(Box::new(test_chain(u64::max_value())));
+    // the loop never ends by itself, whenever the timeout fires
+    match instance.call_with_timeout("spin", Vec::new(), Duration::from_millis(50)) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::Timeout),
+        _ => panic!("expected a timeout trap"),
+    }
+    match instance.call_with_timeout("spin", Vec::new(), Duration::from_millis(0)) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::Timeout),
+        _ => panic!("expected a timeout trap"),
+    }
+    // a call returning before its deadline does not wait for it, and the
+    // deadline passing afterwards has no effect on the next call
+    let start = std::time::Instant::now();
+    let timeout = Duration::from_millis(20);
+    assert_eq!(
+        instance
+            .call_with_timeout("done", Vec::new(), timeout)
+            .unwrap(),
+        None
+    );
+    std::thread::sleep(timeout * 2);
+    assert_eq!(instance.call("done", Vec::new()).unwrap(), None);
+    let long = Duration::from_secs(60);
+    assert_eq!(
+        instance
+            .call_with_timeout("done", Vec::new(), long)
+            .unwrap(),
+        None
+    );
+    assert!(start.elapsed() < long);
+
+    // the wall clock is ignored in deterministic mode, the gas ends the loop
+    let module = Module::compile_with_tunables(
+        &wasm,
+        BuildOption::new().gas_metering(true),
+        Tunables::deterministic(true),
+    )
+    .unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    instance.set_host_state(Box::new(test_chain(1_000_000)));
+    match instance.call_with_timeout("spin", Vec::new(), Duration::from_millis(0)) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::OutOfGas),
+        _ => panic!("expected a gas exhausted trap"),
+    }
+    assert_eq!(instance.host_state().gas_left(), 0);
+}
+
+#[test]
+fn test_module_exports() {
+    use crate::executor::Module;
+    use cranelift_codegen::ir::{types, ArgumentPurpose};
+    use ontio_wasmjit_environ::{ExportKind, ExportType};
+
+    let wat = r#"
+        (module
+          (func (export "add") (param i32 i64) (result i64)
+            (i64.add (i64.extend_i32_u (local.get 0)) (local.get 1)))
+          (func (export "invoke")))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+
+    let exports: Vec<_> = module.exports().collect();
+    assert_eq!(exports.len(), 2);
+
+    let (name, kind, ty) = exports[0];
+    assert_eq!(name, "add");
+    assert_eq!(kind, ExportKind::Function);
+    let sig = match ty {
+        ExportType::Function(sig) => sig,
+        _ => panic!("expected a function"),
+    };
+    assert_eq!(sig.params[0].purpose, ArgumentPurpose::VMContext);
+    let params: Vec<_> = sig.params[1..].iter().map(|p| p.value_type).collect();
+    assert_eq!(params, vec![types::I32, types::I64]);
+    assert_eq!(sig.returns.len(), 1);
+    assert_eq!(sig.returns[0].value_type, types::I64);
+
+    let (name, kind, ty) = exports[1];
+    assert_eq!(name, "invoke");
+    assert_eq!(kind, ExportKind::Function);
+    let sig = match ty {
+        ExportType::Function(sig) => sig,
+        _ => panic!("expected a function"),
+    };
+    assert_eq!(sig.params.len(), 1);
+    assert!(sig.returns.is_empty());
+}
+
+#[test]
+fn test_name_section() {
+    use crate::executor::{parse_only, Module};
+    use cranelift_entity::EntityRef;
+    use cranelift_wasm::FuncIndex;
+    use ontio_wasmjit_environ::{CompileError, Tunables};
+
+    let named = wat::parse_str("(module (func $bad (drop (f32.const 0))))").unwrap();
+    let unnamed = wat::parse_str("(module (func (drop (f32.const 0))))").unwrap();
+    let compile = |wasm: &[u8]| {
+        Module::compile_with_tunables(wasm, BuildOption::new(), Tunables::deterministic(true))
+    };
+
+    match compile(&named) {
+        Err(Error::Compile(CompileError::Function { name, .. })) => assert_eq!(name, "bad"),
+        _ => panic!("expected an error naming the function"),
+    }
+    match compile(&unnamed) {
+        Err(Error::Compile(CompileError::Wasm(_))) => {}
+        _ => panic!("expected a plain translation error"),
+    }
+
+    // a malformed name section is ignored, the names read before are kept
+    let mut malformed = named.clone();
+    malformed.extend_from_slice(&[0, 8, 4, b'n', b'a', b'm', b'e', 1, 5, 0xff]);
+    match compile(&malformed) {
+        Err(Error::Compile(CompileError::Function { name, .. })) => assert_eq!(name, "bad"),
+        _ => panic!("expected an error naming the function"),
+    }
+    let mut valid = wat::parse_str("(module (func $ok))").unwrap();
+    valid.extend_from_slice(&[0, 8, 4, b'n', b'a', b'm', b'e', 1, 5, 0xff]);
+    assert!(Module::compile(&valid, BuildOption::new()).is_ok());
+
+    // the local names are kept on the translation
+    let wasm = wat::parse_str("(module (func $f (param $a i32) (local $b i64)))").unwrap();
+    let translation = parse_only(&wasm, Tunables::default()).unwrap();
+    let locals = &translation.local_names[&FuncIndex::new(0)];
+    assert_eq!(locals[&0], "a");
+    assert_eq!(locals[&1], "b");
+}
+
+#[test]
+fn test_typed_args() {
+    use crate::value::Value;
+
+    let wat = r#"
+        (module
+          (func (export "mix") (param i64 f64) (result i64)
+            (i64.add (local.get 0) (i64.trunc_f64_s (local.get 1))))
+          (func (export "mix3") (param i32 i64 f32) (result i64)
+            (i64.add
+              (i64.extend_i32_s (local.get 0))
+              (i64.add (local.get 1) (i64.trunc_f32_s (local.get 2))))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = build_module(&wasm, BuildOption::new()).unwrap();
+    let mut instance = module.instantiate(&mut ChainResolver).unwrap();
+
+    assert_eq!(
+        instance.call("mix", (1i64 << 40, 2.75f64)).unwrap(),
+        Some((1 << 40) + 2)
+    );
+    assert_eq!(
+        instance.call("mix3", (-3i32, 10i64, 4.5f32)).unwrap(),
+        Some(11)
+    );
+    let args = [Value::I32(-3), Value::I64(10), Value::F32(4.5)];
+    assert_eq!(instance.call("mix3", &args[..]).unwrap(), Some(11));
+
+    match instance.call("mix", (1i64, 2i64)) {
+        Err(Error::SignatureMismatch { expected, found }) => {
+            assert_eq!(expected, "(i64, f64)");
+            assert_eq!(found, "(i64, i64)");
+        }
+        _ => panic!("expected a signature mismatch"),
+    }
+    assert!(instance.call("mix", (1i64,)).is_err());
+    assert!(instance.call("mix", vec![1]).is_err());
+}
+
+#[test]
+fn test_resource_limits() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::{CompileError, Tunables};
+
+    let wat = r#"
+        (module
+          (import "env" "ontio_timestamp" (func (result i64)))
+          (import "env" "ontio_block_height" (func (result i32)))
+          (func) (func) (func)
+          (table 8 funcref)
+          (global i32 (i32.const 0))
+          (global i32 (i32.const 1))
+          (memory 1)
+          (data (i32.const 0) "hello")
+          (data (i32.const 8) "world"))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let compile_error = |tunables: Tunables| match Module::compile_with_tunables(
+        &wasm,
+        BuildOption::new(),
+        tunables,
+    ) {
+        Err(Error::Compile(CompileError::Wasm(err))) => err.to_string(),
+        Err(err) => panic!("unexpected error: {}", err),
+        Ok(_) => String::from("ok"),
+    };
+
+    let mut tunables = Tunables::default();
+    tunables.max_functions = Some(3);
+    tunables.max_table_size = Some(8);
+    tunables.max_globals = Some(2);
+    tunables.max_imports = Some(2);
+    tunables.max_data_bytes = Some(10);
+    assert_eq!(compile_error(tunables.clone()), "ok");
+
+    let mut limited = tunables.clone();
+    limited.max_functions = Some(2);
+    assert!(compile_error(limited).contains("max_functions: 3 > 2"));
+    let mut limited = tunables.clone();
+    limited.max_table_size = Some(7);
+    assert!(compile_error(limited).contains("max_table_size: 8 > 7"));
+    let mut limited = tunables.clone();
+    limited.max_globals = Some(1);
+    assert!(compile_error(limited).contains("max_globals: 2 > 1"));
+    let mut limited = tunables.clone();
+    limited.max_imports = Some(1);
+    assert!(compile_error(limited).contains("max_imports: 2 > 1"));
+    let mut limited = tunables;
+    limited.max_data_bytes = Some(9);
+    assert!(compile_error(limited).contains("max_data_bytes: 10 > 9"));
+}
+
+#[test]
+fn test_max_function_body_bytes() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::{CompileError, Tunables};
+
+    let wat = r#"
+        (module
+          (func (result i32) (i32.const 1))
+          (func (result i32)
+            (i32.add (i32.const 1) (i32.add (i32.const 2) (i32.const 3)))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let mut tunables = Tunables::default();
+    tunables.max_function_body_bytes = Some(12);
+    assert!(Module::compile_with_tunables(&wasm, BuildOption::new(), tunables.clone()).is_ok());
+
+    // the second body is `0` locals, 3 consts of 2 bytes, 2 adds and `end`
+    tunables.max_function_body_bytes = Some(9);
+    match Module::compile_with_tunables(&wasm, BuildOption::new(), tunables) {
+        Err(Error::Compile(CompileError::Wasm(err))) => assert!(err
+            .to_string()
+            .contains("defined function 1 exceeds max_function_body_bytes: 10 > 9")),
+        _ => panic!("expected the function body to be rejected"),
+    }
+}
+
+#[test]
+fn test_max_locals() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::{CompileError, Tunables};
+
+    // a single function declaring 1_000_000 i32 locals
+    let wasm = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section: () -> ()
+        0x03, 0x02, 0x01, 0x00, // function section
+        0x0a, 0x08, 0x01, 0x06, 0x01, 0xc0, 0x84, 0x3d, 0x7f, 0x0b, // code section
+    ];
+    let mut tunables = Tunables::default();
+    tunables.max_locals = Some(1000);
+    match Module::compile_with_tunables(&wasm, BuildOption::new(), tunables.clone()) {
+        Err(Error::Compile(CompileError::Wasm(err))) => assert!(err
+            .to_string()
+            .contains("defined function 0 exceeds max_locals: 1000000 > 1000")),
+        _ => panic!("expected the locals to be rejected"),
+    }
+
+    let wat = "(module (func (param i64) (local i32 i32) (local i64)))";
+    let wasm = wat::parse_str(wat).unwrap();
+    tunables.max_locals = Some(3);
+    assert!(Module::compile_with_tunables(&wasm, BuildOption::new(), tunables.clone()).is_ok());
+    tunables.max_locals = Some(2);
+    assert!(Module::compile_with_tunables(&wasm, BuildOption::new(), tunables).is_err());
+}
+
+#[test]
+fn test_data_init_bounds() {
+    use crate::executor::Module;
+    use std::sync::Arc;
+
+    let instantiate = |base: i32| {
+        let wat = format!(
+            r#"
+            (module
+              (global i32 (i32.const {}))
+              (memory 1)
+              (data (i32.const 0) "first")
+              (data (global.get 0) "0123456789")
+              (func (export "load") (param i32) (result i32)
+                (i32.load8_u (local.get 0))))"#,
+            base
+        );
+        let wasm = wat::parse_str(wat).unwrap();
+        let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+        Arc::new(module).instantiate(&mut ChainResolver)
+    };
+
+    // the base global is resolved before the bounds are checked
+    let mut instance = instantiate(100).unwrap();
+    assert_eq!(
+        instance.call("load", (100i32,)).unwrap(),
+        Some(i64::from(b'0'))
+    );
+    assert_eq!(
+        instance.call("load", (109i32,)).unwrap(),
+        Some(i64::from(b'9'))
+    );
+    assert_eq!(
+        instance.call("load", (0i32,)).unwrap(),
+        Some(i64::from(b'f'))
+    );
+
+    assert!(instantiate(65526).is_ok());
+    match instantiate(65527) {
+        Err(Error::Link(msg)) => assert!(msg.contains("offset 65527 and length 10")),
+        _ => panic!("expected the data segment not to fit"),
+    }
+    match instantiate(-1) {
+        Err(Error::Link(_)) => {}
+        _ => panic!("expected the data segment not to fit"),
+    }
+}
+
+#[test]
+fn test_passive_data_rejected() {
+    use crate::executor::Module;
+
+    let wat = r#"
+        (module
+          (memory 1)
+          (data "passive")
+          (func (export "init")
+            (memory.init 0 (i32.const 0) (i32.const 0) (i32.const 7))
+            (data.drop 0)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    match Module::compile(&wasm, BuildOption::new()) {
+        Err(Error::Compile(_)) => {}
+        _ => panic!("expected the passive data segment to fail the compilation"),
+    }
+}
+
+#[test]
+fn test_simd() {
+    use crate::executor::{build_isa, Module};
+    use ontio_wasmjit_environ::Tunables;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (memory 1)
+          (func (export "lanes") (param i32) (result i32)
+            (v128.store (i32.const 16)
+              (i32x4.add (i32x4.splat (local.get 0)) (i32x4.splat (i32.const 2))))
+            (i32.add
+              (i32x4.extract_lane 3 (v128.load (i32.const 16)))
+              (i32.load (i32.const 20)))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    assert!(Module::compile(&wasm, BuildOption::new()).is_err());
+
+    let mut tunables = Tunables::default();
+    tunables.features.simd = true;
+    assert!(build_isa(&tunables).flags().enable_simd());
+    let module = Module::compile_with_tunables(&wasm, BuildOption::new(), tunables).unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    assert_eq!(instance.call("lanes", (40i32,)).unwrap(), Some(84));
+
+    // float lanes are no escape from the deterministic float rejection
+    let wat = r#"
+        (module
+          (func (export "lanes") (param i32) (result i32)
+            (i32x4.extract_lane 0
+              (i32x4.trunc_sat_f32x4_s (f32x4.convert_i32x4_s (i32x4.splat (local.get 0)))))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let mut tunables = Tunables::deterministic(true);
+    tunables.features.simd = true;
+    assert!(Module::compile_with_tunables(&wasm, BuildOption::new(), tunables).is_err());
+}
+
+#[test]
+fn test_trap_frame() {
+    use cranelift_entity::EntityRef;
+    use cranelift_wasm::DefinedFuncIndex;
+    use ontio_wasmjit_runtime::TrapCode;
+
+    let wat = r#"
+        (module
+          (func $outer (export "trap") (param i32) (result i32)
+            (call $inner (local.get 0)))
+          (func $inner (param i32) (result i32)
+            (i32.div_u (i32.const 1) (local.get 0))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = build_module(&wasm, BuildOption::new()).unwrap();
+    let mut instance = module.instantiate(&mut ChainResolver).unwrap();
+
+    assert_eq!(instance.call("trap", (1i32,)).unwrap(), Some(1));
+    let trap = match instance.call("trap", (0i32,)) {
+        Err(Error::Trap(trap)) => trap,
+        _ => panic!("expected a trap"),
+    };
+    assert_eq!(trap.code, TrapCode::IntegerDivisionByZero);
+    assert!(trap.pc.is_some());
+    // the trapping frame, not its caller $outer
+    let frame = trap.frame.as_ref().unwrap();
+    assert_eq!(frame.func_index, DefinedFuncIndex::new(1));
+    assert_eq!(frame.func_name, Some(String::from("inner")));
+    let offset = frame.module_offset.unwrap();
+    // i32.div_u
+    assert_eq!(wasm[offset], 0x6e);
+    assert!(trap.to_string().contains("in inner at module offset"));
+}
+
+#[test]
+fn test_host_call_hook() {
+    use crate::executor::Module;
+    use ontio_wasmjit_runtime::HostCallPhase;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (import "env" "ontio_timestamp" (func $timestamp (result i64)))
+          (func (export "now") (result i64)
+            (call $timestamp)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let hook = |calls: Arc<Mutex<Vec<_>>>| -> ontio_wasmjit_runtime::HostCallHook {
+        Box::new(move |call| {
+            calls.lock().push((
+                format!("{}.{}", call.module, call.field),
+                call.phase,
+                call.values.to_vec(),
+            ))
+        })
+    };
+
+    let traced = Module::compile(&wasm, BuildOption::new().trace_host_calls(true)).unwrap();
+    let mut instance = Arc::new(traced).instantiate(&mut ChainResolver).unwrap();
+    instance.set_host_call_hook(Some(hook(calls.clone())));
+    assert_eq!(
+        instance
+            .execute(test_chain(u64::max_value()), "now", ())
+            .unwrap(),
+        Some(1)
+    );
+    assert_eq!(
+        *calls.lock(),
+        vec![
+            (
+                String::from("env.ontio_timestamp"),
+                HostCallPhase::Entry,
+                vec![]
+            ),
+            (
+                String::from("env.ontio_timestamp"),
+                HostCallPhase::Exit,
+                vec![1]
+            ),
+        ]
+    );
+
+    // without a hook the traced module runs as usual
+    instance.set_host_call_hook(None);
+    assert_eq!(
+        instance
+            .execute(test_chain(u64::max_value()), "now", ())
+            .unwrap(),
+        Some(1)
+    );
+    assert_eq!(calls.lock().len(), 2);
+
+    // and a module compiled without tracing never reports its calls
+    calls.lock().clear();
+    let untraced = Module::compile(&wasm, BuildOption::new()).unwrap();
+    let mut instance = Arc::new(untraced).instantiate(&mut ChainResolver).unwrap();
+    instance.set_host_call_hook(Some(hook(calls.clone())));
+    assert_eq!(
+        instance
+            .execute(test_chain(u64::max_value()), "now", ())
+            .unwrap(),
+        Some(1)
+    );
+    assert!(calls.lock().is_empty());
+}
+
+#[test]
+fn test_gas_cost_table() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::GasCostTable;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func (export "invoke") (result i32)
+            (local i32)
+            (block
+              (loop
+                (br_if 1 (i32.ge_u (local.get 0) (i32.const 10)))
+                (i32.store (i32.const 0) (local.get 0))
+                (local.set 0 (i32.add (local.get 0) (i32.const 1)))
+                (br 0)))
+            (local.get 0))
+          (memory (;0;) 1)
+        )"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let gas_used = |table: GasCostTable| {
+        let gas_limit = 1_000_000;
+        let chain = test_chain(gas_limit);
+        let build_option = BuildOption::new().gas_metering(true).gas_cost_table(table);
+        let module = Module::compile(&wasm, build_option).unwrap();
+        let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+        let receipt = instance.try_execute_full(chain, "invoke", ()).unwrap();
+        assert_eq!(receipt.result, Some(10));
+        receipt.gas_used
+    };
+
+    let default = gas_used(GasCostTable::default());
+    let expensive_arithmetic = gas_used(GasCostTable {
+        arithmetic: 10,
+        ..GasCostTable::default()
+    });
+    let free_memory = gas_used(GasCostTable {
+        memory: 0,
+        ..GasCostTable::default()
+    });
+    assert!(default > 0);
+    assert!(expensive_arithmetic > default);
+    // one store for each of the ten iterations
+    assert_eq!(free_memory, default - 10);
+    // the ten branches back to the loop are charged one each
+    let free = gas_used(GasCostTable {
+        arithmetic: 0,
+        memory: 0,
+        call: 0,
+        control: 0,
+        ..GasCostTable::default()
+    });
+    assert_eq!(free, 10);
+}
+
+#[test]
+fn test_memory_grow_gas() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::{GasCostTable, Tunables};
+    use ontio_wasmjit_runtime::TrapCode;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func (export "grow") (param i32) (result i32)
+            (memory.grow (local.get 0)))
+          (func (export "size") (result i32)
+            (memory.size))
+          (memory (;0;) 1)
+        )"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let build_option = BuildOption::new()
+        .gas_metering(true)
+        .gas_cost_table(GasCostTable {
+            grow: 1000,
+            ..GasCostTable::default()
+        });
+    let mut tunables = Tunables::default();
+    tunables.max_memory_pages = Some(4);
+    let module = Module::compile_with_tunables(&wasm, build_option, tunables.clone()).unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+
+    // each page is charged
+    let receipt = instance
+        .try_execute_full(test_chain(10_000), "grow", (1i32,))
+        .unwrap();
+    assert_eq!(receipt.result, Some(1));
+    assert!(receipt.gas_used >= 1000 && receipt.gas_used < 2000);
+
+    // the page cap is checked first, a grow beyond it fails without being charged
+    let receipt = instance
+        .try_execute_full(test_chain(10_000), "grow", (10i32,))
+        .unwrap();
+    assert_eq!(receipt.result, Some(-1));
+    assert!(receipt.gas_used < 1000);
+
+    // within the cap, the gas is checked before the memory grows
+    match instance.execute(test_chain(1500), "grow", (2i32,)) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::OutOfGas),
+        _ => panic!("expected an out of gas trap"),
+    }
+    assert_eq!(
+        instance.execute(test_chain(10_000), "size", ()).unwrap(),
+        Some(2)
+    );
+
+    let wat = r#"(module (memory (;0;) 5))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    assert!(Module::compile_with_tunables(&wasm, BuildOption::new(), tunables).is_err());
+}
+
+#[test]
+fn test_call_export_by_index() {
+    let wat = r#"
+        (module
+          (func $double (param i32) (result i32)
+            (i32.mul (local.get 0) (i32.const 2)))
+          (func (export "square") (param i32) (result i32)
+            (i32.mul (local.get 0) (local.get 0)))
+          (export "double" (func $double)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = build_module(&wasm, BuildOption::new()).unwrap();
+    let names: Vec<_> = module
+        .exports()
+        .map(|(name, _, _)| name.to_string())
+        .collect();
+    assert_eq!(names, vec!["square", "double"]);
+
+    let mut instance = module.instantiate(&mut ChainResolver).unwrap();
+    for (index, name) in names.iter().enumerate() {
+        assert_eq!(
+            instance.call_export_by_index(index, (7i32,)).unwrap(),
+            instance.call(name, (7i32,)).unwrap()
+        );
+    }
+    assert_eq!(instance.call_export_by_index(0, (7i32,)).unwrap(), Some(49));
+    assert_eq!(instance.call_export_by_index(1, (7i32,)).unwrap(), Some(14));
+    assert!(instance.call_export_by_index(2, (7i32,)).is_err());
+}
+
+#[test]
+fn test_artifact_file() {
+    use crate::artifact::{compile_to_file, load_from_file};
+    use ontio_wasmjit_environ::Tunables;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func $fib (export "fib") (param i32) (result i32)
+            (if (result i32) (i32.lt_u (local.get 0) (i32.const 2))
+              (then (local.get 0))
+              (else
+                (i32.add
+                  (call $fib (i32.sub (local.get 0) (i32.const 1)))
+                  (call $fib (i32.sub (local.get 0) (i32.const 2)))))))
+          (func (export "load") (result i32)
+            (i32.load8_u (i32.const 3)))
+          (memory (;0;) 1)
+          (data (i32.const 0) "wasm"))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let path = std::env::temp_dir().join(format!("wasmjit-artifact-{}.bin", std::process::id()));
+    compile_to_file(&wasm, BuildOption::new(), Tunables::default(), &path).unwrap();
+    let module = load_from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    assert_eq!(instance.call("fib", (10i32,)).unwrap(), Some(55));
+    assert_eq!(instance.call("load", ()).unwrap(), Some(i64::from(b'm')));
+
+    assert!(load_from_file(&path).is_err());
+    assert!(crate::executor::Module::deserialize(b"garbage").is_err());
+
+    // an artifact compiled with other settings is refused
+    let module = crate::executor::Module::compile(&wasm, BuildOption::new()).unwrap();
+    let mut bytes = module.serialize().unwrap();
+    let settings = 8 + crate::artifact::ARTIFACT_VERSION.len();
+    bytes[settings] ^= 1;
+    match crate::executor::Module::deserialize(&bytes) {
+        Err(Error::Internal(msg)) => assert!(msg.contains("stale"), "{}", msg),
+        _ => panic!("expected the artifact to be refused"),
+    }
+}
+
+#[test]
+fn test_parse_only() {
+    use crate::executor::parse_only;
+    use ontio_wasmjit_environ::Tunables;
+
+    let wat = r#"
+        (module
+          (import "env" "ontio_timestamp" (func $timestamp (result i64)))
+          (func (export "invoke") (result i64)
+            (call $timestamp))
+          (func (param i32) (result i32)
+            (local.get 0))
+          (memory (;0;) 1)
+          (data (i32.const 0) "hello"))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let translation = parse_only(&wasm, Tunables::default()).unwrap();
+    assert_eq!(translation.module.imported_funcs.len(), 1);
+    assert_eq!(translation.function_body_inputs.len(), 2);
+    assert_eq!(translation.data_initializers[0].data, b"hello");
+
+    // malformed modules are rejected, they never panic
+    for len in 0..wasm.len() {
+        let _ = parse_only(&wasm[..len], Tunables::default());
+    }
+    for i in 0..wasm.len() {
+        let mut corrupted = wasm.clone();
+        corrupted[i] ^= 0xff;
+        let _ = parse_only(&corrupted, Tunables::default());
+    }
+    assert!(parse_only(&wasm[..wasm.len() - 1], Tunables::default()).is_err());
+}
+
+#[test]
+#[cfg(feature = "dump-ir")]
+fn test_dump_function_ir() {
+    use crate::executor::{dump_function_ir, Module};
+    use cranelift_entity::EntityRef;
+    use cranelift_wasm::DefinedFuncIndex;
+    use ontio_wasmjit_environ::Tunables;
+
+    let wat = r#"
+        (module
+          (func (export "add") (param i32 i32) (result i32)
+            (i32.add (local.get 0) (local.get 1)))
+          (func (export "mix") (param i32) (result i32)
+            (v128.store (i32.const 0) (i32x4.splat (local.get 0)))
+            (i32.load (i32.const 0)))
+          (memory (;0;) 1))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    // the SIMD function fails the compilation of the module...
+    assert!(Module::compile(&wasm, BuildOption::new()).is_err());
+
+    // ...but the IR of the other one is still available
+    let ir = dump_function_ir(
+        &wasm,
+        BuildOption::new(),
+        Tunables::default(),
+        DefinedFuncIndex::new(0),
+    )
+    .unwrap();
+    assert!(ir.contains("iadd"));
+    assert!(dump_function_ir(
+        &wasm,
+        BuildOption::new(),
+        Tunables::default(),
+        DefinedFuncIndex::new(2)
+    )
+    .is_err());
+}
+
+#[test]
+fn test_disassemble() {
+    use crate::executor::Module;
+    use cranelift_entity::EntityRef;
+    use cranelift_wasm::DefinedFuncIndex;
+
+    let wat = r#"
+        (module
+          (func (export "add") (param i32 i32) (result i32)
+            (i32.add (local.get 0) (local.get 1))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let plain = Module::compile(&wasm, BuildOption::new()).unwrap();
+    let metered = Module::compile(&wasm, BuildOption::new().gas_metering(true)).unwrap();
+    let plain = plain.disassemble(DefinedFuncIndex::new(0)).unwrap();
+    let metered = metered.disassemble(DefinedFuncIndex::new(0)).unwrap();
+    assert!(plain.contains("ret"));
+    assert!(!plain.contains("call"));
+    // the gas check is a call of a builtin
+    assert!(metered.contains("call"));
+
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+    assert!(module.disassemble(DefinedFuncIndex::new(1)).is_err());
+}
+
+#[test]
+fn test_opcode_weights() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::{operator_opcode, GasCostTable, Operator};
+    use std::sync::Arc;
+
+    // both loops run the same number of operators, the first one loads
+    let memory_loop = r#"
+        (module
+          (func (export "invoke") (result i32)
+            (local i32)
+            (block
+              (loop
+                (br_if 1 (i32.ge_u (local.get 0) (i32.const 100)))
+                (local.set 0 (i32.add (local.get 0) (i32.load (i32.const 0))))
+                (br 0)))
+            (local.get 0))
+          (memory (;0;) 1)
+          (data (i32.const 0) "\01"))"#;
+    let arithmetic_loop = r#"
+        (module
+          (func (export "invoke") (result i32)
+            (local i32)
+            (block
+              (loop
+                (br_if 1 (i32.ge_u (local.get 0) (i32.const 100)))
+                (local.set 0 (i32.add (local.get 0) (i32.eqz (i32.const 0))))
+                (br 0)))
+            (local.get 0))
+          (memory (;0;) 1))"#;
+
+    let gas_used = |wat: &str, table: GasCostTable| {
+        let chain = test_chain(1_000_000);
+        let wasm = wat::parse_str(wat).unwrap();
+        let build_option = BuildOption::new().gas_metering(true).gas_cost_table(table);
+        let module = Module::compile(&wasm, build_option).unwrap();
+        let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+        let receipt = instance.try_execute_full(chain, "invoke", ()).unwrap();
+        assert_eq!(receipt.result, Some(100));
+        receipt.gas_used
+    };
+
+    let table = GasCostTable::default();
+    assert_eq!(
+        gas_used(memory_loop, table.clone()),
+        gas_used(arithmetic_loop, table)
+    );
+
+    // the operators are weighed by their opcode, 0x28 for `i32.load`
+    assert_eq!(operator_opcode(&Operator::I32Add), Some(0x6A));
+    assert_eq!(operator_opcode(&Operator::I64TruncSatF64U), Some(0xFC07));
+    let mut table = GasCostTable::default();
+    table.opcode_weights.insert(0x28, 20);
+    assert_eq!(
+        gas_used(memory_loop, table.clone()),
+        gas_used(arithmetic_loop, table.clone()) + 100 * 19
+    );
+
+    // the weights are part of the serialized table
+    let bytes = bincode::serialize(&table).unwrap();
+    assert_eq!(bincode::deserialize::<GasCostTable>(&bytes).unwrap(), table);
+}
+
+#[test]
+fn test_signature_mismatch_hardening() {
+    use crate::executor::Module;
+    use crate::value::Value;
+    use ontio_wasmjit_environ::Tunables;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func (export "lane") (param v128) (result i32)
+            (i32x4.extract_lane 0 (local.get 0)))
+          (func (export "splat") (param i32) (result v128)
+            (i32x4.splat (local.get 0)))
+          (func (export "invoke") (param i32)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let mut tunables = Tunables::default();
+    tunables.features.simd = true;
+    let module = Module::compile_with_tunables(&wasm, BuildOption::new(), tunables).unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+
+    // a vector does not fit a raw argument slot
+    match instance.call("lane", vec![1]) {
+        Err(Error::SignatureMismatch { expected, found }) => {
+            assert_ne!(expected, found);
+            assert_eq!(found, "(i64)");
+        }
+        _ => panic!("expected a signature mismatch"),
+    }
+    assert!(instance.call("lane", &[Value::I32(1)][..]).is_err());
+    assert!(instance.call("splat", (1i32,)).is_err());
+
+    match instance.invoke(Box::new(ChainCtx::default())) {
+        Err(Error::SignatureMismatch { expected, found }) => {
+            assert_eq!(expected, "()");
+            assert_eq!(found, "(i32)");
+        }
+        _ => panic!("expected a signature mismatch"),
+    }
+}
+
+#[test]
+fn test_guard_pages() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::Tunables;
+    use ontio_wasmjit_runtime::TrapCode;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func (export "load") (param i32) (result i32)
+            (i32.load (local.get 0)))
+          (func (export "store") (param i32)
+            (i32.store offset=8 (local.get 0) (i32.const 1)))
+          (memory (;0;) 1 1)
+          (data (i32.const 0) "\2a"))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    for &guard_pages in &[true, false] {
+        let mut tunables = Tunables::default();
+        tunables.guard_pages = guard_pages;
+        let module = Module::compile_with_tunables(&wasm, BuildOption::new(), tunables).unwrap();
+        let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+
+        assert_eq!(instance.call("load", (0i32,)).unwrap(), Some(42));
+        assert_eq!(instance.call("load", (0xfffci32,)).unwrap(), Some(0));
+        for &(func, address) in &[("load", 0xfffdi32), ("load", -4), ("store", 0xfff8)] {
+            match instance.call(func, (address,)) {
+                Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::MemoryOutOfBounds),
+                _ => panic!(
+                    "expected an out of bounds trap, guard pages: {}",
+                    guard_pages
+                ),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_threads_rejected() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::CompileError;
+
+    let compile_error = |wat: &str| {
+        let wasm = wat::parse_str(wat).unwrap();
+        match Module::compile(&wasm, BuildOption::new()) {
+            Err(Error::Compile(CompileError::Wasm(err))) => err.to_string(),
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("threads are not supported"),
+        }
+    };
+
+    let err = compile_error(r#"(module (memory 1 1 shared))"#);
+    assert!(err.contains("threads proposal: shared memory"), "{}", err);
+
+    let err = compile_error(
+        r#"
+        (module
+          (memory 1 1)
+          (func (result i32)
+            (i32.atomic.load (i32.const 0))))"#,
+    );
+    assert!(
+        err.contains("threads proposal: atomic operator I32AtomicLoad"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_max_table_size() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::{CompileError, Tunables};
+
+    let compile = |wat: &str| {
+        let wasm = wat::parse_str(wat).unwrap();
+        let mut tunables = Tunables::default();
+        tunables.max_table_size = Some(100);
+        match Module::compile_with_tunables(&wasm, BuildOption::new(), tunables) {
+            Err(Error::Compile(CompileError::Wasm(err))) => err.to_string(),
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => String::from("ok"),
+        }
+    };
+
+    assert_eq!(compile("(module (table 100 funcref))"), "ok");
+    assert_eq!(compile("(module (table 10 100 funcref))"), "ok");
+    let err = compile("(module (table 101 funcref))");
+    assert!(
+        err.contains("module exceeds max_table_size: 101 > 100"),
+        "{}",
+        err
+    );
+    let err = compile("(module (table 1 1000000 funcref))");
+    assert!(
+        err.contains("module exceeds max_table_size: 1000000 > 100"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_table_grow() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::Tunables;
+    use ontio_wasmjit_runtime::TrapCode;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (type $t (func (result i32)))
+          (table 1 funcref)
+          (elem (i32.const 0) $one)
+          (func $one (result i32) (i32.const 1))
+          (func (export "call") (param i32) (result i32)
+            (call_indirect (type $t) (local.get 0))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let mut tunables = Tunables::default();
+    tunables.max_table_size = Some(4);
+    let module = Module::compile_with_tunables(&wasm, BuildOption::new(), tunables).unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    let trap_code = |instance: &mut crate::executor::Instance, index: i32| match instance
+        .call("call", (index,))
+    {
+        Err(Error::Trap(trap)) => trap.code,
+        _ => panic!("expected a trap"),
+    };
+
+    assert_eq!(instance.table_size(0), Some(1));
+    assert_eq!(trap_code(&mut instance, 1), TrapCode::TableOutOfBounds);
+
+    assert_eq!(instance.table_grow(0, 2), Some(1));
+    assert_eq!(instance.table_size(0), Some(3));
+    assert_eq!(instance.call("call", (0i32,)).unwrap(), Some(1));
+    // the new elements are null
+    assert_eq!(trap_code(&mut instance, 2), TrapCode::IndirectCallToNull);
+    assert_eq!(trap_code(&mut instance, 3), TrapCode::TableOutOfBounds);
+
+    // max_table_size caps the growth
+    assert_eq!(instance.table_grow(0, 2), None);
+    assert_eq!(instance.table_grow(0, 1), Some(3));
+    assert_eq!(instance.table_size(0), Some(4));
+    assert_eq!(instance.table_grow(1, 1), None);
+
+    let wat = r#"
+        (module
+          (table 1 funcref)
+          (func (result i32) (table.size 0)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    assert!(Module::compile(&wasm, BuildOption::new()).is_err());
+}
+
+#[test]
+fn test_globals() {
+    use crate::executor::Module;
+    use crate::value::Value;
+    use ontio_wasmjit_environ::{ExportKind, ExportType};
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (global $counter (export "counter") (mut i64) (i64.const 1))
+          (global $step (mut i64) (i64.const 2))
+          (global (export "version") i32 (i32.const 7))
+          (func (export "bump") (result i64)
+            (global.set $counter (i64.add (global.get $counter) (global.get $step)))
+            (global.get $counter)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+    let globals: Vec<_> = module
+        .exports()
+        .filter(|(_, kind, _)| *kind == ExportKind::Global)
+        .map(|(name, _, ty)| match ty {
+            ExportType::Global(global) => (name.to_string(), global.mutability),
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(
+        globals,
+        vec![
+            ("counter".to_string(), true),
+            ("version".to_string(), false)
+        ]
+    );
+
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    assert_eq!(instance.get_global("counter").unwrap(), Value::I64(1));
+    assert_eq!(instance.get_global(2).unwrap(), Value::I32(7));
+
+    // internal globals are reachable by index
+    instance.set_global(1, Value::I64(10)).unwrap();
+    instance.set_global("counter", Value::I64(5)).unwrap();
+    assert_eq!(instance.call("bump", Vec::new()).unwrap(), Some(15));
+    assert_eq!(instance.get_global(0).unwrap(), Value::I64(15));
+
+    assert!(instance.set_global("version", Value::I32(8)).is_err());
+    assert_eq!(instance.get_global("version").unwrap(), Value::I32(7));
+    match instance.set_global("counter", Value::I32(1)) {
+        Err(Error::SignatureMismatch { .. }) => {}
+        _ => panic!("expected a type mismatch"),
+    }
+    assert!(instance.get_global("missing").is_err());
+    assert!(instance.get_global(3).is_err());
+}
+
+#[test]
+fn test_read_write_memory() {
+    use crate::executor::Module;
+    use ontio_wasmjit_runtime::TrapCode;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (memory 1 2)
+          (data (i32.const 16) "\01\02\03\04")
+          (func (export "grow") (result i32) (memory.grow (i32.const 1)))
+          (func (export "sum") (result i32)
+            (i32.add (i32.load8_u (i32.const 65536)) (i32.load8_u (i32.const 131071)))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+
+    assert_eq!(instance.read_memory(16, 4).unwrap(), vec![1, 2, 3, 4]);
+    assert_eq!(instance.read_memory(65536, 0).unwrap(), Vec::<u8>::new());
+    assert_eq!(
+        instance.read_memory(65535, 2),
+        Err(TrapCode::MemoryOutOfBounds)
+    );
+    assert_eq!(
+        instance.read_memory(usize::max_value(), 2),
+        Err(TrapCode::MemoryOutOfBounds)
+    );
+
+    // an out of bounds write leaves the memory untouched
+    assert_eq!(
+        instance.write_memory(65534, &[9, 9, 9]),
+        Err(TrapCode::MemoryOutOfBounds)
+    );
+    assert_eq!(instance.read_memory(65534, 2).unwrap(), vec![0, 0]);
+
+    assert_eq!(instance.call("grow", Vec::new()).unwrap(), Some(1));
+    instance.write_memory(65536, &[5]).unwrap();
+    instance.write_memory(131071, &[6]).unwrap();
+    assert_eq!(instance.call("sum", Vec::new()).unwrap(), Some(11));
+    assert_eq!(instance.read_memory(65535, 2).unwrap(), vec![0, 5]);
+    assert_eq!(
+        instance.write_memory(131071, &[6, 7]),
+        Err(TrapCode::MemoryOutOfBounds)
+    );
+}
+
+#[test]
+fn test_memory_image() {
+    use crate::executor::Module;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (memory 1 3)
+          (data (i32.const 0) "\01\02\03\04")
+          (func (export "size") (result i32) (memory.size)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Arc::new(Module::compile(&wasm, BuildOption::new()).unwrap());
+
+    // the image overrides the overlapping data segment bytes
+    let mut instance = module
+        .clone()
+        .instantiate_with_memory(&mut ChainResolver, &[9, 9])
+        .unwrap();
+    assert_eq!(instance.read_memory(0, 4).unwrap(), vec![9, 9, 3, 4]);
+    assert_eq!(instance.call("size", Vec::new()).unwrap(), Some(1));
+
+    // the memory grows to fit the image
+    let mut image = vec![0u8; 65536 + 1];
+    image[65536] = 7;
+    let mut instance = module
+        .clone()
+        .instantiate_with_memory(&mut ChainResolver, &image)
+        .unwrap();
+    assert_eq!(instance.call("size", Vec::new()).unwrap(), Some(2));
+    assert_eq!(instance.read_memory(65536, 1).unwrap(), vec![7]);
+
+    let image = vec![0u8; 3 * 65536 + 1];
+    assert!(module
+        .instantiate_with_memory(&mut ChainResolver, &image)
+        .is_err());
+
+    let wasm = wat::parse_str("(module)").unwrap();
+    let module = Arc::new(Module::compile(&wasm, BuildOption::new()).unwrap());
+    assert!(module
+        .clone()
+        .instantiate_with_memory(&mut ChainResolver, &[])
+        .is_ok());
+    assert!(module
+        .instantiate_with_memory(&mut ChainResolver, &[1])
+        .is_err());
+}
+
+#[test]
+fn test_host_context() {
+    use crate::executor::Module;
+    use ontio_wasmjit_runtime::{VMContext, VMFunctionBody, VMFunctionImport};
+    use std::sync::Arc;
+
+    struct Counter(u32);
+
+    unsafe extern "C" fn bump(vmctx: *mut VMContext) -> u32 {
+        let instance = (&mut *vmctx).instance();
+        match instance.host_context::<Counter>() {
+            Some(counter) => {
+                counter.0 += 1;
+                counter.0
+            }
+            None => 0,
+        }
+    }
+
+    let wat = r#"
+        (module
+          (import "env" "bump" (func $bump (result i32)))
+          (func (export "bump_twice") (result i32)
+            (drop (call $bump))
+            (call $bump)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+    let mut resolver = |_: &str, _: &str| {
+        Some(VMFunctionImport {
+            body: bump as *const VMFunctionBody,
+            vmctx: std::ptr::null_mut(),
+        })
+    };
+    let mut instance = Arc::new(module).instantiate(&mut resolver).unwrap();
+
+    let mut counter = Counter(10);
+    let result = instance.call_with_context(&mut counter, "bump_twice", Vec::new());
+    assert_eq!(result.unwrap(), Some(12));
+    assert_eq!(counter.0, 12);
+
+    // the context is only reachable during the scoped call
+    assert_eq!(instance.call("bump_twice", Vec::new()).unwrap(), Some(0));
+
+    // a context of another type is not handed out
+    let mut other = 5u64;
+    let result = instance.call_with_context(&mut other, "bump_twice", Vec::new());
+    assert_eq!(result.unwrap(), Some(0));
+}
+
+#[test]
+fn test_linker() {
+    use crate::executor::Module;
+    use crate::resolver::{Linker, NullResolver};
+    use std::sync::Arc;
+
+    let compile = |wat: &str| {
+        let wasm = wat::parse_str(wat).unwrap();
+        Arc::new(Module::compile(&wasm, BuildOption::new()).unwrap())
+    };
+    let lib = compile(
+        r#"
+        (module
+          (global $calls (mut i32) (i32.const 0))
+          (func (export "add") (param i32 i32) (result i32)
+            (global.set $calls (i32.add (global.get $calls) (i32.const 1)))
+            (i32.add (local.get 0) (local.get 1)))
+          (func (export "calls") (result i32) (global.get $calls)))"#,
+    );
+    let mut linker = Linker::new();
+    let lib = linker.define_instance("lib", lib.instantiate(&mut NullResolver).unwrap());
+
+    let contract = compile(
+        r#"
+        (module
+          (type $binop (func (param i32 i32) (result i32)))
+          (import "lib" "add" (func $add (type $binop)))
+          (table 1 funcref)
+          (elem (i32.const 0) $add)
+          (func (export "direct") (result i32) (call $add (i32.const 2) (i32.const 3)))
+          (func (export "indirect") (result i32)
+            (call_indirect (type $binop) (i32.const 4) (i32.const 5) (i32.const 0))))"#,
+    );
+    let mut instance = linker.instantiate(contract, &mut NullResolver).unwrap();
+    assert_eq!(instance.call("direct", Vec::new()).unwrap(), Some(5));
+    assert_eq!(instance.call("indirect", Vec::new()).unwrap(), Some(9));
+    // the calls ran against the globals of the library instance
+    assert_eq!(lib.lock().call("calls", Vec::new()).unwrap(), Some(2));
+    // a call is refused while the library is in use elsewhere
+    {
+        let _guard = lib.lock();
+        match instance.call("direct", Vec::new()) {
+            Err(Error::Link(msg)) => assert!(msg.contains("in use"), "{}", msg),
+            _ => panic!("expected the call to be refused"),
+        }
+    }
+    assert_eq!(instance.call("direct", Vec::new()).unwrap(), Some(5));
+
+    let mismatch = compile(r#"(module (import "lib" "add" (func (param i64) (result i32))))"#);
+    match linker.instantiate(mismatch, &mut NullResolver) {
+        Err(Error::Link(msg)) => assert!(msg.contains("lib.add"), "{}", msg),
+        _ => panic!("expected a link error"),
+    }
+    let missing = compile(r#"(module (import "lib" "sub" (func)))"#);
+    match linker.instantiate(missing, &mut NullResolver) {
+        Err(Error::Link(msg)) => assert!(msg.contains("lib.sub"), "{}", msg),
+        _ => panic!("expected a link error"),
+    }
+    let unknown = compile(r#"(module (import "env" "missing" (func)))"#);
+    assert!(linker
+        .instantiate(unknown.clone(), &mut NullResolver)
+        .is_err());
+    match unknown.instantiate(&mut NullResolver) {
+        Err(Error::Link(msg)) => assert!(msg.contains("env.missing"), "{}", msg),
+        _ => panic!("expected a link error"),
+    }
+}
+
+#[test]
+fn test_linker_metering() {
+    use crate::executor::Module;
+    use crate::resolver::{Linker, NullResolver};
+    use ontio_wasmjit_runtime::TrapCode;
+    use std::sync::Arc;
+
+    let compile = |wat: &str| {
+        let wasm = wat::parse_str(wat).unwrap();
+        let build_option = BuildOption::new().gas_metering(true);
+        Arc::new(Module::compile(&wasm, build_option).unwrap())
+    };
+    let lib = compile(
+        r#"
+        (module
+          (func $div (export "div") (param i32) (result i32)
+            (i32.div_u (i32.const 1) (local.get 0)))
+          (func $spin (export "spin")
+            (loop (br 0))))"#,
+    );
+    let mut linker = Linker::new();
+    let lib = linker.define_instance("lib", lib.instantiate(&mut NullResolver).unwrap());
+    lib.lock().set_gas(1_000_000);
+
+    let contract = compile(
+        r#"
+        (module
+          (import "lib" "div" (func $div (param i32) (result i32)))
+          (import "lib" "spin" (func $spin))
+          (func (export "div") (param i32) (result i32) (call $div (local.get 0)))
+          (func (export "spin") (call $spin)))"#,
+    );
+    let mut instance = linker.instantiate(contract, &mut NullResolver).unwrap();
+
+    // the library code is charged to the caller
+    instance.set_gas(10_000);
+    assert_eq!(instance.call("div", (1i32,)).unwrap(), Some(1));
+    assert!(instance.gas_remaining() < 10_000);
+    assert_eq!(lib.lock().gas_remaining(), 1_000_000);
+
+    // a trap in the library is reported against the library code, and the
+    // call depth it used is given back to the caller
+    for _ in 0..1000 {
+        match instance.call("div", (0i32,)) {
+            Err(Error::Trap(trap)) => {
+                assert_eq!(trap.code, TrapCode::IntegerDivisionByZero);
+                assert_eq!(trap.frame.unwrap().func_name, Some(String::from("div")));
+            }
+            _ => panic!("expected a division by zero trap"),
+        }
+    }
+    assert_eq!(instance.call("div", (1i32,)).unwrap(), Some(1));
+
+    // the library runs out of the gas of the caller
+    instance.set_gas(1000);
+    match instance.call("spin", Vec::new()) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::OutOfGas),
+        _ => panic!("expected an out of gas trap"),
+    }
+    assert_eq!(instance.gas_remaining(), 0);
+    assert_eq!(lib.lock().gas_remaining(), 1_000_000);
+}
+
+#[test]
+fn test_read_and_compile() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::Tunables;
+    use std::io::{self, Read};
+    use std::sync::Arc;
+
+    /// Hands out the bytes a few at a time, like a network stream.
+    struct Chunked<'a>(&'a [u8]);
+
+    impl Read for Chunked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(3);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    let wat = r#"
+        (module
+          (func $double (param i32) (result i32) (i32.mul (local.get 0) (i32.const 2)))
+          (func (export "calc") (param i32) (result i32)
+            (call $double (i32.add (local.get 0) (i32.const 1)))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let reader = Chunked(&wasm);
+    let read = Module::read_and_compile(reader, BuildOption::new(), Tunables::default()).unwrap();
+    let buffered = Module::compile(&wasm, BuildOption::new()).unwrap();
+
+    let names = |module: &Module| {
+        let names: Vec<_> = module
+            .exports()
+            .map(|(name, _, _)| name.to_string())
+            .collect();
+        names
+    };
+    assert_eq!(names(&read), names(&buffered));
+    let mut instance = Arc::new(read).instantiate(&mut ChainResolver).unwrap();
+    assert_eq!(instance.call("calc", (4,)).unwrap(), Some(10));
+
+    let truncated = &wasm[..wasm.len() - 1];
+    let result = Module::read_and_compile(truncated, BuildOption::new(), Tunables::default());
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_reentrancy_guard() {
+    use crate::executor::Module;
+    use ontio_wasmjit_runtime::{
+        wasmjit_call, TrapCode, VMContext, VMFunctionBody, VMFunctionImport,
+    };
+    use std::sync::Arc;
+
+    unsafe extern "C" fn reenter(vmctx: *mut VMContext) -> i32 {
+        let instance = (&mut *vmctx).instance();
+        let inner = instance.lookup("inner").unwrap();
+        wasmjit_call(inner.vmctx, inner.address).is_ok() as i32
+    }
+
+    let wat = r#"
+        (module
+          (import "env" "reenter" (func $reenter (result i32)))
+          (func (export "inner"))
+          (func (export "outer") (result i32) (call $reenter))
+          (func (export "boom") (unreachable)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+    let mut resolver = |_: &str, _: &str| {
+        Some(VMFunctionImport {
+            body: reenter as *const VMFunctionBody,
+            vmctx: std::ptr::null_mut(),
+        })
+    };
+    let mut instance = Arc::new(module).instantiate(&mut resolver).unwrap();
+
+    // reentrancy is allowed by default
+    assert_eq!(instance.call("outer", Vec::new()).unwrap(), Some(1));
+
+    instance.set_reentrancy_guard(true);
+    match instance.call("outer", Vec::new()) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::Reentrancy),
+        _ => panic!("expected a reentrancy trap"),
+    }
+    // the guard is cleared once the trapping call returns
+    assert!(instance.call("inner", Vec::new()).is_ok());
+    assert!(instance.call("boom", Vec::new()).is_err());
+    assert!(instance.call("inner", Vec::new()).is_ok());
+
+    instance.set_reentrancy_guard(false);
+    assert_eq!(instance.call("outer", Vec::new()).unwrap(), Some(1));
+}
+
+#[test]
+fn test_loop_back_edge_gas() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::GasCostTable;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func (export "count") (param i32) (result i32)
+            (local i32)
+            (loop
+              (local.set 1 (i32.add (local.get 1) (i32.const 1)))
+              (br_if 0 (i32.lt_u (local.get 1) (local.get 0))))
+            (local.get 1)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let gas_used = |table: &GasCostTable, iterations: i32| {
+        let chain = test_chain(1_000_000);
+        let build_option = BuildOption::new()
+            .gas_metering(true)
+            .gas_cost_table(table.clone());
+        let module = Module::compile(&wasm, build_option).unwrap();
+        let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+        let receipt = instance
+            .try_execute_full(chain, "count", (iterations,))
+            .unwrap();
+        assert_eq!(receipt.result, Some(i64::from(iterations)));
+        receipt.gas_used
+    };
+
+    // the eight operators of the body, up to the `br_if`, are charged by the
+    // `br_if` on every iteration
+    let default = GasCostTable::default();
+    let per_iteration = gas_used(&default, 2) - gas_used(&default, 1);
+    assert_eq!(per_iteration, 8);
+    for &n in [10, 100, 1000].iter() {
+        let expected = gas_used(&default, 1) + per_iteration * (n as u64 - 1);
+        assert_eq!(gas_used(&default, n), expected);
+    }
+
+    // only the `br_if` costs something, the loop itself is charged once
+    let control = GasCostTable {
+        arithmetic: 0,
+        ..GasCostTable::default()
+    };
+    assert_eq!(gas_used(&control, 100) - gas_used(&control, 1), 99);
+}
+
+#[test]
+fn test_free_loop_out_of_gas() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::GasCostTable;
+    use ontio_wasmjit_runtime::TrapCode;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func (export "spin")
+            (loop (br 0))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let free = GasCostTable {
+        arithmetic: 0,
+        memory: 0,
+        call: 0,
+        control: 0,
+        ..GasCostTable::default()
+    };
+    // the opcodes of `loop` and `br`
+    let mut weights = GasCostTable::default();
+    weights.opcode_weights.insert(0x03, 0);
+    weights.opcode_weights.insert(0x0C, 0);
+
+    // each iteration is charged at least one, so the loop still runs out of gas
+    for table in [free, weights].iter() {
+        let chain = test_chain(1000);
+        let build_option = BuildOption::new()
+            .gas_metering(true)
+            .gas_cost_table(table.clone());
+        let module = Module::compile(&wasm, build_option).unwrap();
+        let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+        match instance.execute(chain, "spin", ()) {
+            Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::OutOfGas),
+            _ => panic!("expected an out of gas trap"),
+        }
+    }
+}
+
+#[test]
+fn test_native_stack_bytes() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::Tunables;
+    use ontio_wasmjit_runtime::TrapCode;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func $rec (export "rec") (param i32) (result i32)
+            (if (result i32) (i32.eqz (local.get 0))
+              (then (i32.const 0))
+              (else (i32.add (i32.const 1) (call $rec (i32.sub (local.get 0) (i32.const 1))))))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let instantiate = |stack_bytes| {
+        let mut tunables = Tunables::default();
+        tunables.native_stack_bytes = Some(stack_bytes);
+        let module = Module::compile_with_tunables(&wasm, BuildOption::new(), tunables).unwrap();
+        Arc::new(module).instantiate(&mut ChainResolver).unwrap()
+    };
+
+    let mut instance = instantiate(64 << 20);
+    assert_eq!(instance.call("rec", (100_000,)).unwrap(), Some(100_000));
+
+    let mut instance = instantiate(128 << 10);
+    match instance.call("rec", (100_000,)) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::StackOverflow),
+        _ => panic!("expected a stack overflow"),
+    }
+    // the instance is still usable after the overflow
+    assert_eq!(instance.call("rec", (10,)).unwrap(), Some(10));
+}
+
+#[test]
+fn test_native_stack_worker() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::Tunables;
+    use ontio_wasmjit_runtime::{VMContext, VMFunctionBody, VMFunctionImport};
+    use std::sync::Arc;
+    use std::thread::{self, ThreadId};
+
+    unsafe extern "C" fn record(vmctx: *mut VMContext) {
+        let instance = (&mut *vmctx).instance();
+        if let Some(thread) = instance.host_context::<Option<ThreadId>>() {
+            *thread = Some(thread::current().id());
+        }
+    }
+
+    let wat = r#"
+        (module
+          (import "env" "record" (func $record))
+          (func (export "record") (call $record)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let mut tunables = Tunables::default();
+    tunables.native_stack_bytes = Some(1 << 20);
+    let module = Module::compile_with_tunables(&wasm, BuildOption::new(), tunables).unwrap();
+    let mut resolver = |_: &str, _: &str| {
+        Some(VMFunctionImport {
+            body: record as *const VMFunctionBody,
+            vmctx: std::ptr::null_mut(),
+        })
+    };
+    let module = Arc::new(module);
+    let mut instance = module.clone().instantiate(&mut resolver).unwrap();
+    let mut other = module.instantiate(&mut resolver).unwrap();
+
+    let mut first = None;
+    instance
+        .call_with_context(&mut first, "record", ())
+        .unwrap();
+    let mut second = None;
+    other.call_with_context(&mut second, "record", ()).unwrap();
+    // the calls ran on the same worker, not on the calling thread
+    assert!(first.is_some());
+    assert_eq!(first, second);
+    assert_ne!(first, Some(thread::current().id()));
+}
+
+#[test]
+fn test_memory_access_trap() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::Tunables;
+    use ontio_wasmjit_runtime::{MemoryAccess, TrapCode};
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func (export "load") (param i32) (result i32)
+            (i32.load (local.get 0)))
+          (func (export "store") (param i32)
+            (i64.store16 offset=8 (local.get 0) (i64.const 1)))
+          (memory (;0;) 1 1))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    for &guard_pages in &[true, false] {
+        let mut tunables = Tunables::default();
+        tunables.guard_pages = guard_pages;
+        let module = Module::compile_with_tunables(&wasm, BuildOption::new(), tunables).unwrap();
+        let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+
+        let cases = [("load", 65636, 65636, 4), ("store", 65530, 65538, 2)];
+        for &(func, address, fault, size) in &cases {
+            let trap = match instance.call(func, (address,)) {
+                Err(Error::Trap(trap)) => trap,
+                _ => panic!(
+                    "expected an out of bounds trap, guard pages: {}",
+                    guard_pages
+                ),
+            };
+            assert_eq!(trap.code, TrapCode::MemoryOutOfBounds);
+            // without guard pages the explicit bounds check has no faulting address
+            let offset = if guard_pages { Some(fault) } else { None };
+            assert_eq!(trap.memory_access, Some(MemoryAccess { offset, size }));
+        }
+    }
+}
+
+#[test]
+fn test_typed_func() {
+    use crate::executor::Module;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (global $count (mut i32) (i32.const 0))
+          (func (export "add") (param i64 i64) (result i64)
+            (i64.add (local.get 0) (local.get 1)))
+          (func (export "half") (param f32) (result f32)
+            (f32.div (local.get 0) (f32.const 2)))
+          (func (export "incr") (result i32)
+            (global.set $count (i32.add (global.get $count) (i32.const 1)))
+            (global.get $count)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+
+    let mut add = instance.typed_func::<(i64, i64), i64>("add").unwrap();
+    for i in 0..1000 {
+        assert_eq!(add.call((i, -1)).unwrap(), i - 1);
+    }
+    let mut half = instance.typed_func::<(f32,), f32>("half").unwrap();
+    assert_eq!(half.call((3.0,)).unwrap(), 1.5);
+    let mut incr = instance.typed_func::<(), u32>("incr").unwrap();
+    assert_eq!(incr.call(()).unwrap(), 1);
+    assert_eq!(incr.call(()).unwrap(), 2);
+    // the state is shared with the untyped calls
+    assert_eq!(incr.instance().call("incr", ()).unwrap(), Some(3));
+
+    for err in vec![
+        instance.typed_func::<(i32, i64), i64>("add").err(),
+        instance.typed_func::<(i64, i64), i32>("add").err(),
+        instance.typed_func::<(i64, i64), ()>("add").err(),
+    ] {
+        match err {
+            Some(Error::SignatureMismatch { .. }) => {}
+            _ => panic!("expected a signature mismatch"),
+        }
+    }
+    assert!(instance.typed_func::<(), ()>("missing").is_err());
+}
+
+#[test]
+fn test_timings() {
+    use crate::executor::{execute_timed, Module};
+    use ontio_wasmjit_environ::Tunables;
+    use std::time::Duration;
+
+    let wat = r#"
+        (module
+          (func (export "sum") (param i32) (result i32) (local i32)
+            (block
+              (loop
+                (br_if 1 (i32.eqz (local.get 0)))
+                (local.set 1 (i32.add (local.get 1) (local.get 0)))
+                (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+                (br 0)))
+            (local.get 1)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let (result, timings) = execute_timed(
+        &wasm,
+        BuildOption::new(),
+        Tunables::default(),
+        ChainCtx::default(),
+        "sum",
+        (10_000i32,),
+    )
+    .unwrap();
+    assert_eq!(result, Some(50_005_000));
+    assert!(timings.parse > Duration::from_secs(0));
+    assert!(timings.codegen > Duration::from_secs(0));
+    assert!(timings.instantiate > Duration::from_secs(0));
+    assert!(timings.execute > Duration::from_secs(0));
+
+    let (_module, timings) =
+        Module::compile_timed(&wasm, BuildOption::new(), Tunables::default()).unwrap();
+    assert_eq!(timings.instantiate, Duration::from_secs(0));
+    assert_eq!(timings.execute, Duration::from_secs(0));
+}
+
+#[test]
+fn test_shared_signature_registry() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::{SignatureRegistry, Tunables};
+    use std::sync::Arc;
+    use std::thread;
+
+    let wats = [
+        r#"(module
+             (type (func (param i32) (result i32)))
+             (type (func))
+             (func (export "id") (type 0) (local.get 0)))"#,
+        r#"(module
+             (type (func (param i64)))
+             (type (func (param i32) (result i32)))
+             (func (export "inc") (type 1) (i32.add (local.get 0) (i32.const 1))))"#,
+    ];
+    let registry = Arc::new(SignatureRegistry::new());
+    assert!(registry.is_empty());
+    let threads: Vec<_> = wats
+        .iter()
+        .map(|wat| {
+            let wasm = wat::parse_str(wat).unwrap();
+            let registry = registry.clone();
+            thread::spawn(move || {
+                Module::compile_with_registry(
+                    &wasm,
+                    BuildOption::new(),
+                    Tunables::default(),
+                    &registry,
+                )
+                .unwrap()
+            })
+        })
+        .collect();
+    let modules: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+
+    // (i32) -> i32 collapses to a single id across the two modules
+    assert_eq!(registry.len(), 3);
+    let ids = |module: &Module| {
+        module
+            .info()
+            .signature_ids
+            .values()
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let (first, second) = (ids(&modules[0]), ids(&modules[1]));
+    assert_eq!(first[0], second[1]);
+    assert_ne!(first[1], second[0]);
+
+    // without a registry the signature ids are left to the instances
+    let wasm = wat::parse_str(wats[0]).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+    assert!(module.info().signature_ids.is_empty());
+
+    let mut modules = modules.into_iter().map(Arc::new);
+    let mut id = modules
+        .next()
+        .unwrap()
+        .instantiate(&mut ChainResolver)
+        .unwrap();
+    let mut inc = modules
+        .next()
+        .unwrap()
+        .instantiate(&mut ChainResolver)
+        .unwrap();
+    assert_eq!(id.call("id", (7,)).unwrap(), Some(7));
+    assert_eq!(inc.call("inc", (7,)).unwrap(), Some(8));
+}
+
+#[test]
+fn test_unreachable_trap() {
+    use crate::executor::Module;
+    use ontio_wasmjit_runtime::TrapCode;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func $panic (param i32)
+            (if (local.get 0) (then (unreachable))))
+          (func (export "panic") (param i32) (result i32)
+            (call $panic (local.get 0))
+            (i32.const 1))
+          (func (export "div") (param i32) (result i32)
+            (i32.div_u (i32.const 1) (local.get 0)))
+          (func (export "load") (param i32) (result i32)
+            (i32.load (local.get 0)))
+          (memory 1 1))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+
+    assert_eq!(instance.call("panic", (0,)).unwrap(), Some(1));
+    let cases = [
+        ("panic", 1, TrapCode::Unreachable),
+        ("div", 0, TrapCode::IntegerDivisionByZero),
+        ("load", 65536, TrapCode::MemoryOutOfBounds),
+    ];
+    for &(func, arg, code) in &cases {
+        match instance.call(func, (arg,)) {
+            Err(Error::Trap(trap)) => assert_eq!(trap.code, code, "{}", func),
+            _ => panic!("expected a trap of {}", func),
+        }
+    }
+    assert_eq!(TrapCode::Unreachable.to_consensus_code(), 10);
+}
+
+#[test]
+fn test_signed_division_traps() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::Tunables;
+    use ontio_wasmjit_runtime::TrapCode;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func (export "div32") (param i32 i32) (result i32)
+            (i32.div_s (local.get 0) (local.get 1)))
+          (func (export "div64") (param i64 i64) (result i64)
+            (i64.div_s (local.get 0) (local.get 1)))
+          (func (export "rem32") (param i32 i32) (result i32)
+            (i32.rem_s (local.get 0) (local.get 1))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    for &avoid_div_traps in &[false, true] {
+        let mut tunables = Tunables::default();
+        tunables.avoid_div_traps = avoid_div_traps;
+        let module = Module::compile_with_tunables(&wasm, BuildOption::new(), tunables).unwrap();
+        let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+        let trap_code = |result: Result<Option<i64>, Error>| match result {
+            Err(Error::Trap(trap)) => trap.code,
+            other => panic!(
+                "expected a trap, found {:?}",
+                other.map_err(|e| e.to_string())
+            ),
+        };
+
+        let (min32, min64) = (i32::min_value(), i64::min_value());
+        assert_eq!(instance.call("div32", (-7, 2)).unwrap(), Some(-3));
+        assert_eq!(
+            instance.call("div32", (min32, 1)).unwrap(),
+            Some(i64::from(min32))
+        );
+        assert_eq!(
+            trap_code(instance.call("div32", (1, 0))),
+            TrapCode::IntegerDivisionByZero
+        );
+        assert_eq!(
+            trap_code(instance.call("div32", (min32, -1))),
+            TrapCode::IntegerOverflow
+        );
+
+        assert_eq!(
+            instance.call("div64", (min64, 2i64)).unwrap(),
+            Some(min64 / 2)
+        );
+        let zero = TrapCode::IntegerDivisionByZero;
+        assert_eq!(trap_code(instance.call("div64", (1i64, 0i64))), zero);
+        assert_eq!(
+            trap_code(instance.call("div64", (min64, -1i64))),
+            TrapCode::IntegerOverflow
+        );
+
+        // the remainder of `MIN / -1` is defined
+        assert_eq!(instance.call("rem32", (min32, -1)).unwrap(), Some(0));
+    }
+}
+
+#[test]
+fn test_data_bytes() {
+    use crate::executor::{parse_only, Module};
+    use ontio_wasmjit_environ::{CompileError, Tunables};
+
+    // the two first segments overlap on 2 bytes
+    let wat = r#"
+        (module
+          (memory 1)
+          (data (i32.const 0) "abcd")
+          (data (i32.const 2) "cdef")
+          (data (i32.const 16) "xyz"))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let translation = parse_only(&wasm, Tunables::default()).unwrap();
+    assert_eq!(translation.data_bytes(), 11);
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+    assert_eq!(module.data_bytes(), 11);
+
+    let mut tunables = Tunables::default();
+    tunables.max_data_bytes = Some(11);
+    assert!(parse_only(&wasm, tunables.clone()).is_ok());
+    tunables.max_data_bytes = Some(10);
+    // the effective footprint is 9 bytes, the limit is on the declared bytes
+    match Module::compile_with_tunables(&wasm, BuildOption::new(), tunables) {
+        Err(Error::Compile(CompileError::Wasm(err))) => {
+            assert!(err.to_string().contains("max_data_bytes: 11 > 10"))
+        }
+        _ => panic!("expected the data limit to be exceeded"),
+    }
+}
+
+#[test]
+fn test_module_imports() {
+    use crate::executor::Module;
+    use crate::resolver::Resolver;
+    use cranelift_codegen::ir::types;
+
+    let wat = r#"
+        (module
+          (import "env" "add" (func (param i32 i64) (result i64)))
+          (import "host" "log" (func (param i32)))
+          (func (export "run") (result i64)
+            (call 1 (i32.const 0))
+            (call 0 (i32.const 1) (i64.const 2))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+
+    let imports: Vec<_> = module
+        .imports()
+        .map(|(module, field, signature)| {
+            let params: Vec<_> = signature.params[1..].iter().map(|p| p.value_type).collect();
+            let returns: Vec<_> = signature.returns.iter().map(|p| p.value_type).collect();
+            (module, field, params, returns)
+        })
+        .collect();
+    assert_eq!(
+        imports,
+        vec![
+            ("env", "add", vec![types::I32, types::I64], vec![types::I64]),
+            ("host", "log", vec![types::I32], vec![]),
+        ]
+    );
+
+    // the imports a resolver can not satisfy are known before instantiation
+    let missing: Vec<_> = module
+        .imports()
+        .filter(|&(module, field, _)| ChainResolver.resolve(module, field).is_none())
+        .map(|(module, field, _)| format!("{}.{}", module, field))
+        .collect();
+    assert_eq!(missing, vec!["env.add", "host.log"]);
+}
+
+#[test]
+fn test_features() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::{CompileError, Features, Tunables};
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func (export "sext") (param i32) (result i32)
+            (i32.extend8_s (local.get 0))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    assert_eq!(instance.call("sext", (0xff,)).unwrap(), Some(-1));
+
+    let mut tunables = Tunables::default();
+    tunables.features = Features {
+        sign_extension: false,
+        ..Features::default()
+    };
+    match Module::compile_with_tunables(&wasm, BuildOption::new(), tunables) {
+        Err(Error::Compile(CompileError::Wasm(err))) => assert!(
+            err.to_string()
+                .contains("operator I32Extend8S, feature `sign_extension` is disabled"),
+            "{}",
+            err
+        ),
+        _ => panic!("expected sign-extension to be rejected"),
+    }
+
+    let wat = r#"
+        (module
+          (memory 1)
+          (func (memory.fill (i32.const 0) (i32.const 0) (i32.const 1))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    match Module::compile(&wasm, BuildOption::new()) {
+        Err(Error::Compile(CompileError::Wasm(err))) => {
+            assert!(
+                err.to_string()
+                    .contains("feature `bulk_memory` is disabled"),
+                "{}",
+                err
+            )
+        }
+        _ => panic!("expected bulk memory to be rejected"),
+    }
+}
+
+#[test]
+fn test_memory_grow_gas_per_page() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::{GasCostTable, Tunables};
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func (export "grow") (param i32) (result i32)
+            (memory.grow (local.get 0)))
+          (memory (;0;) 1 8))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let build_option = BuildOption::new()
+        .gas_metering(true)
+        .gas_cost_table(GasCostTable {
+            grow: 1000,
+            ..GasCostTable::default()
+        });
+
+    // the static memory only commits pages, the dynamic one reallocates:
+    // the charge is the same whatever the allocation does
+    for &guard_pages in &[true, false] {
+        let mut tunables = Tunables::default();
+        tunables.guard_pages = guard_pages;
+        let module = Module::compile_with_tunables(&wasm, build_option.clone(), tunables).unwrap();
+        let module = Arc::new(module);
+        let gas_used = |pages: i32| {
+            let mut instance = module.clone().instantiate(&mut ChainResolver).unwrap();
+            let chain = test_chain(100_000);
+            let receipt = instance.try_execute_full(chain, "grow", (pages,)).unwrap();
+            (receipt.result, receipt.gas_used)
+        };
+
+        let (result, base) = gas_used(0);
+        assert_eq!(result, Some(1));
+        for pages in 1..=7 {
+            assert_eq!(gas_used(pages), (Some(1), base + 1000 * pages as u64));
+        }
+        // beyond the declared maximum nothing is charged for the pages
+        assert_eq!(gas_used(8), (Some(-1), base));
+    }
+}
+
+#[test]
+fn test_v128_results() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::Tunables;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func (export "splat") (param i32) (result v128)
+            (i32x4.splat (local.get 0)))
+          (func (export "add") (param i64 i64) (result i64)
+            (i64.add (local.get 0) (local.get 1))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let mut tunables = Tunables::default();
+    tunables.features.simd = true;
+    let module = Module::compile_with_tunables(&wasm, BuildOption::new(), tunables).unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+
+    let mut expected = [0u8; 16];
+    for lane in expected.chunks_mut(4) {
+        lane.copy_from_slice(&0x0102_0304u32.to_le_bytes());
+    }
+    let bytes: [u8; 16] = instance.call_typed("splat", (0x0102_0304i32,)).unwrap();
+    assert_eq!(bytes, expected);
+    let value: u128 = instance.call_typed("splat", (0x0102_0304i32,)).unwrap();
+    assert_eq!(value, u128::from_le_bytes(expected));
+
+    let mut splat = instance.typed_func::<(i32,), [u8; 16]>("splat").unwrap();
+    assert_eq!(splat.call((0x0102_0304,)).unwrap(), expected);
+
+    // scalar results keep their slot layout
+    let sum: i64 = instance.call_typed("add", (1i64, 2i64)).unwrap();
+    assert_eq!(sum, 3);
+    assert!(instance.call_typed::<_, i32>("splat", (1i32,)).is_err());
+    // the untyped call can not represent a vector
+    assert!(instance.call("splat", (1i32,)).is_err());
+}
+
+#[test]
+fn test_execute_any() {
+    let wat = r#"
+        (module
+          (func (export "invoke") (param i64 i64) (result i64)
+            (i64.mul (local.get 0) (local.get 1))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let from_text = execute_any(
+        wat.as_bytes(),
+        test_chain(u64::max_value()),
+        "invoke",
+        vec![6, 7],
+    )
+    .unwrap();
+    let from_binary =
+        execute_any(&wasm, test_chain(u64::max_value()), "invoke", vec![6, 7]).unwrap();
+    assert_eq!(from_text, Some(42));
+    assert_eq!(from_text, from_binary);
+    assert_eq!(
+        execute(wat, test_chain(u64::max_value()), "invoke", vec![6, 7]).unwrap(),
+        from_text
+    );
+
+    match execute_any(
+        b"(module (func",
+        test_chain(u64::max_value()),
+        "invoke",
+        Vec::new(),
+    ) {
+        Err(Error::Parse(ParseError::Text(_))) => {}
+        other => panic!("expected invalid WAT text, got {:?}", other),
+    }
+    let truncated = &wasm[..wasm.len() - 1];
+    match execute_any(
+        truncated,
+        test_chain(u64::max_value()),
+        "invoke",
+        Vec::new(),
+    ) {
+        Err(Error::Parse(ParseError::Binary(_))) => {}
+        other => panic!("expected invalid binary module, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_execute_bytes() {
+    let wat = r#"
+        (module
+          (memory 1)
+          (data (i32.const 8) "\2a")
+          (func (export "invoke") (param i32) (result i32)
+            (i32.add (i32.load8_u (i32.const 8)) (local.get 0))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let from_bytes = execute_bytes(&wasm, test_chain(u64::max_value()), "invoke", vec![1]).unwrap();
+    assert_eq!(from_bytes, Some(43));
+    assert_eq!(
+        execute(wat, test_chain(u64::max_value()), "invoke", vec![1]).unwrap(),
+        from_bytes
+    );
+
+    // validation errors of the binary are returned, not panicked on
+    let invalid = wat::parse_str("(module (func (result i32) (i64.const 0)))").unwrap();
+    match execute_bytes(&invalid, test_chain(u64::max_value()), "invoke", Vec::new()) {
+        Err(Error::Compile(_)) => {}
+        other => panic!("expected a compile error, got {:?}", other),
+    }
+    assert!(execute_bytes(
+        b"(module)",
+        test_chain(u64::max_value()),
+        "invoke",
+        Vec::new()
+    )
+    .is_err());
+}
+
+#[test]
+fn test_owned_translation() {
+    use crate::executor::{parse_only, Module};
+    use ontio_wasmjit_environ::Tunables;
+    use std::sync::Arc;
+
+    let wasm = wat::parse_str(
+        r#"
+        (module
+          (memory 1)
+          (data (i32.const 0) "\07")
+          (func (export "invoke") (param i32) (result i32)
+            (i32.store8 (i32.const 0)
+              (i32.add (i32.load8_u (i32.const 0)) (local.get 0)))
+            (i32.load8_u (i32.const 0))))"#,
+    )
+    .unwrap();
+    let translation = parse_only(&wasm, Tunables::default()).unwrap().into_owned();
+    drop(wasm);
+
+    let first = Module::compile_translation(&translation, BuildOption::new()).unwrap();
+    let second = Module::compile_translation(&translation.clone(), BuildOption::new()).unwrap();
+    assert_eq!(first.data_bytes(), 1);
+
+    let first = Arc::new(first);
+    let mut instance = first.clone().instantiate(&mut ChainResolver).unwrap();
+    assert_eq!(instance.call("invoke", (1,)).unwrap(), Some(8));
+    assert_eq!(instance.call("invoke", (1,)).unwrap(), Some(9));
+    // every instance starts from the data initializers
+    let mut instance = first.instantiate(&mut ChainResolver).unwrap();
+    assert_eq!(instance.call("invoke", (2,)).unwrap(), Some(9));
+    let mut instance = Arc::new(second).instantiate(&mut ChainResolver).unwrap();
+    assert_eq!(instance.call("invoke", (3,)).unwrap(), Some(10));
+}
+
+#[test]
+fn test_defined_export() {
+    use crate::executor::Module;
+    use cranelift_entity::EntityRef;
+    use cranelift_wasm::DefinedFuncIndex;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (import "env" "ret" (func $ret (param i32 i32)))
+          (func $double (param i32) (result i32)
+            (i32.mul (local.get 0) (i32.const 2)))
+          (func $square (param i32) (result i32)
+            (i32.mul (local.get 0) (local.get 0)))
+          (export "square" (func $square))
+          (export "double" (func $double))
+          (export "ret" (func $ret)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+
+    let square = module.defined_export("square").unwrap();
+    let double = module.defined_export("double").unwrap();
+    assert_eq!(square, DefinedFuncIndex::new(1));
+    assert_eq!(double, DefinedFuncIndex::new(0));
+    match module.defined_export("ret") {
+        Err(Error::Internal(msg)) => assert!(msg.contains("env.ret"), "{}", msg),
+        _ => panic!("expected an imported function error"),
+    }
+    assert!(module.defined_export("missing").is_err());
+
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    assert_eq!(instance.call_defined(square, (7,)).unwrap(), Some(49));
+    assert_eq!(instance.call_defined(double, (7,)).unwrap(), Some(14));
+    assert!(instance
+        .call_defined(DefinedFuncIndex::new(2), (7,))
+        .is_err());
+}
+
+#[test]
+fn test_unsupported_operator_location() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::CompileError;
+
+    let wat = r#"
+        (module
+          (func (drop (i32.const 0)))
+          (func (param i32) (result v128)
+            (nop)
+            (i32x4.splat (local.get 0))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    match Module::compile(&wasm, BuildOption::new()) {
+        Err(Error::Compile(CompileError::Wasm(err))) => {
+            let err = err.to_string();
+            // the body starts with the empty local declarations, then `nop`
+            // and `local.get 0`
+            assert!(err.contains("operator I32x4Splat"), "{}", err);
+            assert!(
+                err.contains("in defined function 1 at offset 4 of its body"),
+                "{}",
+                err
+            );
+        }
+        _ => panic!("expected the simd operator to be rejected"),
+    }
+}
+
+#[test]
+fn test_uses_float() {
+    use crate::executor::parse_only;
+    use ontio_wasmjit_environ::Tunables;
+
+    let uses_float = |wat: &str| {
+        let wasm = wat::parse_str(wat).unwrap();
+        parse_only(&wasm, Tunables::default()).unwrap().uses_float
+    };
+
+    assert!(!uses_float(
+        r#"
+        (module
+          (memory 1)
+          (global (mut i64) (i64.const 0))
+          (func (param i32) (result i64)
+            (i64.extend_i32_s (i32.load (local.get 0)))))"#
+    ));
+    assert!(uses_float(
+        "(module (func (result i32) (i32.trunc_f32_s (f32.const 1))))"
+    ));
+    assert!(uses_float("(module (func (param f64)))"));
+    assert!(uses_float("(module (global f32 (f32.const 0)))"));
+    assert!(uses_float("(module (func (local f64)))"));
+}
+
+#[test]
+fn test_indirect_call_traps() {
+    use crate::executor::Module;
+    use ontio_wasmjit_runtime::TrapCode;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (type $t (func (param i32) (result i32)))
+          (table 3 funcref)
+          (elem (i32.const 0) $inc $wide)
+          (func $inc (param i32) (result i32) (i32.add (local.get 0) (i32.const 1)))
+          (func $wide (param i64) (result i32) (i32.wrap_i64 (local.get 0)))
+          (func (export "call") (param i32) (result i32)
+            (call_indirect (type $t) (i32.const 41) (local.get 0))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    let mut trap_code = |index: i32| match instance.call("call", (index,)) {
+        Err(Error::Trap(trap)) => trap.code,
+        other => panic!("expected a trap, got {:?}", other),
+    };
+
+    assert_eq!(trap_code(1), TrapCode::BadSignature);
+    assert_eq!(trap_code(2), TrapCode::IndirectCallToNull);
+    assert_eq!(trap_code(3), TrapCode::TableOutOfBounds);
+    assert_eq!(instance.call("call", (0i32,)).unwrap(), Some(42));
+}
+
+#[test]
+fn test_memory_budget() {
+    use crate::executor::Module;
+    use ontio_wasmjit_runtime::MemoryBudget;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (memory 2)
+          (func (export "grow") (param i32) (result i32)
+            (memory.grow (local.get 0))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Arc::new(Module::compile(&wasm, BuildOption::new()).unwrap());
+    let budget = Arc::new(MemoryBudget::new(5));
+
+    let instantiate = || {
+        module
+            .clone()
+            .instantiate_with_budget(&mut ChainResolver, budget.clone())
+    };
+
+    let mut first = instantiate().unwrap();
+    let mut second = instantiate().unwrap();
+    assert_eq!(budget.used_pages(), 4);
+    assert!(instantiate().is_err());
+
+    assert_eq!(first.call("grow", (2,)).unwrap(), Some(-1));
+    assert_eq!(first.call("grow", (1,)).unwrap(), Some(2));
+    assert_eq!(second.call("grow", (1,)).unwrap(), Some(-1));
+    assert_eq!(budget.used_pages(), 5);
+
+    // the pages of a dropped instance are given back
+    drop(first);
+    assert_eq!(budget.used_pages(), 2);
+    assert_eq!(second.call("grow", (3,)).unwrap(), Some(2));
+}
+
+#[test]
+fn test_warm() {
+    use crate::executor::Module;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func (export "invoke") (param i64) (result i64)
+            (i64.mul (local.get 0) (local.get 0))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+    module.warm(false).unwrap();
+    module.warm(false).unwrap();
+
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    // locking may be refused by the memlock limit, the code is still usable
+    let _ = instance.warm(true);
+    let _ = instance.warm(true);
+    assert_eq!(instance.call("invoke", (7i64,)).unwrap(), Some(49));
+}
+
+#[test]
+fn test_function_code_ranges() {
+    use crate::executor::Module;
+
+    let wat = r#"
+        (module
+          (func (param i32) (result i32) (i32.add (local.get 0) (i32.const 1)))
+          (func)
+          (func (export "invoke") (param i64) (result i64)
+            (i64.mul (local.get 0) (i64.const 3))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+
+    let ranges = module.function_code_ranges();
+    assert_eq!(ranges.len(), 3);
+    let mut end = 0;
+    for (offset, len) in ranges.values() {
+        assert!(*len > 0);
+        assert!(*offset >= end, "functions overlap");
+        end = offset + len;
+    }
+    assert!(end <= module.code_size());
+    assert_eq!(
+        ranges.values().map(|(_, len)| len).sum::<usize>(),
+        module.code_size()
+    );
+}
+
+#[test]
+fn test_call_cancel() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::Tunables;
+    use ontio_wasmjit_runtime::TrapCode;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    let wat = r#"
+        (module
+          (func $spin (loop (br 0)))
+          (func (export "spin") (call $spin)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+
+    let module = Module::compile(&wasm, BuildOption::new().gas_metering(true)).unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    instance.set_host_state(Box::new(test_chain(u64::max_value())));
+    let cancel = Arc::new(AtomicBool::new(false));
+    let watchdog = {
+        let cancel = cancel.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            cancel.store(true, Ordering::Relaxed);
+        })
+    };
+    match instance.call_with_cancel("spin", Vec::new(), cancel.clone()) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::Cancelled),
+        _ => panic!("expected a cancelled trap"),
+    }
+    watchdog.join().unwrap();
+
+    // the flag is ignored in deterministic mode, the gas ends the loop
+    let module = Module::compile_with_tunables(
+        &wasm,
+        BuildOption::new().gas_metering(true),
+        Tunables::deterministic(true),
+    )
+    .unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    instance.set_host_state(Box::new(test_chain(1_000_000)));
+    match instance.call_with_cancel("spin", Vec::new(), cancel) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::OutOfGas),
+        _ => panic!("expected a gas exhausted trap"),
+    }
+}
+
+#[test]
+fn test_duplicate_export_name() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::CompileError;
+
+    let wat = r#"
+        (module
+          (func (export "run"))
+          (func (export "run")))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    match Module::compile(&wasm, BuildOption::new()) {
+        Err(Error::Compile(CompileError::Wasm(WasmError::InvalidWebAssembly {
+            message,
+            offset,
+        }))) => {
+            assert_eq!(message, "duplicate export name");
+            // the id of the export section
+            assert_eq!(wasm[offset], 7);
+        }
+        _ => panic!("expected the duplicate export to be rejected"),
+    }
+}
+
+#[test]
+fn test_i64_results() {
+    let wat = r#"
+        (module
+          (func (export "max") (param i64) (result i64) (i64.const -1))
+          (func (export "min") (param i64) (result i64) (i64.const 0x8000000000000000))
+          (func (export "neg") (param i64) (result i64) (i64.sub (i64.const 0) (local.get 0)))
+          (func (export "neg32") (param i64) (result i32) (i32.const -2))
+          (func (export "float") (param i64) (result f32) (f32.const 1)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = build_module(&wasm, BuildOption::new()).unwrap();
+    let mut instance = module.instantiate(&mut ChainResolver).unwrap();
+    instance.set_host_state(Box::new(ChainCtx::default()));
+    let mut call = |func, arg: i64| instance.call(func, (arg,)).unwrap().unwrap();
+
+    assert_eq!(call("max", 0) as u64, u64::max_value());
+    assert_eq!(call("min", 0), i64::min_value());
+    assert_eq!(call("neg", 5), -5);
+    assert_eq!(call("neg", i64::min_value() + 1), i64::max_value());
+    // the 32 bits results do not keep the high half of the argument slot
+    assert_eq!(call("neg32", -1), -2);
+    assert_eq!(call("float", -1), i64::from(1f32.to_bits()));
+
+    let max: u64 = instance.call_typed("max", (0i64,)).unwrap();
+    assert_eq!(max, u64::max_value());
+    let min: i64 = instance.call_typed("min", (0i64,)).unwrap();
+    assert_eq!(min, i64::min_value());
+}
+
+#[test]
+fn test_host_trap() {
+    use crate::executor::Module;
+    use ontio_wasmjit_runtime::builtins::check_host_trap;
+    use ontio_wasmjit_runtime::{TrapCode, VMContext, VMFunctionBody, VMFunctionImport};
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    unsafe extern "C" fn debit(vmctx: *mut VMContext, amount: u32) -> u32 {
+        check_host_trap((&mut *vmctx).instance(), |_| {
+            if amount > 10 {
+                return Err(TrapCode::Unreachable);
+            }
+            Ok(10 - amount)
+        })
+    }
+
+    let wat = r#"
+        (module
+          (import "env" "debit" (func $debit (param i32) (result i32)))
+          (func $pay (param i32) (result i32)
+            (i32.add (call $debit (local.get 0)) (i32.const 100)))
+          (func (export "pay") (param i32) (result i32)
+            (call $pay (local.get 0))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new().gas_metering(true)).unwrap();
+    let mut resolver = |_: &str, _: &str| {
+        Some(VMFunctionImport {
+            body: debit as *const VMFunctionBody,
+            vmctx: std::ptr::null_mut(),
+        })
+    };
+    let mut instance = Arc::new(module).instantiate(&mut resolver).unwrap();
+    instance.set_host_state(Box::new(test_chain(u64::max_value())));
+
+    assert_eq!(instance.call("pay", (3u32,)).unwrap(), Some(107));
+    match instance.call("pay", (11u32,)) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::Unreachable),
+        _ => panic!("expected the host function to trap"),
+    }
+    // the depth taken by the unwound calls is given back
+    let exec_metrics = instance.host_state().get_exec_metrics();
+    assert_eq!(exec_metrics.depth_left.load(Ordering::Relaxed), 100000);
+    assert_eq!(instance.call("pay", (10u32,)).unwrap(), Some(100));
+}
+
+#[test]
+fn test_truncation_traps() {
+    use ontio_wasmjit_runtime::TrapCode;
+
+    let wat = r#"
+        (module
+          (func (export "i32_s") (param f64) (result i32) (i32.trunc_f64_s (local.get 0)))
+          (func (export "i32_u") (param f64) (result i32) (i32.trunc_f64_u (local.get 0)))
+          (func (export "i64_s") (param f64) (result i64) (i64.trunc_f64_s (local.get 0)))
+          (func (export "i64_u") (param f32) (result i64) (i64.trunc_f32_u (local.get 0))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = build_module(&wasm, BuildOption::new()).unwrap();
+    let mut instance = module.instantiate(&mut ChainResolver).unwrap();
+    instance.set_host_state(Box::new(ChainCtx::default()));
+
+    let traps = [
+        ("i32_s", f64::NAN, TrapCode::BadConversionToInteger),
+        ("i32_s", f64::INFINITY, TrapCode::IntegerOverflow),
+        ("i32_s", f64::NEG_INFINITY, TrapCode::IntegerOverflow),
+        ("i32_s", 2147483648.0, TrapCode::IntegerOverflow),
+        ("i32_s", -2147483649.0, TrapCode::IntegerOverflow),
+        ("i32_u", f64::NAN, TrapCode::BadConversionToInteger),
+        ("i32_u", -1.0, TrapCode::IntegerOverflow),
+        ("i32_u", 4294967296.0, TrapCode::IntegerOverflow),
+        ("i64_s", 9223372036854775808.0, TrapCode::IntegerOverflow),
+    ];
+    for &(func, arg, code) in traps.iter() {
+        match instance.call(func, (arg,)) {
+            Err(Error::Trap(trap)) => assert_eq!(trap.code, code, "{}({})", func, arg),
+            other => panic!("expected {}({}) to trap, got {:?}", func, arg, other),
+        }
+    }
+    match instance.call("i64_u", (f32::NAN,)) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::BadConversionToInteger),
+        other => panic!("expected i64_u(NaN) to trap, got {:?}", other),
+    }
+
+    // the values truncated into the range do not trap
+    let results = [
+        ("i32_s", 2147483647.9, 2147483647),
+        ("i32_s", -2147483648.9, -2147483648),
+        ("i32_u", -0.9, 0),
+        ("i32_u", 4294967295.9, -1),
+        ("i64_s", -9223372036854775808.0, i64::min_value()),
+    ];
+    for &(func, arg, result) in results.iter() {
+        assert_eq!(
+            instance.call(func, (arg,)).unwrap(),
+            Some(result),
+            "{}({})",
+            func,
+            arg
+        );
+    }
+}
+
+#[test]
+fn test_saturating_truncations() {
+    let wat = r#"
+        (module
+          (func (export "i32_s") (param f64) (result i32) (i32.trunc_sat_f64_s (local.get 0)))
+          (func (export "i32_u") (param f64) (result i32) (i32.trunc_sat_f64_u (local.get 0)))
+          (func (export "i64_s") (param f64) (result i64) (i64.trunc_sat_f64_s (local.get 0)))
+          (func (export "i64_u") (param f64) (result i64) (i64.trunc_sat_f64_u (local.get 0)))
+          (func (export "f32_i32_s") (param f32) (result i32)
+            (i32.trunc_sat_f32_s (local.get 0)))
+          (func (export "f32_i32_u") (param f32) (result i32)
+            (i32.trunc_sat_f32_u (local.get 0)))
+          (func (export "f32_i64_s") (param f32) (result i64)
+            (i64.trunc_sat_f32_s (local.get 0)))
+          (func (export "f32_i64_u") (param f32) (result i64)
+            (i64.trunc_sat_f32_u (local.get 0))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = build_module(&wasm, BuildOption::new()).unwrap();
+    let mut instance = module.instantiate(&mut ChainResolver).unwrap();
+    instance.set_host_state(Box::new(ChainCtx::default()));
+
+    let i32_max = i64::from(i32::max_value());
+    let i32_min = i64::from(i32::min_value());
+    // the unsigned 32 bits results are returned sign extended
+    let u32_max = -1;
+    let f64_results = [
+        ("i32_s", f64::NAN, 0),
+        ("i32_s", f64::INFINITY, i32_max),
+        ("i32_s", f64::NEG_INFINITY, i32_min),
+        ("i32_s", 2147483648.0, i32_max),
+        ("i32_s", 2147483647.9, i32_max),
+        ("i32_s", -2147483649.0, i32_min),
+        ("i32_s", -1.9, -1),
+        ("i32_u", f64::NAN, 0),
+        ("i32_u", f64::INFINITY, u32_max),
+        ("i32_u", f64::NEG_INFINITY, 0),
+        ("i32_u", 4294967296.0, u32_max),
+        ("i32_u", -0.9, 0),
+        ("i32_u", 2147483648.0, i32_min),
+        ("i64_s", f64::NAN, 0),
+        ("i64_s", f64::INFINITY, i64::max_value()),
+        ("i64_s", f64::NEG_INFINITY, i64::min_value()),
+        ("i64_s", 9223372036854775808.0, i64::max_value()),
+        ("i64_s", -9223372036854775808.0, i64::min_value()),
+        ("i64_u", f64::NAN, 0),
+        ("i64_u", f64::INFINITY, u64::max_value() as i64),
+        ("i64_u", f64::NEG_INFINITY, 0),
+        ("i64_u", 18446744073709551616.0, u64::max_value() as i64),
+        ("i64_u", 9223372036854775808.0, i64::min_value()),
+    ];
+    for &(func, arg, result) in f64_results.iter() {
+        assert_eq!(
+            instance.call(func, (arg,)).unwrap(),
+            Some(result),
+            "{}({})",
+            func,
+            arg
+        );
+    }
+
+    let f32_results = [
+        ("f32_i32_s", f32::NAN, 0),
+        ("f32_i32_s", f32::INFINITY, i32_max),
+        ("f32_i32_s", f32::NEG_INFINITY, i32_min),
+        ("f32_i32_s", 2147483648.0, i32_max),
+        ("f32_i32_u", f32::NAN, 0),
+        ("f32_i32_u", f32::INFINITY, u32_max),
+        ("f32_i32_u", -1.5, 0),
+        ("f32_i64_s", f32::NAN, 0),
+        ("f32_i64_s", f32::INFINITY, i64::max_value()),
+        ("f32_i64_s", f32::NEG_INFINITY, i64::min_value()),
+        ("f32_i64_u", f32::NAN, 0),
+        ("f32_i64_u", f32::INFINITY, u64::max_value() as i64),
+        ("f32_i64_u", -1.5, 0),
+    ];
+    for &(func, arg, result) in f32_results.iter() {
+        assert_eq!(
+            instance.call(func, (arg,)).unwrap(),
+            Some(result),
+            "{}({})",
+            func,
+            arg
+        );
+    }
+}
+
+#[test]
+fn test_instance_pool() {
+    use crate::pool::InstancePool;
+
+    let wat = r#"
+        (module
+          (global $counter (mut i32) (i32.const 0))
+          (func (export "bump") (result i32)
+            (drop (memory.grow (i32.const 1)))
+            (i32.store (i32.const 8) (i32.add (i32.load (i32.const 8)) (i32.const 1)))
+            (i32.store (i32.const 65536) (i32.const 7))
+            (global.set $counter (i32.add (global.get $counter) (i32.const 1)))
+            (global.get $counter))
+          (func (export "state") (result i32)
+            (i32.add
+              (i32.mul (memory.size) (i32.const 100))
+              (i32.add (i32.load (i32.const 8)) (global.get $counter))))
+          (memory (;0;) 1 10)
+          (table 1 4 funcref)
+          (data (i32.const 8) "\05"))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = build_module(&wasm, BuildOption::new()).unwrap();
+    let pool = InstancePool::new(module, ChainResolver, 1).unwrap();
+    assert_eq!(pool.idle(), 1);
+
+    let mut instance = pool.acquire().unwrap();
+    assert_eq!(pool.idle(), 0);
+    assert_eq!(instance.call("state", ()).unwrap(), Some(105));
+    assert_eq!(instance.call("bump", ()).unwrap(), Some(1));
+    assert_eq!(instance.call("bump", ()).unwrap(), Some(2));
+    // two pages grown, the data incremented twice, the global at 2
+    assert_eq!(instance.call("state", ()).unwrap(), Some(309));
+    assert_eq!(instance.table_grow(0, 2), Some(1));
+    assert_eq!(instance.table_size(0), Some(3));
+    pool.release(instance).unwrap();
+    assert_eq!(pool.idle(), 1);
+
+    // the recycled instance is in its initial state again
+    let mut instance = pool.acquire().unwrap();
+    assert_eq!(instance.call("state", ()).unwrap(), Some(105));
+    assert_eq!(instance.table_size(0), Some(1));
+    assert_eq!(instance.table_grow(0, 3), Some(1));
+    assert_eq!(instance.call("bump", ()).unwrap(), Some(1));
+
+    // a fresh instance is created when none is idle, the surplus is dropped
+    let mut other = pool.acquire().unwrap();
+    assert_eq!(other.call("state", ()).unwrap(), Some(105));
+    pool.release(instance).unwrap();
+    pool.release(other).unwrap();
+    assert_eq!(pool.idle(), 1);
+
+    // the instances of another module are not accepted
+    let other = build_module(&wat::parse_str("(module)").unwrap(), BuildOption::new())
+        .unwrap()
+        .instantiate(&mut ChainResolver)
+        .unwrap();
+    assert!(pool.release(other).is_err());
+}
+
+#[test]
+fn test_instance_pool_gas() {
+    use crate::pool::InstancePool;
+
+    let wat = r#"
+        (module
+          (func (export "work")
+            (drop (i32.const 1))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = build_module(&wasm, BuildOption::new().gas_metering(true)).unwrap();
+    let pool = InstancePool::new(module, ChainResolver, 1).unwrap();
+
+    // with a gas factor of 10, the three operators of a call are charged on
+    // every fourth call, from the costs counted by the earlier ones
+    let gas_used = |instance: &mut crate::executor::Instance| {
+        (0..4)
+            .map(|_| {
+                let mut chain = test_chain(1000);
+                chain.set_gas_factor(10);
+                instance
+                    .try_execute_full(chain, "work", ())
+                    .unwrap()
+                    .gas_used
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut instance = pool.acquire().unwrap();
+    let mut fresh = pool.acquire().unwrap();
+    assert_eq!(gas_used(&mut fresh), vec![0, 0, 0, 1]);
+    assert_eq!(gas_used(&mut instance), vec![0, 0, 0, 1]);
+    // the costs counted by the previous user are not carried over
+    instance.call("work", ()).unwrap();
+    pool.release(instance).unwrap();
+    let mut recycled = pool.acquire().unwrap();
+    assert_eq!(gas_used(&mut recycled), vec![0, 0, 0, 1]);
+}
+
+#[test]
+fn test_collect_violations() {
+    use crate::executor::{parse_collecting, parse_only};
+    use ontio_wasmjit_environ::Tunables;
+
+    let wat = r#"
+        (module
+          (global f64 (f64.const 0))
+          (global i32 (i32.const 0))
+          (memory 4)
+          (func)
+          (func)
+          (func))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let mut tunables = Tunables::default();
+    tunables.reject_float = true;
+    tunables.max_functions = Some(2);
+    tunables.max_globals = Some(1);
+    tunables.max_memory_pages = Some(2);
+
+    // the default translation fails on the first violation
+    assert!(parse_only(&wasm, tunables.clone()).is_err());
+
+    let violations = match parse_collecting(&wasm, tunables) {
+        Err(violations) => violations,
+        Ok(_) => panic!("expected the violations to be reported"),
+    };
+    let violations: Vec<_> = violations.iter().map(ToString::to_string).collect();
+    assert_eq!(violations.len(), 4, "{:?}", violations);
+    // in the order of the sections, the limits on the counts come last
+    assert!(violations[0].contains("memory exceeds max_memory_pages: 4 > 2"));
+    assert!(violations[1].contains("floating point global of type f64"));
+    assert!(violations[2].contains("module exceeds max_functions: 3 > 2"));
+    assert!(violations[3].contains("module exceeds max_globals: 2 > 1"));
+
+    // a module within the tunables translates as usual
+    let wasm = wat::parse_str("(module (func))").unwrap();
+    assert!(parse_collecting(&wasm, Tunables::default()).is_ok());
+}
+
+#[test]
+fn test_memory_pages() {
+    use crate::executor::Module;
+    use ontio_wasmjit_environ::Tunables;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func (export "grow") (param i32) (result i32)
+            (memory.grow (local.get 0)))
+          (memory 1 10))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    assert_eq!(instance.memory_size_pages(), Some(1));
+    assert_eq!(instance.memory_max_pages(), Some(10));
+    assert_eq!(instance.call("grow", (2,)).unwrap(), Some(1));
+    assert_eq!(instance.memory_size_pages(), Some(3));
+    // a failed grow leaves the size unchanged
+    assert_eq!(instance.call("grow", (8,)).unwrap(), Some(-1));
+    assert_eq!(instance.memory_size_pages(), Some(3));
+
+    // the tunables cap the declared maximum
+    let mut tunables = Tunables::default();
+    tunables.max_memory_pages = Some(4);
+    let module = Module::compile_with_tunables(&wasm, BuildOption::new(), tunables).unwrap();
+    let instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    assert_eq!(instance.memory_max_pages(), Some(4));
+
+    let wasm = wat::parse_str("(module)").unwrap();
+    let module = Module::compile(&wasm, BuildOption::new()).unwrap();
+    let instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    assert_eq!(instance.memory_size_pages(), None);
+    assert_eq!(instance.memory_max_pages(), None);
+}
+
+#[test]
+fn test_overlapping_data_segments() {
+    let wat = r#"
+        (module
+          (func (export "load") (param i32) (result i32)
+            (i32.load8_u (local.get 0)))
+          (memory 1)
+          (data (i32.const 0) "aaaaaa")
+          (data (i32.const 2) "bbb")
+          (data (i32.const 3) "c"))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = build_module(&wasm, BuildOption::new()).unwrap();
+    let mut instance = module.instantiate(&mut ChainResolver).unwrap();
+
+    // the segments are applied in declaration order, the later ones win
+    assert_eq!(instance.read_memory(0, 7).unwrap(), b"aabcba\0".to_vec());
+    assert_eq!(instance.call("load", (3,)).unwrap(), Some(i64::from(b'c')));
+
+    // the reverse order keeps the first segment whole
+    let wat = r#"
+        (module
+          (memory 1)
+          (data (i32.const 3) "c")
+          (data (i32.const 2) "bbb")
+          (data (i32.const 0) "aaaaaa"))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = build_module(&wasm, BuildOption::new()).unwrap();
+    let instance = module.instantiate(&mut ChainResolver).unwrap();
+    assert_eq!(instance.read_memory(0, 7).unwrap(), b"aaaaaa\0".to_vec());
+}
+
+#[test]
+fn test_host_call_costs() {
+    use crate::executor::Module;
+    use crate::resolver::Resolver;
+    use ontio_wasmjit_environ::HostCallCharge;
+    use ontio_wasmjit_runtime::builtins::check_host_trap;
+    use ontio_wasmjit_runtime::{
+        HostCallCost, TrapCode, VMContext, VMFunctionBody, VMFunctionImport,
+    };
+    use std::sync::Arc;
+
+    unsafe extern "C" fn fail(vmctx: *mut VMContext, _size: u32) {
+        check_host_trap((&mut *vmctx).instance(), |_| Err(TrapCode::Unreachable))
+    }
+
+    /// Resolves like `ChainResolver`, charging 1000 gas per call of the
+    /// timestamp and 10 gas per unit of the size given to `fail`.
+    struct Costs(bool);
+
+    impl Resolver for Costs {
+        fn resolve(&mut self, module: &str, field: &str) -> Option<VMFunctionImport> {
+            match field {
+                "fail" => Some(VMFunctionImport {
+                    body: fail as *const VMFunctionBody,
+                    vmctx: std::ptr::null_mut(),
+                }),
+                _ => ChainResolver.resolve(module, field),
+            }
+        }
+
+        fn resolve_cost(&mut self, _module: &str, field: &str) -> Option<HostCallCost> {
+            if !self.0 {
+                return None;
+            }
+            match field {
+                "ontio_timestamp" => Some(HostCallCost::Fixed(1000)),
+                "fail" => Some(HostCallCost::Arguments(Arc::new(|args: &[u64]| {
+                    args[0] * 10
+                }))),
+                _ => None,
+            }
+        }
+    }
+
+    let wat = r#"
+        (module
+          (import "env" "ontio_timestamp" (func $timestamp (result i64)))
+          (import "env" "fail" (func $fail (param i32)))
+          (func (export "now") (result i64)
+            (drop (call $timestamp))
+            (call $timestamp))
+          (func (export "fail")
+            (call $fail (i32.const 7))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let compile = |charge| {
+        let build_option = BuildOption::new()
+            .gas_metering(true)
+            .host_call_charge(charge);
+        Arc::new(Module::compile(&wasm, build_option).unwrap())
+    };
+    let module = compile(HostCallCharge::Arguments);
+    let gas_used = |charged, func, gas_left| {
+        let mut instance = module.clone().instantiate(&mut Costs(charged)).unwrap();
+        let result = instance.execute(test_chain(gas_left), func, ());
+        (result, gas_left - instance.host_state().gas_left())
+    };
+
+    let (result, base) = gas_used(false, "now", 1_000_000);
+    assert_eq!(result.unwrap(), Some(1));
+    let (result, charged) = gas_used(true, "now", 1_000_000);
+    assert_eq!(result.unwrap(), Some(1));
+    assert_eq!(charged, base + 2000);
+
+    // the cost is charged before the call, even if the host function traps
+    let (result, base) = gas_used(false, "fail", 1_000_000);
+    assert!(result.is_err());
+    let (result, charged) = gas_used(true, "fail", 1_000_000);
+    match result {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::Unreachable),
+        _ => panic!("expected the host function to trap"),
+    }
+    assert_eq!(charged, base + 70);
+
+    // without the gas for the cost, the host function is not called
+    let (result, _) = gas_used(true, "fail", base + 69);
+    match result {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::OutOfGas),
+        _ => panic!("expected the cost to exhaust the gas"),
+    }
+
+    // the code charges only the costs it is compiled for
+    for &(charge, ok) in &[
+        (HostCallCharge::None, false),
+        (HostCallCharge::Fixed, false),
+        (HostCallCharge::Arguments, true),
+    ] {
+        match compile(charge).instantiate(&mut Costs(true)) {
+            Err(Error::Link(err)) => assert!(!ok, "{}", err),
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => assert!(ok),
+        }
+        assert!(compile(charge).instantiate(&mut Costs(false)).is_ok());
+    }
+}
+
+#[test]
+fn test_validate() {
+    use crate::executor::validate;
+    use ontio_wasmjit_environ::{Features, Tunables};
+
+    let mut tunables = Tunables::default();
+    tunables.reject_float = true;
+    tunables.max_functions = Some(3);
+
+    let wasm = wat::parse_str(
+        r#"(module (func (export "add") (param i32 i32) (result i32)
+             (i32.add (local.get 0) (local.get 1))))"#,
+    )
+    .unwrap();
+    assert!(validate(&wasm, tunables.clone(), Features::default()).is_ok());
+
+    // the violations of the structure, of the limits and of each body
+    let wat = r#"
+        (module
+          (memory 1)
+          (export "memory" (memory 0))
+          (func (drop (f32.const 1)))
+          (func (param i32) (drop (i32x4.splat (local.get 0))))
+          (func)
+          (func (drop (i32.extend8_s (i32.const 1)))))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let errors = validate(&wasm, tunables.clone(), Features::default()).unwrap_err();
+    let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+    assert!(
+        errors[0].contains("can not export memory memory"),
+        "{}",
+        errors[0]
+    );
+    assert!(
+        errors[1].contains("module exceeds max_functions: 4 > 3"),
+        "{}",
+        errors[1]
+    );
+
+    // within the limits, the function bodies are checked one by one
+    tunables.max_functions = None;
+    let wasm = wat::parse_str(wat.replace(r#"(export "memory" (memory 0))"#, "")).unwrap();
+    let mut features = Features::default();
+    features.sign_extension = false;
+    let errors = validate(&wasm, tunables.clone(), features).unwrap_err();
+    let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+    assert_eq!(errors.len(), 3, "{:?}", errors);
+    assert!(
+        errors[0].contains("floating point operator F32Const"),
+        "{}",
+        errors[0]
+    );
+    assert!(errors[1].contains("I32x4Splat"), "{}", errors[1]);
+    assert!(errors[2].contains("I32Extend8S"), "{}", errors[2]);
+
+    // the features given override those of the tunables
+    features.sign_extension = true;
+    features.simd = true;
+    tunables.reject_float = false;
+    assert!(validate(&wasm, tunables, features).is_ok());
+
+    // a malformed module
+    let errors = validate(&wasm[..wasm.len() - 1], Tunables::default(), features).unwrap_err();
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn test_gas_remaining() {
+    use crate::executor::Module;
+    use ontio_wasmjit_runtime::TrapCode;
+    use std::sync::Arc;
+
+    let wat = r#"
+        (module
+          (func (export "spin") (param i32) (result i32)
+            (local i32)
+            (block
+              (loop
+                (br_if 1 (i32.ge_u (local.get 1) (i32.const 100)))
+                (local.set 1 (i32.add (local.get 1) (i32.const 1)))
+                (br 0)))
+            (if (local.get 0)
+              (then (drop (i32.div_u (i32.const 1) (i32.sub (local.get 0) (i32.const 1))))))
+            (local.get 1)))"#;
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = Module::compile(&wasm, BuildOption::new().gas_metering(true)).unwrap();
+    let mut instance = Arc::new(module).instantiate(&mut ChainResolver).unwrap();
+    let chain = test_chain(0);
+    instance.set_host_state(Box::new(chain));
+
+    let initial = 1_000_000;
+    instance.set_gas(initial);
+    assert_eq!(instance.gas_remaining(), initial);
+    assert_eq!(instance.call("spin", (0,)).unwrap(), Some(100));
+    let used = initial - instance.gas_remaining();
+    // `block` and `loop`, 100 iterations of 9 operators, the 4 of the last
+    // test, `local.get 0` with `if`, and `local.get 1` with the final `end`
+    assert_eq!(used, 1 + 1 + 100 * 9 + 4 + 2 + 2);
+    // the host state sees the same gas
+    assert_eq!(instance.host_state().gas_left(), instance.gas_remaining());
+
+    instance.set_gas(initial);
+    assert_eq!(instance.call("spin", (0,)).unwrap(), Some(100));
+    assert_eq!(initial - instance.gas_remaining(), used);
+
+    // the operators executed up to the trap are charged, the same amount on
+    // every run, including the 5 of the `then` scope up to `i32.div_u`
+    instance.set_gas(initial);
+    match instance.call("spin", (1,)) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::IntegerDivisionByZero),
+        result => panic!("unexpected {:?}", result),
+    }
+    let trapped = initial - instance.gas_remaining();
+    assert_eq!(trapped, 1 + 1 + 100 * 9 + 4 + 2 + 5);
+    instance.set_gas(initial);
+    assert!(instance.call("spin", (1,)).is_err());
+    assert_eq!(initial - instance.gas_remaining(), trapped);
+
+    // an exhausted call leaves nothing
+    instance.set_gas(used / 2);
+    match instance.call("spin", (0,)) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code, TrapCode::OutOfGas),
+        result => panic!("unexpected {:?}", result),
+    }
+    assert_eq!(instance.gas_remaining(), 0);
+}
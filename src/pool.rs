@@ -0,0 +1,95 @@
+//! A pool of instances of a compiled module, recycled across transactions
+//! to amortize the allocation of their memories and contexts.
+
+use crate::chain_api::ChainCtx;
+use crate::error::Error;
+use crate::executor::{Instance, Module};
+use crate::resolver::Resolver;
+use ontio_wasmjit_runtime::InstanceSnapshot;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// Recycles the instances of a module: an instance given back with `release`
+/// is reset to the state the module instantiates to, then handed out again
+/// by `acquire`.
+///
+/// The reset restores the linear memories, shrinking the grown pages away,
+/// the table sizes and the mutable globals from a snapshot taken right after
+/// instantiation, with the data segments applied. The host state is replaced
+/// by a default `ChainCtx`, and the host call hook, reentrancy guard, cancel
+/// flag and host context are removed, so nothing of a transaction is seen by
+/// the next one.
+pub struct InstancePool {
+    module: Arc<Module>,
+    resolver: Mutex<Box<dyn Resolver + Send>>,
+    initial: InstanceSnapshot,
+    idle: Mutex<Vec<Instance>>,
+    max_idle: usize,
+}
+
+impl InstancePool {
+    /// Create a pool of instances of `module`, whose imports are resolved
+    /// with `resolver`. At most `max_idle` released instances are kept, the
+    /// others are dropped.
+    pub fn new(
+        module: Arc<Module>,
+        resolver: impl Resolver + Send + 'static,
+        max_idle: usize,
+    ) -> Result<Self, Error> {
+        let mut resolver: Box<dyn Resolver + Send> = Box::new(resolver);
+        let mut instance = module.clone().instantiate(&mut *resolver)?;
+        let initial = instance.snapshot();
+        let mut idle = Vec::new();
+        if max_idle > 0 {
+            idle.push(instance);
+        }
+        Ok(InstancePool {
+            module,
+            resolver: Mutex::new(resolver),
+            initial,
+            idle: Mutex::new(idle),
+            max_idle,
+        })
+    }
+
+    /// The module the instances of the pool belong to.
+    pub fn module(&self) -> &Arc<Module> {
+        &self.module
+    }
+
+    /// Take an idle instance, or instantiate a new one if there is none.
+    pub fn acquire(&self) -> Result<Instance, Error> {
+        if let Some(instance) = self.idle.lock().pop() {
+            return Ok(instance);
+        }
+        let mut resolver = self.resolver.lock();
+        self.module.clone().instantiate(&mut **resolver)
+    }
+
+    /// Reset `instance` and keep it for a later `acquire`. An instance which
+    /// fails to reset is dropped, as well as the instances beyond `max_idle`.
+    ///
+    /// The instance must have been acquired from this pool, an instance of
+    /// another module is an `Error::Internal`.
+    pub fn release(&self, mut instance: Instance) -> Result<(), Error> {
+        if !Arc::ptr_eq(instance.module(), &self.module) {
+            return Err(Error::Internal(String::from(
+                "instance does not belong to the module of this pool",
+            )));
+        }
+        instance.restore(&self.initial)?;
+        instance.set_host_state(Box::new(ChainCtx::default()));
+        instance.clear_settings();
+
+        let mut idle = self.idle.lock();
+        if idle.len() < self.max_idle {
+            idle.push(instance);
+        }
+        Ok(())
+    }
+
+    /// The number of idle instances kept by the pool.
+    pub fn idle(&self) -> usize {
+        self.idle.lock().len()
+    }
+}
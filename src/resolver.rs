@@ -1,7 +1,22 @@
-use ontio_wasmjit_runtime::VMFunctionImport;
+use crate::error::Error;
+use crate::executor::{Instance, Module};
+use cranelift_codegen::ir;
+use cranelift_entity::PrimaryMap;
+use ontio_wasmjit_runtime::{HostCallCost, VMFunctionImport};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
 
 pub trait Resolver {
     fn resolve(&mut self, module: &str, field: &str) -> Option<VMFunctionImport>;
+
+    /// The gas charged for each call of the import `module.field`, on top of
+    /// what the host function charges itself, none by default. Only modules
+    /// compiled with gas metering and a `HostCallCharge` charge it, the other
+    /// modules fail to instantiate with a cost.
+    fn resolve_cost(&mut self, _module: &str, _field: &str) -> Option<HostCallCost> {
+        None
+    }
 }
 
 pub use crate::chain_api::ChainResolver;
@@ -22,3 +37,98 @@ where
         (self)(module, field)
     }
 }
+
+/// Links modules against the function exports of already instantiated
+/// modules, so shared library code can live in its own module.
+///
+/// A call into a linked instance runs with the memory and globals of that
+/// instance, but its gas, steps and call depth are charged to the calling
+/// instance, whose traps it raises. Since the code of the importing instance calls
+/// the exports directly, the linked instances are locked for the duration of
+/// each of its calls: a call is an `Error::Link` while one of them is locked
+/// elsewhere, for instance by a host function holding the `Arc<Mutex<_>>`
+/// returned by `define_instance`.
+#[derive(Default)]
+pub struct Linker {
+    instances: HashMap<String, Arc<Mutex<Instance>>>,
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make the function exports of `instance` importable from the module
+    /// `name`, and return the shared instance.
+    pub fn define_instance(&mut self, name: &str, instance: Instance) -> Arc<Mutex<Instance>> {
+        let instance = Arc::new(Mutex::new(instance));
+        self.instances.insert(name.to_string(), instance.clone());
+        instance
+    }
+
+    /// Instantiate `module`, binding its imports from a defined module to the
+    /// exports of that instance and resolving the others with `host`.
+    ///
+    /// The signatures of the linked functions are checked, a missing or
+    /// mismatched import is an `Error::Link` naming it.
+    pub fn instantiate(
+        &self,
+        module: Arc<Module>,
+        host: &mut dyn Resolver,
+    ) -> Result<Instance, Error> {
+        let info = module.info();
+        let mut imports = PrimaryMap::new();
+        let mut costs = PrimaryMap::new();
+        let mut linked = Vec::new();
+        for (index, (module_name, field)) in info.imported_funcs.iter() {
+            let mut cost = None;
+            let import = match self.instances.get(module_name) {
+                Some(instance) => {
+                    let export = instance.lock().lookup(field).ok_or_else(|| {
+                        Error::Link(format!("unknown import {}.{}", module_name, field))
+                    })?;
+                    let expected = &info.signatures[info.functions[index]];
+                    if !same_signature(expected, &export.signature) {
+                        return Err(Error::Link(format!(
+                            "incompatible import {}.{}: expected {}, found {}",
+                            module_name, field, expected, export.signature
+                        )));
+                    }
+                    // the instances it links to are called through it as well
+                    let transitive = instance.lock().linked().to_vec();
+                    for instance in Some(instance.clone()).into_iter().chain(transitive) {
+                        if !linked.iter().any(|other| Arc::ptr_eq(other, &instance)) {
+                            linked.push(instance);
+                        }
+                    }
+                    VMFunctionImport {
+                        body: export.address,
+                        vmctx: export.vmctx,
+                    }
+                }
+                None => {
+                    cost = host.resolve_cost(module_name, field);
+                    host.resolve(module_name, field).ok_or_else(|| {
+                        Error::Link(format!("unknown import {}.{}", module_name, field))
+                    })?
+                }
+            };
+            imports.push(import);
+            costs.push(cost);
+        }
+
+        module.instantiate_with_imports(
+            imports.into_boxed_slice(),
+            costs.into_boxed_slice(),
+            linked,
+            None,
+        )
+    }
+}
+
+/// Whether two signatures have the same wasm parameters and results, the
+/// leading vmctx parameter included.
+fn same_signature(a: &ir::Signature, b: &ir::Signature) -> bool {
+    let types = |params: &[ir::AbiParam]| params.iter().map(|p| p.value_type).collect::<Vec<_>>();
+    types(&a.params) == types(&b.params) && types(&a.returns) == types(&b.returns)
+}
@@ -75,9 +75,14 @@ pub(crate) fn make_trampoline(
 
         let results = builder.func.dfg.inst_results(call).to_vec();
 
-        // Store the return values into `values_vec`.
-        let mflags = ir::MemFlags::trusted();
+        // Store the return values into `values_vec`. The slots are only 8
+        // bytes aligned, so a vector is not stored as aligned.
         for (i, r) in results.iter().enumerate() {
+            let mut mflags = ir::MemFlags::trusted();
+            if builder.func.dfg.value_type(*r).is_vector() {
+                mflags = ir::MemFlags::new();
+                mflags.set_notrap();
+            }
             builder
                 .ins()
                 .store(mflags, *r, values_vec_ptr_val, (i * value_size) as i32);
@@ -0,0 +1,273 @@
+use crate::error::Error;
+use cranelift_codegen::ir::{self, types};
+
+/// A wasm value of any of the number types.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+}
+
+impl Value {
+    pub fn ty(&self) -> ir::Type {
+        match self {
+            Value::I32(_) => types::I32,
+            Value::I64(_) => types::I64,
+            Value::F32(_) => types::F32,
+            Value::F64(_) => types::F64,
+        }
+    }
+
+    /// Encode the value into a 64 bits argument slot of the trampoline.
+    pub fn to_raw(&self) -> i64 {
+        match *self {
+            Value::I32(val) => val.to_raw(),
+            Value::I64(val) => val.to_raw(),
+            Value::F32(val) => val.to_raw(),
+            Value::F64(val) => val.to_raw(),
+        }
+    }
+}
+
+/// A rust type which maps to a wasm number type.
+pub trait WasmTy: Copy {
+    fn ty() -> ir::Type;
+
+    /// Encode the value into a 64 bits argument slot of the trampoline.
+    fn to_raw(self) -> i64;
+
+    /// Decode the value from the 64 bits result slot of the trampoline.
+    fn from_raw(raw: i64) -> Self;
+}
+
+impl WasmTy for i32 {
+    fn ty() -> ir::Type {
+        types::I32
+    }
+    fn to_raw(self) -> i64 {
+        i64::from(self)
+    }
+    fn from_raw(raw: i64) -> Self {
+        raw as i32
+    }
+}
+
+impl WasmTy for u32 {
+    fn ty() -> ir::Type {
+        types::I32
+    }
+    fn to_raw(self) -> i64 {
+        i64::from(self)
+    }
+    fn from_raw(raw: i64) -> Self {
+        raw as u32
+    }
+}
+
+impl WasmTy for i64 {
+    fn ty() -> ir::Type {
+        types::I64
+    }
+    fn to_raw(self) -> i64 {
+        self
+    }
+    fn from_raw(raw: i64) -> Self {
+        raw
+    }
+}
+
+impl WasmTy for u64 {
+    fn ty() -> ir::Type {
+        types::I64
+    }
+    fn to_raw(self) -> i64 {
+        self as i64
+    }
+    fn from_raw(raw: i64) -> Self {
+        raw as u64
+    }
+}
+
+impl WasmTy for f32 {
+    fn ty() -> ir::Type {
+        types::F32
+    }
+    fn to_raw(self) -> i64 {
+        i64::from(self.to_bits())
+    }
+    fn from_raw(raw: i64) -> Self {
+        f32::from_bits(raw as u32)
+    }
+}
+
+impl WasmTy for f64 {
+    fn ty() -> ir::Type {
+        types::F64
+    }
+    fn to_raw(self) -> i64 {
+        self.to_bits() as i64
+    }
+    fn from_raw(raw: i64) -> Self {
+        f64::from_bits(raw as u64)
+    }
+}
+
+/// Arguments of a wasm function call.
+pub trait WasmArgs {
+    /// Encode the arguments into the argument slots of the trampoline, after
+    /// checking them against the wasm parameter types `params`.
+    fn encode(self, params: &[ir::Type]) -> Result<Vec<i64>, Error>;
+}
+
+/// Raw argument slots, only their count is checked: integers narrower than
+/// the slot are truncated and floats are taken as their bit patterns. Vector
+/// parameters do not fit a slot and are always a mismatch.
+impl WasmArgs for Vec<i64> {
+    fn encode(self, params: &[ir::Type]) -> Result<Vec<i64>, Error> {
+        if self.len() != params.len() || params.iter().any(|ty| ty.is_vector()) {
+            return Err(Error::signature_mismatch(
+                params,
+                &vec![types::I64; self.len()],
+            ));
+        }
+        Ok(self)
+    }
+}
+
+impl WasmArgs for &[Value] {
+    fn encode(self, params: &[ir::Type]) -> Result<Vec<i64>, Error> {
+        let found: Vec<_> = self.iter().map(Value::ty).collect();
+        if found != params {
+            return Err(Error::signature_mismatch(params, &found));
+        }
+        Ok(self.iter().map(Value::to_raw).collect())
+    }
+}
+
+/// Arguments of a wasm function call whose types are known statically, so
+/// they can be checked once for all the calls of a `TypedFunc`.
+pub trait WasmParams: WasmArgs {
+    /// The wasm parameter types of the arguments.
+    fn types() -> Vec<ir::Type>;
+
+    /// Encode the arguments into the argument slots of the trampoline,
+    /// without checking them.
+    fn into_raw(self) -> Vec<i64>;
+}
+
+/// A single wasm result whose type is known statically.
+///
+/// The trampoline stores each result in a 16 bytes slot, taken as the `u128`
+/// of its little-endian bytes: a scalar is in the low bits, a `v128` has its
+/// bytes in wasm memory order, lane 0 in the low bits, as `v128.store` would
+/// write it. The order does not depend on the host.
+pub trait WasmResult: Sized {
+    /// The wasm result type.
+    fn ty() -> ir::Type;
+
+    /// Decode the result from its slot.
+    fn from_slot(slot: u128) -> Self;
+}
+
+impl<T: WasmTy> WasmResult for T {
+    fn ty() -> ir::Type {
+        T::ty()
+    }
+    fn from_slot(slot: u128) -> Self {
+        T::from_raw(slot as i64)
+    }
+}
+
+/// A `v128` result, lane 0 in the low bits.
+impl WasmResult for u128 {
+    fn ty() -> ir::Type {
+        types::I8X16
+    }
+    fn from_slot(slot: u128) -> Self {
+        slot
+    }
+}
+
+/// A `v128` result, in wasm memory order.
+impl WasmResult for [u8; 16] {
+    fn ty() -> ir::Type {
+        types::I8X16
+    }
+    fn from_slot(slot: u128) -> Self {
+        slot.to_le_bytes()
+    }
+}
+
+/// Results of a wasm function call whose types are known statically: none,
+/// a single one or a tuple of them.
+pub trait WasmResults: Sized {
+    /// The wasm result types.
+    fn types() -> Vec<ir::Type>;
+
+    /// Decode the results from their slots, see `WasmResult`.
+    fn from_slots(slots: &[u128]) -> Self;
+}
+
+impl<T: WasmResult> WasmResults for T {
+    fn types() -> Vec<ir::Type> {
+        vec![T::ty()]
+    }
+    fn from_slots(slots: &[u128]) -> Self {
+        T::from_slot(slots[0])
+    }
+}
+
+macro_rules! impl_wasm_results {
+    ($($ret:ident $index:tt)*) => {
+        impl<$($ret: WasmResult,)*> WasmResults for ($($ret,)*) {
+            fn types() -> Vec<ir::Type> {
+                vec![$($ret::ty(),)*]
+            }
+            #[allow(unused_variables)]
+            fn from_slots(slots: &[u128]) -> Self {
+                ($($ret::from_slot(slots[$index]),)*)
+            }
+        }
+    };
+}
+
+impl_wasm_results!();
+impl_wasm_results!(A 0 B 1);
+impl_wasm_results!(A 0 B 1 C 2);
+impl_wasm_results!(A 0 B 1 C 2 D 3);
+
+macro_rules! impl_wasm_args {
+    ($($arg:ident)*) => {
+        #[allow(non_snake_case)]
+        impl<$($arg: WasmTy,)*> WasmArgs for ($($arg,)*) {
+            fn encode(self, params: &[ir::Type]) -> Result<Vec<i64>, Error> {
+                let found = [$($arg::ty(),)*];
+                if found[..] != params[..] {
+                    return Err(Error::signature_mismatch(params, &found));
+                }
+                Ok(self.into_raw())
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<$($arg: WasmTy,)*> WasmParams for ($($arg,)*) {
+            fn types() -> Vec<ir::Type> {
+                vec![$($arg::ty(),)*]
+            }
+            fn into_raw(self) -> Vec<i64> {
+                let ($($arg,)*) = self;
+                vec![$($arg.to_raw(),)*]
+            }
+        }
+    };
+}
+
+impl_wasm_args!();
+impl_wasm_args!(A);
+impl_wasm_args!(A B);
+impl_wasm_args!(A B C);
+impl_wasm_args!(A B C D);
+impl_wasm_args!(A B C D E);
+impl_wasm_args!(A B C D E F);
@@ -331,17 +331,25 @@ fn result_from_error(error: Error) -> wasmjit_result_t {
             kind: wasmjit_result_err_compile,
             msg: bytes_from_vec(comp.to_string().into_bytes()),
         },
+        Error::Parse(parse) => wasmjit_result_t {
+            kind: wasmjit_result_err_compile,
+            msg: bytes_from_vec(parse.to_string().into_bytes()),
+        },
         Error::Link(link) => wasmjit_result_t {
             kind: wasmjit_result_err_link,
             msg: bytes_from_vec(link.into_bytes()),
         },
+        Error::SignatureMismatch { .. } => wasmjit_result_t {
+            kind: wasmjit_result_err_internal,
+            msg: bytes_from_vec(error.to_string().into_bytes()),
+        },
         Error::Internal(intern) => wasmjit_result_t {
             kind: wasmjit_result_err_internal,
             msg: bytes_from_vec(intern.into_bytes()),
         },
         Error::Trap(trap) => wasmjit_result_t {
             kind: wasmjit_result_err_trap,
-            msg: bytes_from_vec(trap.into_bytes()),
+            msg: bytes_from_vec(trap.message.into_bytes()),
         },
     }
 }
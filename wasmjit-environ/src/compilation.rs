@@ -143,6 +143,18 @@ pub enum CompileError {
     #[fail(display = "WebAssembly translation error: {}", _0)]
     Wasm(WasmError),
 
+    /// A wasm translation error occured in a function named in the name section.
+    #[fail(
+        display = "WebAssembly translation error in function {}: {}",
+        name, error
+    )]
+    Function {
+        /// Name of the function.
+        name: String,
+        /// The translation error.
+        error: WasmError,
+    },
+
     /// A compilation error occured.
     #[fail(display = "Compilation error: {}", _0)]
     Codegen(CodegenError),
@@ -10,16 +10,20 @@ use crate::compilation::{
 use crate::func_environ::{
     get_func_name, get_memory32_grow_name, get_memory32_size_name, BuildOption, FuncEnvironment,
 };
-use crate::module::Module;
+use crate::module::{Module, TrapSites};
 use crate::module_environ::FunctionBodyData;
+use crate::tunables::Tunables;
 use alloc::vec::Vec;
 use cranelift_codegen::binemit;
 use cranelift_codegen::ir;
 use cranelift_codegen::ir::ExternalName;
 use cranelift_codegen::isa;
 use cranelift_codegen::Context;
-use cranelift_entity::PrimaryMap;
-use cranelift_wasm::{DefinedFuncIndex, FuncIndex, FuncTranslator, ModuleTranslationState};
+use cranelift_entity::{EntityRef, PrimaryMap};
+use cranelift_wasm::{
+    DefinedFuncIndex, FuncIndex, FuncTranslator, ModuleTranslationState, WasmError,
+};
+use wasmparser::FunctionBody;
 
 /// Implementation of a relocation sink that just saves all the information for later
 pub struct RelocSink {
@@ -161,8 +165,103 @@ fn get_function_address_map<'data>(
     }
 }
 
+/// Translate the defined function `index` to cranelift IR, without compiling
+/// it nor translating the other functions, to inspect the IR of a function.
+pub fn translate_function(
+    module: &Module,
+    module_translate_state: &ModuleTranslationState,
+    index: DefinedFuncIndex,
+    input: &FunctionBodyData,
+    target_config: isa::TargetFrontendConfig,
+    build_option: BuildOption,
+    tunables: &Tunables,
+) -> Result<ir::Function, CompileError> {
+    let mut func = ir::Function::new();
+    translate_function_into(
+        &mut func,
+        module,
+        module_translate_state,
+        index,
+        input,
+        target_config,
+        &build_option,
+        tunables,
+    )?;
+    Ok(func)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn translate_function_into(
+    func: &mut ir::Function,
+    module: &Module,
+    module_translate_state: &ModuleTranslationState,
+    index: DefinedFuncIndex,
+    input: &FunctionBodyData,
+    target_config: isa::TargetFrontendConfig,
+    build_option: &BuildOption,
+    tunables: &Tunables,
+) -> Result<TrapSites, CompileError> {
+    let func_index = module.func_index(index);
+    func.name = get_func_name(func_index);
+    func.signature = module.signatures[module.functions[func_index]].clone();
+
+    let mut environ = FuncEnvironment::new(target_config, module, build_option.clone(), tunables);
+    let mut trans = FuncTranslator::new();
+    trans
+        .translate(
+            module_translate_state,
+            input.data,
+            input.module_offset,
+            func,
+            &mut environ,
+        )
+        .map_err(|error| locate_unsupported(error, index, input, environ.operators_seen()))
+        .map_err(|error| match module.func_name(func_index) {
+            Some(name) => CompileError::Function {
+                name: name.to_string(),
+                error,
+            },
+            None => CompileError::Wasm(error),
+        })?;
+    Ok(environ.into_trap_sites())
+}
+
+/// Add the location of the operator to an unsupported operator error of the
+/// defined function `index`, the operator being the `count`th of `input`.
+fn locate_unsupported(
+    error: WasmError,
+    index: DefinedFuncIndex,
+    input: &FunctionBodyData,
+    count: usize,
+) -> WasmError {
+    let message = match error {
+        WasmError::Unsupported(message) if count > 0 => message,
+        error => return error,
+    };
+    match operator_offset(input, count - 1) {
+        Some(offset) => WasmError::Unsupported(format!(
+            "{} in defined function {} at offset {} of its body (module offset {})",
+            message,
+            index.index(),
+            offset - input.module_offset,
+            offset
+        )),
+        None => WasmError::Unsupported(message),
+    }
+}
+
+/// Module offset of the operator `n` of a function body.
+fn operator_offset(input: &FunctionBodyData, n: usize) -> Option<usize> {
+    let body = FunctionBody::new(input.module_offset, input.data);
+    let mut reader = body.get_operators_reader().ok()?;
+    for _ in 0..n {
+        reader.read().ok()?;
+    }
+    reader.read_with_offset().ok().map(|(_, offset)| offset)
+}
+
 /// Compile the module using Cranelift, producing a compilation result with
-/// associated relocations.
+/// associated relocations, and the operators which may trap.
 #[allow(clippy::type_complexity)]
 pub fn compile_module<'data, 'module>(
     module: &'module Module,
@@ -171,6 +270,7 @@ pub fn compile_module<'data, 'module>(
     isa: &dyn isa::TargetIsa,
     generate_debug_info: bool,
     build_option: BuildOption,
+    tunables: &Tunables,
 ) -> Result<
     (
         Compilation,
@@ -179,6 +279,7 @@ pub fn compile_module<'data, 'module>(
         ValueLabelsRanges,
         PrimaryMap<DefinedFuncIndex, ir::StackSlots>,
         Traps,
+        TrapSites,
     ),
     CompileError,
 > {
@@ -188,28 +289,27 @@ pub fn compile_module<'data, 'module>(
     let mut value_ranges = PrimaryMap::with_capacity(function_body_inputs.len());
     let mut stack_slots = PrimaryMap::with_capacity(function_body_inputs.len());
     let mut traps = PrimaryMap::with_capacity(function_body_inputs.len());
+    let mut trap_sites = TrapSites::default();
 
     function_body_inputs
         .into_iter()
         .map(|(index, input)| {
             let func_index = module.func_index(index);
             let mut context = Context::new();
-            context.func.name = get_func_name(func_index);
-            context.func.signature = module.signatures[module.functions[func_index]].clone();
             if generate_debug_info {
                 context.func.collect_debug_info();
             }
 
-            let mut trans = FuncTranslator::new();
-            trans
-                .translate(
-                    &module_translate_state,
-                    input.data,
-                    input.module_offset,
-                    &mut context.func,
-                    &mut FuncEnvironment::new(isa.frontend_config(), module, build_option),
-                )
-                .map_err(CompileError::Wasm)?;
+            let func_trap_sites = translate_function_into(
+                &mut context.func,
+                module,
+                module_translate_state,
+                index,
+                input,
+                isa.frontend_config(),
+                &build_option,
+                tunables,
+            )?;
 
             if generate_debug_info {
                 log::warn!("translate func: \n{}", context.func);
@@ -258,6 +358,7 @@ pub fn compile_module<'data, 'module>(
                 ranges,
                 stack_slots,
                 trap_sink.traps,
+                func_trap_sites,
             ))
         })
         .collect::<Result<Vec<_>, CompileError>>()?
@@ -271,6 +372,7 @@ pub fn compile_module<'data, 'module>(
                 ranges,
                 sss,
                 function_traps,
+                mut func_trap_sites,
             )| {
                 functions.push(CodeAndJTOffsets {
                     body: function,
@@ -283,6 +385,7 @@ pub fn compile_module<'data, 'module>(
                 value_ranges.push(ranges.unwrap_or_default());
                 stack_slots.push(sss);
                 traps.push(function_traps);
+                trap_sites.append(&mut func_trap_sites);
             },
         );
 
@@ -295,5 +398,6 @@ pub fn compile_module<'data, 'module>(
         value_ranges,
         stack_slots,
         traps,
+        trap_sites,
     ))
 }
@@ -1,10 +1,12 @@
-use crate::module::{MemoryPlan, MemoryStyle, Module};
+use crate::module::{MemoryPlan, MemoryStyle, Module, TableStyle, TrapSites};
+use crate::module_environ::memory_access_size;
+use crate::tunables::{Feature, Tunables};
 use crate::vmoffsets::VMOffsets;
 use crate::WASM_PAGE_SIZE;
 use alloc::vec::Vec;
 use core::clone::Clone;
 use core::convert::TryFrom;
-use cranelift_codegen::cursor::FuncCursor;
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
 use cranelift_codegen::ir;
 use cranelift_codegen::ir::condcodes::*;
 use cranelift_codegen::ir::immediates::{Offset32, Uimm64};
@@ -14,10 +16,12 @@ use cranelift_codegen::isa::TargetFrontendConfig;
 use cranelift_entity::EntityRef;
 use cranelift_wasm::{
     self, FuncIndex, FuncTranslationState, GlobalIndex, GlobalVariable, MemoryIndex,
-    SignatureIndex, TableIndex, WasmResult,
+    SignatureIndex, TableIndex, WasmError, WasmResult,
 };
 
 use cranelift_frontend::FunctionBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use wasmparser::Operator;
 
 /// Compute an `ir::ExternalName` for a given wasm function index.
@@ -37,6 +41,425 @@ pub fn get_memory32_size_name() -> ir::ExternalName {
     ir::ExternalName::user(1, 1)
 }
 
+/// Return the name of an operator, without its immediates, for the error
+/// messages. The operators are classified by their variants, the name is only
+/// computed once a module is rejected.
+fn operator_name(op: &Operator) -> String {
+    let mut name = format!("{:?}", op);
+    if let Some(end) = name.find(|c: char| !c.is_ascii_alphanumeric()) {
+        name.truncate(end);
+    }
+    name
+}
+
+/// Whether the operator involves floating point values.
+pub(crate) fn is_float_operator(op: &Operator) -> bool {
+    match op {
+        Operator::F32Load { .. }
+        | Operator::F64Load { .. }
+        | Operator::F32Store { .. }
+        | Operator::F64Store { .. }
+        | Operator::F32Const { .. }
+        | Operator::F64Const { .. }
+        | Operator::F32Eq
+        | Operator::F32Ne
+        | Operator::F32Lt
+        | Operator::F32Gt
+        | Operator::F32Le
+        | Operator::F32Ge
+        | Operator::F64Eq
+        | Operator::F64Ne
+        | Operator::F64Lt
+        | Operator::F64Gt
+        | Operator::F64Le
+        | Operator::F64Ge
+        | Operator::F32Abs
+        | Operator::F32Neg
+        | Operator::F32Ceil
+        | Operator::F32Floor
+        | Operator::F32Trunc
+        | Operator::F32Nearest
+        | Operator::F32Sqrt
+        | Operator::F32Add
+        | Operator::F32Sub
+        | Operator::F32Mul
+        | Operator::F32Div
+        | Operator::F32Min
+        | Operator::F32Max
+        | Operator::F32Copysign
+        | Operator::F64Abs
+        | Operator::F64Neg
+        | Operator::F64Ceil
+        | Operator::F64Floor
+        | Operator::F64Trunc
+        | Operator::F64Nearest
+        | Operator::F64Sqrt
+        | Operator::F64Add
+        | Operator::F64Sub
+        | Operator::F64Mul
+        | Operator::F64Div
+        | Operator::F64Min
+        | Operator::F64Max
+        | Operator::F64Copysign
+        | Operator::I32TruncF32S
+        | Operator::I32TruncF32U
+        | Operator::I32TruncF64S
+        | Operator::I32TruncF64U
+        | Operator::I64TruncF32S
+        | Operator::I64TruncF32U
+        | Operator::I64TruncF64S
+        | Operator::I64TruncF64U
+        | Operator::F32ConvertI32S
+        | Operator::F32ConvertI32U
+        | Operator::F32ConvertI64S
+        | Operator::F32ConvertI64U
+        | Operator::F32DemoteF64
+        | Operator::F64ConvertI32S
+        | Operator::F64ConvertI32U
+        | Operator::F64ConvertI64S
+        | Operator::F64ConvertI64U
+        | Operator::F64PromoteF32
+        | Operator::I32ReinterpretF32
+        | Operator::I64ReinterpretF64
+        | Operator::F32ReinterpretI32
+        | Operator::F64ReinterpretI64 => true,
+        _ => is_saturating_operator(op) || is_float_lane_operator(op),
+    }
+}
+
+/// Whether the operator comes from the fixed-width SIMD proposal.
+fn is_simd_operator(op: &Operator) -> bool {
+    match op {
+        Operator::V128Load { .. }
+        | Operator::V128Store { .. }
+        | Operator::V128Const { .. }
+        | Operator::V8x16Shuffle { .. }
+        | Operator::V8x16Swizzle
+        | Operator::I8x16LoadSplat { .. }
+        | Operator::I16x8LoadSplat { .. }
+        | Operator::I32x4LoadSplat { .. }
+        | Operator::I64x2LoadSplat { .. }
+        | Operator::I8x16Splat
+        | Operator::I8x16ExtractLaneS { .. }
+        | Operator::I8x16ExtractLaneU { .. }
+        | Operator::I8x16ReplaceLane { .. }
+        | Operator::I16x8Splat
+        | Operator::I16x8ExtractLaneS { .. }
+        | Operator::I16x8ExtractLaneU { .. }
+        | Operator::I16x8ReplaceLane { .. }
+        | Operator::I32x4Splat
+        | Operator::I32x4ExtractLane { .. }
+        | Operator::I32x4ReplaceLane { .. }
+        | Operator::I64x2Splat
+        | Operator::I64x2ExtractLane { .. }
+        | Operator::I64x2ReplaceLane { .. }
+        | Operator::I8x16Eq
+        | Operator::I8x16Ne
+        | Operator::I8x16LtS
+        | Operator::I8x16LtU
+        | Operator::I8x16GtS
+        | Operator::I8x16GtU
+        | Operator::I8x16LeS
+        | Operator::I8x16LeU
+        | Operator::I8x16GeS
+        | Operator::I8x16GeU
+        | Operator::I16x8Eq
+        | Operator::I16x8Ne
+        | Operator::I16x8LtS
+        | Operator::I16x8LtU
+        | Operator::I16x8GtS
+        | Operator::I16x8GtU
+        | Operator::I16x8LeS
+        | Operator::I16x8LeU
+        | Operator::I16x8GeS
+        | Operator::I16x8GeU
+        | Operator::I32x4Eq
+        | Operator::I32x4Ne
+        | Operator::I32x4LtS
+        | Operator::I32x4LtU
+        | Operator::I32x4GtS
+        | Operator::I32x4GtU
+        | Operator::I32x4LeS
+        | Operator::I32x4LeU
+        | Operator::I32x4GeS
+        | Operator::I32x4GeU
+        | Operator::V128Not
+        | Operator::V128And
+        | Operator::V128Or
+        | Operator::V128Xor
+        | Operator::V128Bitselect
+        | Operator::I8x16Neg
+        | Operator::I8x16AnyTrue
+        | Operator::I8x16AllTrue
+        | Operator::I8x16Shl
+        | Operator::I8x16ShrS
+        | Operator::I8x16ShrU
+        | Operator::I8x16Add
+        | Operator::I8x16AddSaturateS
+        | Operator::I8x16AddSaturateU
+        | Operator::I8x16Sub
+        | Operator::I8x16SubSaturateS
+        | Operator::I8x16SubSaturateU
+        | Operator::I8x16Mul
+        | Operator::I16x8Neg
+        | Operator::I16x8AnyTrue
+        | Operator::I16x8AllTrue
+        | Operator::I16x8Shl
+        | Operator::I16x8ShrS
+        | Operator::I16x8ShrU
+        | Operator::I16x8Add
+        | Operator::I16x8AddSaturateS
+        | Operator::I16x8AddSaturateU
+        | Operator::I16x8Sub
+        | Operator::I16x8SubSaturateS
+        | Operator::I16x8SubSaturateU
+        | Operator::I16x8Mul
+        | Operator::I32x4Neg
+        | Operator::I32x4AnyTrue
+        | Operator::I32x4AllTrue
+        | Operator::I32x4Shl
+        | Operator::I32x4ShrS
+        | Operator::I32x4ShrU
+        | Operator::I32x4Add
+        | Operator::I32x4Sub
+        | Operator::I32x4Mul
+        | Operator::I64x2Neg
+        | Operator::I64x2AnyTrue
+        | Operator::I64x2AllTrue
+        | Operator::I64x2Shl
+        | Operator::I64x2ShrS
+        | Operator::I64x2ShrU
+        | Operator::I64x2Add
+        | Operator::I64x2Sub => true,
+        _ => is_float_lane_operator(op),
+    }
+}
+
+/// Whether the operator is a SIMD operator on float lanes, or converting
+/// between float and integer lanes.
+fn is_float_lane_operator(op: &Operator) -> bool {
+    match op {
+        Operator::F32x4Splat
+        | Operator::F32x4ExtractLane { .. }
+        | Operator::F32x4ReplaceLane { .. }
+        | Operator::F64x2Splat
+        | Operator::F64x2ExtractLane { .. }
+        | Operator::F64x2ReplaceLane { .. }
+        | Operator::F32x4Eq
+        | Operator::F32x4Ne
+        | Operator::F32x4Lt
+        | Operator::F32x4Gt
+        | Operator::F32x4Le
+        | Operator::F32x4Ge
+        | Operator::F64x2Eq
+        | Operator::F64x2Ne
+        | Operator::F64x2Lt
+        | Operator::F64x2Gt
+        | Operator::F64x2Le
+        | Operator::F64x2Ge
+        | Operator::F32x4Abs
+        | Operator::F32x4Neg
+        | Operator::F32x4Sqrt
+        | Operator::F32x4Add
+        | Operator::F32x4Sub
+        | Operator::F32x4Mul
+        | Operator::F32x4Div
+        | Operator::F32x4Min
+        | Operator::F32x4Max
+        | Operator::F64x2Abs
+        | Operator::F64x2Neg
+        | Operator::F64x2Sqrt
+        | Operator::F64x2Add
+        | Operator::F64x2Sub
+        | Operator::F64x2Mul
+        | Operator::F64x2Div
+        | Operator::F64x2Min
+        | Operator::F64x2Max
+        | Operator::I32x4TruncSF32x4Sat
+        | Operator::I32x4TruncUF32x4Sat
+        | Operator::I64x2TruncSF64x2Sat
+        | Operator::I64x2TruncUF64x2Sat
+        | Operator::F32x4ConvertSI32x4
+        | Operator::F32x4ConvertUI32x4
+        | Operator::F64x2ConvertSI64x2
+        | Operator::F64x2ConvertUI64x2 => true,
+        _ => false,
+    }
+}
+
+/// Whether the operator is a scalar saturating float to int conversion.
+fn is_saturating_operator(op: &Operator) -> bool {
+    match op {
+        Operator::I32TruncSatF32S
+        | Operator::I32TruncSatF32U
+        | Operator::I32TruncSatF64S
+        | Operator::I32TruncSatF64U
+        | Operator::I64TruncSatF32S
+        | Operator::I64TruncSatF32U
+        | Operator::I64TruncSatF64S
+        | Operator::I64TruncSatF64U => true,
+        _ => false,
+    }
+}
+
+/// Whether the operator comes from the reference types proposal.
+fn is_reference_type_operator(op: &Operator) -> bool {
+    match op {
+        Operator::RefNull
+        | Operator::RefIsNull
+        | Operator::TableGet { .. }
+        | Operator::TableSet { .. }
+        | Operator::TableGrow { .. }
+        | Operator::TableSize { .. } => true,
+        _ => false,
+    }
+}
+
+/// Whether the operator comes from the threads proposal.
+fn is_atomic_operator(op: &Operator) -> bool {
+    match op {
+        Operator::Wake { .. }
+        | Operator::I32Wait { .. }
+        | Operator::I64Wait { .. }
+        | Operator::Fence { .. }
+        | Operator::I32AtomicLoad { .. }
+        | Operator::I64AtomicLoad { .. }
+        | Operator::I32AtomicLoad8U { .. }
+        | Operator::I32AtomicLoad16U { .. }
+        | Operator::I64AtomicLoad8U { .. }
+        | Operator::I64AtomicLoad16U { .. }
+        | Operator::I64AtomicLoad32U { .. }
+        | Operator::I32AtomicStore { .. }
+        | Operator::I64AtomicStore { .. }
+        | Operator::I32AtomicStore8 { .. }
+        | Operator::I32AtomicStore16 { .. }
+        | Operator::I64AtomicStore8 { .. }
+        | Operator::I64AtomicStore16 { .. }
+        | Operator::I64AtomicStore32 { .. }
+        | Operator::I32AtomicRmwAdd { .. }
+        | Operator::I64AtomicRmwAdd { .. }
+        | Operator::I32AtomicRmw8UAdd { .. }
+        | Operator::I32AtomicRmw16UAdd { .. }
+        | Operator::I64AtomicRmw8UAdd { .. }
+        | Operator::I64AtomicRmw16UAdd { .. }
+        | Operator::I64AtomicRmw32UAdd { .. }
+        | Operator::I32AtomicRmwSub { .. }
+        | Operator::I64AtomicRmwSub { .. }
+        | Operator::I32AtomicRmw8USub { .. }
+        | Operator::I32AtomicRmw16USub { .. }
+        | Operator::I64AtomicRmw8USub { .. }
+        | Operator::I64AtomicRmw16USub { .. }
+        | Operator::I64AtomicRmw32USub { .. }
+        | Operator::I32AtomicRmwAnd { .. }
+        | Operator::I64AtomicRmwAnd { .. }
+        | Operator::I32AtomicRmw8UAnd { .. }
+        | Operator::I32AtomicRmw16UAnd { .. }
+        | Operator::I64AtomicRmw8UAnd { .. }
+        | Operator::I64AtomicRmw16UAnd { .. }
+        | Operator::I64AtomicRmw32UAnd { .. }
+        | Operator::I32AtomicRmwOr { .. }
+        | Operator::I64AtomicRmwOr { .. }
+        | Operator::I32AtomicRmw8UOr { .. }
+        | Operator::I32AtomicRmw16UOr { .. }
+        | Operator::I64AtomicRmw8UOr { .. }
+        | Operator::I64AtomicRmw16UOr { .. }
+        | Operator::I64AtomicRmw32UOr { .. }
+        | Operator::I32AtomicRmwXor { .. }
+        | Operator::I64AtomicRmwXor { .. }
+        | Operator::I32AtomicRmw8UXor { .. }
+        | Operator::I32AtomicRmw16UXor { .. }
+        | Operator::I64AtomicRmw8UXor { .. }
+        | Operator::I64AtomicRmw16UXor { .. }
+        | Operator::I64AtomicRmw32UXor { .. }
+        | Operator::I32AtomicRmwXchg { .. }
+        | Operator::I64AtomicRmwXchg { .. }
+        | Operator::I32AtomicRmw8UXchg { .. }
+        | Operator::I32AtomicRmw16UXchg { .. }
+        | Operator::I64AtomicRmw8UXchg { .. }
+        | Operator::I64AtomicRmw16UXchg { .. }
+        | Operator::I64AtomicRmw32UXchg { .. }
+        | Operator::I32AtomicRmwCmpxchg { .. }
+        | Operator::I64AtomicRmwCmpxchg { .. }
+        | Operator::I32AtomicRmw8UCmpxchg { .. }
+        | Operator::I32AtomicRmw16UCmpxchg { .. }
+        | Operator::I64AtomicRmw8UCmpxchg { .. }
+        | Operator::I64AtomicRmw16UCmpxchg { .. }
+        | Operator::I64AtomicRmw32UCmpxchg { .. } => true,
+        _ => false,
+    }
+}
+
+/// Whether the operator comes from the bulk memory proposal.
+fn is_bulk_memory_operator(op: &Operator) -> bool {
+    match op {
+        Operator::MemoryCopy
+        | Operator::MemoryFill
+        | Operator::MemoryInit { .. }
+        | Operator::DataDrop { .. }
+        | Operator::TableCopy
+        | Operator::TableInit { .. }
+        | Operator::ElemDrop { .. } => true,
+        _ => false,
+    }
+}
+
+/// Whether the operator comes from the sign-extension proposal.
+fn is_sign_extension_operator(op: &Operator) -> bool {
+    match op {
+        Operator::I32Extend8S
+        | Operator::I32Extend16S
+        | Operator::I64Extend8S
+        | Operator::I64Extend16S
+        | Operator::I64Extend32S => true,
+        _ => false,
+    }
+}
+
+/// Whether the operator may trap: the memory accesses, the integer divisions
+/// and the float to int conversions which do not saturate.
+fn may_trap(op: &Operator) -> bool {
+    match op {
+        Operator::I32DivS
+        | Operator::I32DivU
+        | Operator::I32RemS
+        | Operator::I32RemU
+        | Operator::I64DivS
+        | Operator::I64DivU
+        | Operator::I64RemS
+        | Operator::I64RemU
+        | Operator::I32TruncF32S
+        | Operator::I32TruncF32U
+        | Operator::I32TruncF64S
+        | Operator::I32TruncF64U
+        | Operator::I64TruncF32S
+        | Operator::I64TruncF32U
+        | Operator::I64TruncF64S
+        | Operator::I64TruncF64U => true,
+        _ => memory_access_size(op).is_some(),
+    }
+}
+
+/// The proposal the operator comes from, if not the MVP.
+fn operator_feature(op: &Operator) -> Option<Feature> {
+    if is_simd_operator(op) {
+        Some(Feature::Simd)
+    } else if is_sign_extension_operator(op) {
+        Some(Feature::SignExtension)
+    } else if is_saturating_operator(op) {
+        Some(Feature::SaturatingFloatToInt)
+    } else if is_bulk_memory_operator(op) {
+        Some(Feature::BulkMemory)
+    } else if is_reference_type_operator(op) {
+        // The tables only hold funcref and the runtime has no representation
+        // for host references, see the limitations in the README.
+        Some(Feature::ReferenceTypes)
+    } else if is_atomic_operator(op) {
+        Some(Feature::Threads)
+    } else {
+        None
+    }
+}
+
 /// An index type for builtin functions.
 pub struct BuiltinFunctionIndex(u32);
 
@@ -58,10 +481,19 @@ impl BuiltinFunctionIndex {
     pub const fn get_check_depth_index() -> Self {
         Self(3)
     }
+    /// Returns an index for `trace_host_call` builtin function.
+    pub const fn get_trace_host_call_index() -> Self {
+        Self(4)
+    }
+
+    /// Returns an index for `charge_host_call` builtin function.
+    pub const fn get_charge_host_call_index() -> Self {
+        Self(5)
+    }
 
     /// Returns the total number of builtin functions.
     pub const fn builtin_functions_total_number() -> u32 {
-        4
+        6
     }
 
     /// Return the index as an u32 number.
@@ -70,11 +502,297 @@ impl BuiltinFunctionIndex {
     }
 }
 
+/// The binary opcode of an operator, keying `GasCostTable::opcode_weights`:
+/// the opcode byte, or for the prefixed operators the prefix byte followed by
+/// the sub-opcode byte, such as `0xFC00` for `i32.trunc_sat_f32_s`.
+///
+/// Only the operators the table can weigh have one: those of the MVP, the
+/// sign-extension and the saturating conversions. The operators of the other
+/// proposals are charged their class cost.
+pub fn operator_opcode(op: &Operator) -> Option<u32> {
+    let opcode = match op {
+        Operator::Unreachable => 0x00,
+        Operator::Nop => 0x01,
+        Operator::Block { .. } => 0x02,
+        Operator::Loop { .. } => 0x03,
+        Operator::If { .. } => 0x04,
+        Operator::Else => 0x05,
+        Operator::End => 0x0B,
+        Operator::Br { .. } => 0x0C,
+        Operator::BrIf { .. } => 0x0D,
+        Operator::BrTable { .. } => 0x0E,
+        Operator::Return => 0x0F,
+        Operator::Call { .. } => 0x10,
+        Operator::CallIndirect { .. } => 0x11,
+        Operator::Drop => 0x1A,
+        Operator::Select => 0x1B,
+        Operator::LocalGet { .. } => 0x20,
+        Operator::LocalSet { .. } => 0x21,
+        Operator::LocalTee { .. } => 0x22,
+        Operator::GlobalGet { .. } => 0x23,
+        Operator::GlobalSet { .. } => 0x24,
+        Operator::I32Load { .. } => 0x28,
+        Operator::I64Load { .. } => 0x29,
+        Operator::F32Load { .. } => 0x2A,
+        Operator::F64Load { .. } => 0x2B,
+        Operator::I32Load8S { .. } => 0x2C,
+        Operator::I32Load8U { .. } => 0x2D,
+        Operator::I32Load16S { .. } => 0x2E,
+        Operator::I32Load16U { .. } => 0x2F,
+        Operator::I64Load8S { .. } => 0x30,
+        Operator::I64Load8U { .. } => 0x31,
+        Operator::I64Load16S { .. } => 0x32,
+        Operator::I64Load16U { .. } => 0x33,
+        Operator::I64Load32S { .. } => 0x34,
+        Operator::I64Load32U { .. } => 0x35,
+        Operator::I32Store { .. } => 0x36,
+        Operator::I64Store { .. } => 0x37,
+        Operator::F32Store { .. } => 0x38,
+        Operator::F64Store { .. } => 0x39,
+        Operator::I32Store8 { .. } => 0x3A,
+        Operator::I32Store16 { .. } => 0x3B,
+        Operator::I64Store8 { .. } => 0x3C,
+        Operator::I64Store16 { .. } => 0x3D,
+        Operator::I64Store32 { .. } => 0x3E,
+        Operator::MemorySize { .. } => 0x3F,
+        Operator::MemoryGrow { .. } => 0x40,
+        Operator::I32Const { .. } => 0x41,
+        Operator::I64Const { .. } => 0x42,
+        Operator::F32Const { .. } => 0x43,
+        Operator::F64Const { .. } => 0x44,
+        Operator::I32Eqz => 0x45,
+        Operator::I32Eq => 0x46,
+        Operator::I32Ne => 0x47,
+        Operator::I32LtS => 0x48,
+        Operator::I32LtU => 0x49,
+        Operator::I32GtS => 0x4A,
+        Operator::I32GtU => 0x4B,
+        Operator::I32LeS => 0x4C,
+        Operator::I32LeU => 0x4D,
+        Operator::I32GeS => 0x4E,
+        Operator::I32GeU => 0x4F,
+        Operator::I64Eqz => 0x50,
+        Operator::I64Eq => 0x51,
+        Operator::I64Ne => 0x52,
+        Operator::I64LtS => 0x53,
+        Operator::I64LtU => 0x54,
+        Operator::I64GtS => 0x55,
+        Operator::I64GtU => 0x56,
+        Operator::I64LeS => 0x57,
+        Operator::I64LeU => 0x58,
+        Operator::I64GeS => 0x59,
+        Operator::I64GeU => 0x5A,
+        Operator::F32Eq => 0x5B,
+        Operator::F32Ne => 0x5C,
+        Operator::F32Lt => 0x5D,
+        Operator::F32Gt => 0x5E,
+        Operator::F32Le => 0x5F,
+        Operator::F32Ge => 0x60,
+        Operator::F64Eq => 0x61,
+        Operator::F64Ne => 0x62,
+        Operator::F64Lt => 0x63,
+        Operator::F64Gt => 0x64,
+        Operator::F64Le => 0x65,
+        Operator::F64Ge => 0x66,
+        Operator::I32Clz => 0x67,
+        Operator::I32Ctz => 0x68,
+        Operator::I32Popcnt => 0x69,
+        Operator::I32Add => 0x6A,
+        Operator::I32Sub => 0x6B,
+        Operator::I32Mul => 0x6C,
+        Operator::I32DivS => 0x6D,
+        Operator::I32DivU => 0x6E,
+        Operator::I32RemS => 0x6F,
+        Operator::I32RemU => 0x70,
+        Operator::I32And => 0x71,
+        Operator::I32Or => 0x72,
+        Operator::I32Xor => 0x73,
+        Operator::I32Shl => 0x74,
+        Operator::I32ShrS => 0x75,
+        Operator::I32ShrU => 0x76,
+        Operator::I32Rotl => 0x77,
+        Operator::I32Rotr => 0x78,
+        Operator::I64Clz => 0x79,
+        Operator::I64Ctz => 0x7A,
+        Operator::I64Popcnt => 0x7B,
+        Operator::I64Add => 0x7C,
+        Operator::I64Sub => 0x7D,
+        Operator::I64Mul => 0x7E,
+        Operator::I64DivS => 0x7F,
+        Operator::I64DivU => 0x80,
+        Operator::I64RemS => 0x81,
+        Operator::I64RemU => 0x82,
+        Operator::I64And => 0x83,
+        Operator::I64Or => 0x84,
+        Operator::I64Xor => 0x85,
+        Operator::I64Shl => 0x86,
+        Operator::I64ShrS => 0x87,
+        Operator::I64ShrU => 0x88,
+        Operator::I64Rotl => 0x89,
+        Operator::I64Rotr => 0x8A,
+        Operator::F32Abs => 0x8B,
+        Operator::F32Neg => 0x8C,
+        Operator::F32Ceil => 0x8D,
+        Operator::F32Floor => 0x8E,
+        Operator::F32Trunc => 0x8F,
+        Operator::F32Nearest => 0x90,
+        Operator::F32Sqrt => 0x91,
+        Operator::F32Add => 0x92,
+        Operator::F32Sub => 0x93,
+        Operator::F32Mul => 0x94,
+        Operator::F32Div => 0x95,
+        Operator::F32Min => 0x96,
+        Operator::F32Max => 0x97,
+        Operator::F32Copysign => 0x98,
+        Operator::F64Abs => 0x99,
+        Operator::F64Neg => 0x9A,
+        Operator::F64Ceil => 0x9B,
+        Operator::F64Floor => 0x9C,
+        Operator::F64Trunc => 0x9D,
+        Operator::F64Nearest => 0x9E,
+        Operator::F64Sqrt => 0x9F,
+        Operator::F64Add => 0xA0,
+        Operator::F64Sub => 0xA1,
+        Operator::F64Mul => 0xA2,
+        Operator::F64Div => 0xA3,
+        Operator::F64Min => 0xA4,
+        Operator::F64Max => 0xA5,
+        Operator::F64Copysign => 0xA6,
+        Operator::I32WrapI64 => 0xA7,
+        Operator::I32TruncF32S => 0xA8,
+        Operator::I32TruncF32U => 0xA9,
+        Operator::I32TruncF64S => 0xAA,
+        Operator::I32TruncF64U => 0xAB,
+        Operator::I64ExtendI32S => 0xAC,
+        Operator::I64ExtendI32U => 0xAD,
+        Operator::I64TruncF32S => 0xAE,
+        Operator::I64TruncF32U => 0xAF,
+        Operator::I64TruncF64S => 0xB0,
+        Operator::I64TruncF64U => 0xB1,
+        Operator::F32ConvertI32S => 0xB2,
+        Operator::F32ConvertI32U => 0xB3,
+        Operator::F32ConvertI64S => 0xB4,
+        Operator::F32ConvertI64U => 0xB5,
+        Operator::F32DemoteF64 => 0xB6,
+        Operator::F64ConvertI32S => 0xB7,
+        Operator::F64ConvertI32U => 0xB8,
+        Operator::F64ConvertI64S => 0xB9,
+        Operator::F64ConvertI64U => 0xBA,
+        Operator::F64PromoteF32 => 0xBB,
+        Operator::I32ReinterpretF32 => 0xBC,
+        Operator::I64ReinterpretF64 => 0xBD,
+        Operator::F32ReinterpretI32 => 0xBE,
+        Operator::F64ReinterpretI64 => 0xBF,
+        Operator::I32Extend8S => 0xC0,
+        Operator::I32Extend16S => 0xC1,
+        Operator::I64Extend8S => 0xC2,
+        Operator::I64Extend16S => 0xC3,
+        Operator::I64Extend32S => 0xC4,
+        Operator::I32TruncSatF32S => 0xFC00,
+        Operator::I32TruncSatF32U => 0xFC01,
+        Operator::I32TruncSatF64S => 0xFC02,
+        Operator::I32TruncSatF64U => 0xFC03,
+        Operator::I64TruncSatF32S => 0xFC04,
+        Operator::I64TruncSatF32U => 0xFC05,
+        Operator::I64TruncSatF64S => 0xFC06,
+        Operator::I64TruncSatF64U => 0xFC07,
+        _ => return None,
+    };
+    Some(opcode)
+}
+
+/// Gas cost of each class of operators, charged by the metering injection.
+///
+/// The fee schedule is decided by governance, so it is data for the
+/// compiler rather than constants in it.
+///
+/// Any cost may be zero, but a branch back to a `loop` is charged at least
+/// one gas, so a loop of free operators still runs out of gas and still sees
+/// the timeouts and cancellations of the call.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GasCostTable {
+    /// Numeric operators, constants, locals, globals and parametric operators.
+    pub arithmetic: u32,
+    /// Loads, stores, `memory.size` and `memory.grow`.
+    pub memory: u32,
+    /// Direct and indirect calls.
+    pub call: u32,
+    /// Blocks, branches, `return`, `unreachable` and `nop`.
+    pub control: u32,
+    /// Charged for each page by `memory.grow`, on top of its `memory` cost.
+    pub grow: u32,
+    /// Weight of individual operators keyed by their opcode, see
+    /// `operator_opcode`, overriding the cost of their class.
+    #[serde(default)]
+    pub opcode_weights: BTreeMap<u32, u32>,
+}
+
+impl Default for GasCostTable {
+    /// Every operator costs one, growing the memory costs nothing more.
+    fn default() -> Self {
+        Self {
+            arithmetic: 1,
+            memory: 1,
+            call: 1,
+            control: 1,
+            grow: 0,
+            opcode_weights: BTreeMap::new(),
+        }
+    }
+}
+
+impl GasCostTable {
+    /// The cost of the operator `op`.
+    ///
+    /// The costs of the operators of a block are summed at translation time
+    /// and charged by a single gas check.
+    pub fn cost(&self, op: &Operator) -> u32 {
+        if !self.opcode_weights.is_empty() {
+            let weight = operator_opcode(op).and_then(|opcode| self.opcode_weights.get(&opcode));
+            if let Some(&weight) = weight {
+                return weight;
+            }
+        }
+        match op {
+            Operator::Call { .. } | Operator::CallIndirect { .. } => self.call,
+            Operator::Unreachable
+            | Operator::Nop
+            | Operator::Block { .. }
+            | Operator::Loop { .. }
+            | Operator::If { .. }
+            | Operator::Else
+            | Operator::End
+            | Operator::Br { .. }
+            | Operator::BrIf { .. }
+            | Operator::BrTable { .. }
+            | Operator::Return => self.control,
+            Operator::MemorySize { .. } | Operator::MemoryGrow { .. } => self.memory,
+            _ if memory_access_size(op).is_some() => self.memory,
+            _ => self.arithmetic,
+        }
+    }
+}
+
+/// How the metered code charges the gas costs declared for the calls of the
+/// imported functions by the resolver of the instances.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HostCallCharge {
+    /// No cost is charged, the calls have no overhead.
+    None,
+    /// The costs are fixed, they are charged without the arguments.
+    Fixed,
+    /// The costs are computed from the arguments, which are spilled to the
+    /// stack for the cost to read them.
+    Arguments,
+}
+
 /// BuildOption
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct BuildOption {
     enable_gas_metering: bool,
-    mem_gas_factor: u32,
+    gas_costs: GasCostTable,
+    trace_host_calls: bool,
+    host_call_charge: HostCallCharge,
 }
 
 /// The build option when compile
@@ -83,7 +801,9 @@ impl BuildOption {
     pub fn new() -> Self {
         Self {
             enable_gas_metering: false,
-            mem_gas_factor: 0,
+            gas_costs: GasCostTable::default(),
+            trace_host_calls: false,
+            host_call_charge: HostCallCharge::None,
         }
     }
 
@@ -100,14 +820,54 @@ impl BuildOption {
 
     /// gas the memory gas factor
     pub fn get_mem_gas_factor(&mut self) -> u32 {
-        self.mem_gas_factor
+        self.gas_costs.grow
     }
 
-    /// set the memory gas factor
+    /// set the memory gas factor, the `grow` cost of the gas cost table
     pub fn set_mem_gas_factor(mut self, factor: u32) -> Self {
-        self.mem_gas_factor = factor;
+        self.gas_costs.grow = factor;
         self
     }
+
+    /// get the gas cost table
+    pub fn get_gas_cost_table(&mut self) -> &GasCostTable {
+        &self.gas_costs
+    }
+
+    /// set the gas cost table used by the gas metering
+    pub fn gas_cost_table(mut self, gas_costs: GasCostTable) -> Self {
+        self.gas_costs = gas_costs;
+        self
+    }
+
+    /// Report the calls of imported functions to the host call hook of the
+    /// instance. Code compiled without it has no tracing overhead at all.
+    pub fn trace_host_calls(mut self, enable: bool) -> Self {
+        self.trace_host_calls = enable;
+        self
+    }
+
+    /// is_trace_host_calls
+    pub fn is_trace_host_calls(&mut self) -> bool {
+        self.trace_host_calls
+    }
+
+    /// Charge the gas costs of the host calls, see `HostCallCharge`. Only
+    /// effective with the gas metering.
+    pub fn host_call_charge(mut self, charge: HostCallCharge) -> Self {
+        self.host_call_charge = charge;
+        self
+    }
+
+    /// How the host calls are charged, `HostCallCharge::None` without the gas
+    /// metering.
+    pub fn get_host_call_charge(&mut self) -> HostCallCharge {
+        if self.enable_gas_metering {
+            self.host_call_charge
+        } else {
+            HostCallCharge::None
+        }
+    }
 }
 
 /// The `FuncEnvironment` implementation for use by the `ModuleEnvironment`.
@@ -131,8 +891,20 @@ pub struct FuncEnvironment<'module_environment> {
 
     check_gas_sig: Option<ir::SigRef>,
     scope_gas_counter: u32,
+    /// Whether each enclosing control frame, the function body first, is a
+    /// `loop`, to find the branches that start another iteration.
+    control_frames: Vec<bool>,
+    /// Number of operators seen by `before_translate_operator`, to locate the
+    /// operator a translation error is about.
+    operators_seen: usize,
+    /// The operators of the function which may trap.
+    trap_sites: TrapSites,
     check_depth_sig: Option<ir::SigRef>,
+    trace_host_call_sig: Option<ir::SigRef>,
+    charge_host_call_sig: Option<ir::SigRef>,
     build_option: BuildOption,
+    /// Tunable parameters the module is compiled with.
+    tunables: &'module_environment Tunables,
     /// Offsets to struct fields accessed by JIT code.
     offsets: VMOffsets,
 }
@@ -142,6 +914,7 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         target_config: TargetFrontendConfig,
         module: &'module_environment Module,
         build_option: BuildOption,
+        tunables: &'module_environment Tunables,
     ) -> Self {
         Self {
             target_config,
@@ -151,12 +924,29 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
             memory_grow_sig: None,
             check_gas_sig: None,
             scope_gas_counter: 0,
+            control_frames: vec![false],
+            operators_seen: 0,
+            trap_sites: TrapSites::default(),
             check_depth_sig: None,
+            trace_host_call_sig: None,
+            charge_host_call_sig: None,
             build_option,
+            tunables,
             offsets: VMOffsets::new(target_config.pointer_bytes(), module),
         }
     }
 
+    /// Number of operators translated so far, including the one whose
+    /// translation failed.
+    pub(crate) fn operators_seen(&self) -> usize {
+        self.operators_seen
+    }
+
+    /// The operators which may trap recorded by the translation.
+    pub(crate) fn into_trap_sites(self) -> TrapSites {
+        self.trap_sites
+    }
+
     fn pointer_type(&self) -> ir::Type {
         self.target_config.pointer_type()
     }
@@ -244,6 +1034,116 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         )
     }
 
+    fn get_trace_host_call_sig(&mut self, func: &mut Function) -> ir::SigRef {
+        let sig = self.trace_host_call_sig.unwrap_or_else(|| {
+            func.import_signature(Signature {
+                params: vec![
+                    AbiParam::special(self.pointer_type(), ArgumentPurpose::VMContext),
+                    AbiParam::new(I32),
+                    AbiParam::new(self.pointer_type()),
+                    AbiParam::new(I32),
+                    AbiParam::new(I32),
+                ],
+                returns: Vec::new(),
+                call_conv: self.target_config.default_call_conv,
+            })
+        });
+        self.trace_host_call_sig = Some(sig);
+        sig
+    }
+
+    fn get_charge_host_call_sig(&mut self, func: &mut Function) -> ir::SigRef {
+        let sig = self.charge_host_call_sig.unwrap_or_else(|| {
+            func.import_signature(Signature {
+                params: vec![
+                    AbiParam::special(self.pointer_type(), ArgumentPurpose::VMContext),
+                    AbiParam::new(I32),
+                    AbiParam::new(self.pointer_type()),
+                    AbiParam::new(I32),
+                ],
+                returns: Vec::new(),
+                call_conv: self.target_config.default_call_conv,
+            })
+        });
+        self.charge_host_call_sig = Some(sig);
+        sig
+    }
+
+    /// Store `values` into a stack slot of 64 bits slots and return its
+    /// address, null if there are no values. The values are widened the same
+    /// way the trampoline encodes the arguments.
+    fn spill_values(&mut self, pos: &mut FuncCursor<'_>, values: &[ir::Value]) -> ir::Value {
+        let pointer_type = self.pointer_type();
+        if values.is_empty() {
+            pos.ins().iconst(pointer_type, 0)
+        } else {
+            let slot = pos.func.create_stack_slot(ir::StackSlotData::new(
+                ir::StackSlotKind::ExplicitSlot,
+                (values.len() * 8) as u32,
+            ));
+            for (i, &val) in values.iter().enumerate() {
+                let raw = match pos.func.dfg.value_type(val) {
+                    I32 => pos.ins().uextend(I64, val),
+                    F32 => {
+                        let bits = pos.ins().bitcast(I32, val);
+                        pos.ins().uextend(I64, bits)
+                    }
+                    F64 => pos.ins().bitcast(I64, val),
+                    _ => val,
+                };
+                pos.ins().stack_store(raw, slot, (i * 8) as i32);
+            }
+            pos.ins().stack_addr(pointer_type, slot, 0)
+        }
+    }
+
+    /// Emit a call to the `trace_host_call` builtin reporting `values` of the
+    /// call to the imported function `callee_index`, see `spill_values`. The
+    /// `phase` is 0 for the entry and 1 for the exit of the call.
+    fn translate_trace_host_call(
+        &mut self,
+        pos: &mut FuncCursor<'_>,
+        callee_index: FuncIndex,
+        values: &[ir::Value],
+        phase: u32,
+    ) {
+        let values_addr = self.spill_values(pos, values);
+        let func_sig = self.get_trace_host_call_sig(&mut pos.func);
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(
+            pos,
+            BuiltinFunctionIndex::get_trace_host_call_index(),
+        );
+        let index = pos.ins().iconst(I32, callee_index.index() as i64);
+        let count = pos.ins().iconst(I32, values.len() as i64);
+        let phase = pos.ins().iconst(I32, i64::from(phase));
+        pos.ins().call_indirect(
+            func_sig,
+            func_addr,
+            &[vmctx, index, values_addr, count, phase],
+        );
+    }
+
+    /// Emit a call to the `charge_host_call` builtin charging the gas cost of
+    /// a call to the imported function `callee_index`, computed from the
+    /// arguments `args` if there are any, see `spill_values`.
+    fn translate_charge_host_call(
+        &mut self,
+        pos: &mut FuncCursor<'_>,
+        callee_index: FuncIndex,
+        args: &[ir::Value],
+    ) {
+        let args_addr = self.spill_values(pos, args);
+        let func_sig = self.get_charge_host_call_sig(&mut pos.func);
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(
+            pos,
+            BuiltinFunctionIndex::get_charge_host_call_index(),
+        );
+        let index = pos.ins().iconst(I32, callee_index.index() as i64);
+        let count = pos.ins().iconst(I32, args.len() as i64);
+        pos.ins()
+            .call_indirect(func_sig, func_addr, &[vmctx, index, args_addr, count]);
+    }
+
     fn get_memory32_size_sig(&mut self, func: &mut Function) -> ir::SigRef {
         let sig = self.memory32_size_sig.unwrap_or_else(|| {
             func.import_signature(Signature {
@@ -296,6 +1196,37 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         (base, func_addr)
     }
 
+    /// Compute the address of a table entry for a table whose size never
+    /// changes, the bounds check is done against a constant instead of loading
+    /// the current number of elements.
+    fn translate_fixed_table_addr(
+        &mut self,
+        pos: &mut FuncCursor<'_>,
+        table: ir::Table,
+        index: ir::Value,
+        size: u32,
+    ) -> ir::Value {
+        let pointer_type = self.pointer_type();
+        let (base_gv, element_size) = {
+            let data = &pos.func.tables[table];
+            (data.base_gv, data.element_size)
+        };
+
+        let oob = pos
+            .ins()
+            .icmp_imm(IntCC::UnsignedGreaterThanOrEqual, index, i64::from(size));
+        pos.ins().trapnz(oob, ir::TrapCode::TableOutOfBounds);
+
+        let base = pos.ins().global_value(pointer_type, base_gv);
+        let index = if pointer_type == I32 {
+            index
+        } else {
+            pos.ins().uextend(pointer_type, index)
+        };
+        let offset = pos.ins().imul_imm(index, u64::from(element_size) as i64);
+        pos.ins().iadd(base, offset)
+    }
+
     fn update_call_depth(&mut self, count: i32, builder: &mut FunctionBuilder) {
         let update_const = builder.ins().iconst(ir::types::I32, count as i64);
 
@@ -414,17 +1345,25 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
             (vmctx, base_offset, current_elements_offset)
         };
 
+        // A static table is reserved up front and never moved.
+        let plan = &self.module.table_plans[index];
+        let readonly_base = match plan.style {
+            TableStyle::Static { .. } => true,
+            TableStyle::Dynamic => false,
+        };
+        let readonly_bound = plan.fixed_size().is_some();
+
         let base_gv = func.create_global_value(ir::GlobalValueData::Load {
             base: ptr,
             offset: Offset32::new(base_offset),
             global_type: pointer_type,
-            readonly: false,
+            readonly: readonly_base,
         });
         let bound_gv = func.create_global_value(ir::GlobalValueData::Load {
             base: ptr,
             offset: Offset32::new(current_elements_offset),
             global_type: self.offsets.type_of_vmtable_definition_current_elements(),
-            readonly: false,
+            readonly: readonly_bound,
         });
 
         let element_size = u64::from(self.offsets.size_of_vmcaller_checked_anyfunc());
@@ -466,7 +1405,7 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
     fn translate_call_indirect(
         &mut self,
         mut pos: FuncCursor<'_>,
-        _table_index: TableIndex,
+        table_index: TableIndex,
         table: ir::Table,
         sig_index: SignatureIndex,
         sig_ref: ir::SigRef,
@@ -475,7 +1414,10 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
     ) -> WasmResult<ir::Inst> {
         let pointer_type = self.pointer_type();
 
-        let table_entry_addr = pos.ins().table_addr(pointer_type, table, callee, 0);
+        let table_entry_addr = match self.module.table_plans[table_index].fixed_size() {
+            Some(size) => self.translate_fixed_table_addr(&mut pos, table, callee, size),
+            None => pos.ins().table_addr(pointer_type, table, callee, 0),
+        };
 
         // Dereference table_entry_addr to get the function address.
         let mem_flags = ir::MemFlags::trusted();
@@ -565,12 +1507,33 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
             i32::try_from(self.offsets.vmctx_vmfunction_import_body(callee_index)).unwrap();
         let func_addr = pos.ins().load(pointer_type, mem_flags, base, body_offset);
 
-        real_call_args.push(base);
+        // Then the vmctx of the exporting instance, host functions get ours.
+        let vmctx_offset =
+            i32::try_from(self.offsets.vmctx_vmfunction_import_vmctx(callee_index)).unwrap();
+        let callee_vmctx = pos.ins().load(pointer_type, mem_flags, base, vmctx_offset);
+
+        real_call_args.push(callee_vmctx);
 
         // Then append the regular call arguments.
         real_call_args.extend_from_slice(call_args);
 
-        Ok(pos.ins().call_indirect(sig_ref, func_addr, &real_call_args))
+        match self.build_option.get_host_call_charge() {
+            HostCallCharge::None => {}
+            HostCallCharge::Fixed => self.translate_charge_host_call(&mut pos, callee_index, &[]),
+            HostCallCharge::Arguments => {
+                self.translate_charge_host_call(&mut pos, callee_index, call_args)
+            }
+        }
+        if !self.build_option.is_trace_host_calls() {
+            return Ok(pos.ins().call_indirect(sig_ref, func_addr, &real_call_args));
+        }
+
+        self.translate_trace_host_call(&mut pos, callee_index, call_args, 0);
+        let call = pos.ins().call_indirect(sig_ref, func_addr, &real_call_args);
+        let results = pos.func.dfg.inst_results(call).to_vec();
+        self.translate_trace_host_call(&mut pos, callee_index, &results, 1);
+
+        Ok(call)
     }
 
     fn translate_memory_grow(
@@ -614,15 +1577,72 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         builder: &mut FunctionBuilder,
         state: &FuncTranslationState,
     ) -> WasmResult<()> {
-        //todo: remove debug log
-        log::warn!("curr opcode: {:?}", op);
+        self.operators_seen += 1;
+
+        if self.tunables.reject_float && is_float_operator(op) {
+            return Err(WasmError::Unsupported(format!(
+                "floating point operator {}",
+                operator_name(op)
+            )));
+        }
+
+        if let Some(feature) = operator_feature(op) {
+            let kind = match feature {
+                Feature::Threads => "atomic operator",
+                _ => "operator",
+            };
+            let what = format!("{} {}", kind, operator_name(op));
+            self.tunables.features.require(feature, &what)?;
+        }
+
+        // Only a reachable access can trap, the size it reports is looked up
+        // from the offset of the trap.
+        if state.reachable() {
+            if let Some(size) = memory_access_size(op) {
+                let offset = builder.cursor().srcloc().bits() as usize;
+                self.trap_sites.memory_accesses.push((offset, size));
+            }
+        }
 
         if !self.build_option.is_enable_gas_metering() {
             return Ok(());
         }
 
+        let back_edge = match op {
+            Operator::Br { relative_depth } | Operator::BrIf { relative_depth } => {
+                let depth = *relative_depth as usize;
+                depth < self.control_frames.len()
+                    && self.control_frames[self.control_frames.len() - 1 - depth]
+            }
+            Operator::BrTable { .. } => self.control_frames.contains(&true),
+            _ => false,
+        };
+        match op {
+            Operator::Block { .. } | Operator::If { .. } => self.control_frames.push(false),
+            Operator::Loop { .. } => self.control_frames.push(true),
+            Operator::End => {
+                self.control_frames.pop();
+            }
+            _ => {}
+        }
+
         if state.reachable() {
-            self.scope_gas_counter += 1;
+            let cost = self.build_option.get_gas_cost_table().cost(op);
+            self.scope_gas_counter = self.scope_gas_counter.saturating_add(cost);
+            // Every iteration of a loop is charged, even when its operators
+            // are free, for the gas check to bound it and to observe the
+            // timeouts and cancellations.
+            if back_edge && self.scope_gas_counter == 0 {
+                self.scope_gas_counter = 1;
+            }
+            // A trap leaves the scope before it is charged, the runtime
+            // charges what it owes from the offset of the trap.
+            if self.scope_gas_counter != 0 && may_trap(op) {
+                let offset = builder.cursor().srcloc().bits() as usize;
+                self.trap_sites
+                    .pending_gas
+                    .push((offset, self.scope_gas_counter));
+            }
 
             match op {
                 Operator::Unreachable
@@ -651,12 +1671,13 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
                             .call_indirect(func_sig, func_addr, &[vmctx, update_const]);
 
                         self.scope_gas_counter = 0;
-                        match op {
-                            Operator::CallIndirect { .. } | Operator::Call { .. } => {
-                                self.update_call_depth(1, builder);
-                            }
-                            _ => {}
+                    }
+                    // Balanced by `after_translate_operator`, even for free calls.
+                    match op {
+                        Operator::CallIndirect { .. } | Operator::Call { .. } => {
+                            self.update_call_depth(1, builder);
                         }
+                        _ => {}
                     }
                 }
                 _ => {}
@@ -674,8 +1695,11 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         builder: &mut FunctionBuilder,
         state: &FuncTranslationState,
     ) -> WasmResult<()> {
-        //todo: remove debug log
-        log::warn!("after opcode: {:?}", op);
+        if state.reachable() {
+            if let Operator::I32DivS | Operator::I64DivS = op {
+                guard_signed_division(builder);
+            }
+        }
 
         if !self.build_option.is_enable_gas_metering() {
             return Ok(());
@@ -692,3 +1716,37 @@ impl<'module_environment> cranelift_wasm::FuncEnvironment for FuncEnvironment<'m
         Ok(())
     }
 }
+
+/// Check the operands of the `sdiv` just translated, so that a zero divisor
+/// raises `IntegerDivisionByZero` and `MIN / -1` raises `IntegerOverflow`.
+/// The hardware raises the same exception for both, so the native trap could
+/// only carry a single code.
+fn guard_signed_division(builder: &mut FunctionBuilder) {
+    let ebb = builder.current_ebb().expect("sdiv outside of an ebb");
+    let inst = builder
+        .func
+        .layout
+        .last_inst(ebb)
+        .expect("sdiv not translated");
+    debug_assert_eq!(builder.func.dfg[inst].opcode(), ir::Opcode::Sdiv);
+    let (x, y) = {
+        let args = builder.func.dfg.inst_args(inst);
+        (args[0], args[1])
+    };
+    let min = if builder.func.dfg.value_type(x) == I32 {
+        i64::from(i32::min_value())
+    } else {
+        i64::min_value()
+    };
+    let srcloc = builder.func.srclocs[inst];
+
+    let mut pos = FuncCursor::new(builder.func)
+        .with_srcloc(srcloc)
+        .at_inst(inst);
+    pos.ins().trapz(y, ir::TrapCode::IntegerDivisionByZero);
+    // `(x ^ MIN) | !y` is zero only for `x == MIN && y == -1`
+    let x_bits = pos.ins().bxor_imm(x, min);
+    let y_bits = pos.ins().bnot(y);
+    let overflow = pos.ins().bor(x_bits, y_bits);
+    pos.ins().trapz(overflow, ir::TrapCode::IntegerOverflow);
+}
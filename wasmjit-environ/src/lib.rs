@@ -39,12 +39,13 @@ mod compilation;
 mod func_environ;
 mod module;
 mod module_environ;
+mod sig_registry;
 mod tunables;
 mod vmoffsets;
 
 mod cranelift;
 
-pub use cranelift::compile_module;
+pub use cranelift::{compile_module, translate_function};
 
 pub use crate::address_map::{
     FunctionAddressMap, InstructionAddressMap, ModuleAddressMap, ModuleVmctxInfo, ValueLabelsRanges,
@@ -53,14 +54,24 @@ pub use crate::compilation::{
     Compilation, CompileError, Relocation, RelocationTarget, Relocations, TrapInformation, Traps,
 };
 
-pub use crate::func_environ::{BuildOption, BuiltinFunctionIndex};
-pub use crate::module::{MemoryPlan, MemoryStyle, Module, TableElements};
+pub use crate::func_environ::{
+    operator_opcode, BuildOption, BuiltinFunctionIndex, GasCostTable, HostCallCharge,
+};
+pub use crate::module::{
+    ExportError, ExportKind, ExportType, MemoryPlan, MemoryStyle, Module, TableElements, TablePlan,
+    TableStyle, TrapSites,
+};
 pub use crate::module_environ::{
     translate_signature, DataInitializer, DataInitializerLocation, FunctionBodyData,
-    ModuleEnvironment, ModuleTranslation, OwnedDataInitializer,
+    ModuleEnvironment, ModuleTranslation, OwnedDataInitializer, OwnedFunctionBodyData,
+    OwnedModuleTranslation,
+};
+pub use crate::sig_registry::SignatureRegistry;
+pub use crate::tunables::{
+    Feature, Features, InvalidTunables, OptLevel, Tunables, TunablesBuilder,
 };
-pub use crate::tunables::Tunables;
 pub use crate::vmoffsets::{TargetSharedSignatureIndex, VMOffsets};
+pub use wasmparser::Operator;
 
 /// WebAssembly page sizes are defined to be 64KiB.
 pub const WASM_PAGE_SIZE: u32 = 0x10000;
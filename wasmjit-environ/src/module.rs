@@ -1,6 +1,7 @@
 //! Data structures for representing decoded wasm modules.
 
 use crate::tunables::Tunables;
+use crate::WASM_MAX_PAGES;
 use alloc::string::String;
 use alloc::vec::Vec;
 use cranelift_codegen::ir;
@@ -11,8 +12,10 @@ use cranelift_wasm::{
 };
 
 use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 /// A WebAssembly table initializer.
-#[derive(Clone, Debug, Hash)]
+#[derive(Serialize, Deserialize, Clone, Debug, Hash)]
 pub struct TableElements {
     /// The index of a table to initialize.
     pub table_index: TableIndex,
@@ -25,7 +28,7 @@ pub struct TableElements {
 }
 
 /// Implemenation styles for WebAssembly linear memory.
-#[derive(Debug, Clone, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, Hash)]
 pub enum MemoryStyle {
     /// The actual memory can be resized and moved.
     Dynamic,
@@ -39,6 +42,10 @@ pub enum MemoryStyle {
 impl MemoryStyle {
     /// Decide on an implementation style for the given `Memory`.
     pub fn for_memory(memory: Memory, tunables: &Tunables) -> (Self, u64) {
+        if !tunables.guard_pages {
+            return (Self::Dynamic, 0);
+        }
+
         if let Some(maximum) = memory.maximum {
             if maximum <= tunables.static_memory_bound {
                 // A heap with a declared maximum can be immovable, so make
@@ -58,9 +65,64 @@ impl MemoryStyle {
     }
 }
 
+/// Implemenation styles for WebAssembly tables.
+#[derive(Serialize, Deserialize, Debug, Clone, Hash)]
+pub enum TableStyle {
+    /// The table storage can be reallocated when growing.
+    Dynamic,
+    /// Storage for `bound` elements is reserved up front, the table is never
+    /// moved.
+    Static {
+        /// The number of reserved elements.
+        bound: u32,
+    },
+}
+
+impl TableStyle {
+    /// Decide on an implementation style for the given `Table`.
+    pub fn for_table(table: Table, tunables: &Tunables) -> Self {
+        if let Some(maximum) = table.maximum {
+            if maximum <= tunables.static_table_bound {
+                // A table with a declared maximum within the reservation can
+                // be immovable, so make it static.
+                return Self::Static { bound: maximum };
+            }
+        }
+
+        Self::Dynamic
+    }
+}
+
+/// A WebAssembly table description along with our chosen style for
+/// implementing it.
+#[derive(Serialize, Deserialize, Debug, Clone, Hash)]
+pub struct TablePlan {
+    /// The WebAssembly table description.
+    pub table: Table,
+    /// Our chosen implementation style.
+    pub style: TableStyle,
+}
+
+impl TablePlan {
+    /// Draw up a plan for implementing a `Table`.
+    pub fn for_table(table: Table, tunables: &Tunables) -> Self {
+        let style = TableStyle::for_table(table, tunables);
+        Self { table, style }
+    }
+
+    /// Returns the number of elements if the table can never change its size,
+    /// such table can be bounds checked against a constant.
+    pub fn fixed_size(&self) -> Option<u32> {
+        match self.style {
+            TableStyle::Static { bound } if bound == self.table.minimum => Some(bound),
+            _ => None,
+        }
+    }
+}
+
 /// A WebAssembly linear memory description along with our chosen style for
 /// implementing it.
-#[derive(Debug, Clone, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, Hash)]
 pub struct MemoryPlan {
     /// The WebAssembly linear memory description.
     pub memory: Memory,
@@ -68,35 +130,139 @@ pub struct MemoryPlan {
     pub style: MemoryStyle,
     /// Our chosen offset-guard size.
     pub offset_guard_size: u64,
+    /// Number of wasm pages of address space reserved up front, the bound of
+    /// a static memory. A dynamic memory is only moved by the grows beyond
+    /// it, see `Tunables::dynamic_memory_reserved_pages`.
+    pub reserved_pages: u32,
 }
 
 impl MemoryPlan {
     /// Draw up a plan for implementing a `Memory`.
     pub fn for_memory(memory: Memory, tunables: &Tunables) -> Self {
         let (style, offset_guard_size) = MemoryStyle::for_memory(memory, tunables);
+        let reserved_pages = match style {
+            MemoryStyle::Dynamic => {
+                let maximum = memory.maximum.unwrap_or(WASM_MAX_PAGES).min(WASM_MAX_PAGES);
+                tunables
+                    .dynamic_memory_reserved_pages
+                    .min(maximum)
+                    .max(memory.minimum)
+            }
+            MemoryStyle::Static { bound } => bound,
+        };
         Self {
             memory,
             style,
             offset_guard_size,
+            reserved_pages,
         }
     }
 }
 
+/// The kind of an exported entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportKind {
+    /// A function.
+    Function,
+    /// A table.
+    Table,
+    /// A linear memory.
+    Memory,
+    /// A global variable.
+    Global,
+}
+
+/// The type of an exported entity.
+#[derive(Debug, Clone, Copy)]
+pub enum ExportType<'module> {
+    /// The signature of an exported function, including the leading vmctx
+    /// parameter.
+    Function(&'module ir::Signature),
+    /// The type and mutability of an exported global.
+    Global(&'module Global),
+}
+
+impl ExportType<'_> {
+    /// The kind of the exported entity.
+    pub fn kind(&self) -> ExportKind {
+        match self {
+            ExportType::Function(_) => ExportKind::Function,
+            ExportType::Global(_) => ExportKind::Global,
+        }
+    }
+}
+
+/// Why an export name does not resolve to a defined function, see
+/// `Module::defined_export`.
+#[derive(Fail, Debug, PartialEq, Eq)]
+pub enum ExportError {
+    /// No function is exported with the name.
+    #[fail(display = "unknown export function: {}", _0)]
+    Unknown(String),
+
+    /// The name exports an imported function.
+    #[fail(
+        display = "export function {} is the imported function {}.{}",
+        name, module, field
+    )]
+    Imported {
+        /// The export name.
+        name: String,
+        /// The module name of the import.
+        module: String,
+        /// The field name of the import.
+        field: String,
+    },
+}
+
+/// What the translation of the function bodies records about the operators
+/// which may trap, keyed by their offset in the module in increasing order.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TrapSites {
+    /// The size in bytes of the access of each memory access operator.
+    pub memory_accesses: Vec<(usize, u32)>,
+    /// The gas of the operators of the scope of each operator, up to and
+    /// including it, which its trap leaves uncharged.
+    pub pending_gas: Vec<(usize, u32)>,
+}
+
+impl TrapSites {
+    /// Append the sites of a function translated after the ones recorded.
+    pub fn append(&mut self, other: &mut TrapSites) {
+        self.memory_accesses.append(&mut other.memory_accesses);
+        self.pending_gas.append(&mut other.pending_gas);
+    }
+}
+
+/// The value recorded for the operator at `module_offset` in `sites`.
+fn find_site(sites: &[(usize, u32)], module_offset: usize) -> Option<u32> {
+    sites
+        .binary_search_by_key(&module_offset, |&(offset, _)| offset)
+        .ok()
+        .map(|index| sites[index].1)
+}
+
 /// A translated WebAssembly module, excluding the function bodies and
 /// memory initializers.
-#[derive(Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Module {
     /// Unprocessed signatures exactly as provided by `declare_signature()`.
     pub signatures: PrimaryMap<SignatureIndex, ir::Signature>,
 
+    /// Ids of the signatures in the shared `SignatureRegistry` the module was
+    /// translated with, empty without one. Only valid in the process which
+    /// translated the module, so they are not serialized.
+    #[serde(skip)]
+    pub signature_ids: PrimaryMap<SignatureIndex, u32>,
+
     /// Names of imported functions.
     pub imported_funcs: PrimaryMap<FuncIndex, (String, String)>,
 
     /// Types of functions, imported and local.
     pub functions: PrimaryMap<FuncIndex, SignatureIndex>,
 
-    /// WebAssembly tables.
-    pub tables: PrimaryMap<TableIndex, Table>,
+    /// WebAssembly table plans.
+    pub table_plans: PrimaryMap<TableIndex, TablePlan>,
 
     /// WebAssembly linear memory plans.
     pub memory_plans: PrimaryMap<MemoryIndex, MemoryPlan>,
@@ -107,8 +273,17 @@ pub struct Module {
     /// Exported invoke func.
     pub exports: IndexMap<String, FuncIndex>,
 
+    /// Exported globals.
+    pub global_exports: IndexMap<String, GlobalIndex>,
+
+    /// Function names from the custom name section, if any.
+    pub func_names: HashMap<FuncIndex, String>,
+
     /// WebAssembly table initializers.
     pub table_elements: Vec<TableElements>,
+
+    /// The operators which may trap, recorded by the compilation.
+    pub trap_sites: TrapSites,
 }
 
 impl Module {
@@ -116,16 +291,85 @@ impl Module {
     pub fn new() -> Self {
         Self {
             signatures: PrimaryMap::new(),
+            signature_ids: PrimaryMap::new(),
             imported_funcs: PrimaryMap::new(),
             functions: PrimaryMap::new(),
-            tables: PrimaryMap::new(),
+            table_plans: PrimaryMap::new(),
             memory_plans: PrimaryMap::new(),
             globals: PrimaryMap::new(),
             exports: IndexMap::new(),
+            global_exports: IndexMap::new(),
+            func_names: HashMap::new(),
             table_elements: Vec::new(),
+            trap_sites: TrapSites::default(),
         }
     }
 
+    /// Iterate over the exports in declaration order, along with their kind
+    /// and type. The functions come first, then the globals.
+    pub fn export_types(&self) -> impl Iterator<Item = (&str, ExportKind, ExportType)> {
+        let funcs = self.exports.iter().map(move |(name, &func_index)| {
+            (
+                name,
+                ExportType::Function(&self.signatures[self.functions[func_index]]),
+            )
+        });
+        let globals = self
+            .global_exports
+            .iter()
+            .map(move |(name, &global_index)| {
+                (name, ExportType::Global(&self.globals[global_index]))
+            });
+        funcs
+            .chain(globals)
+            .map(|(name, ty)| (name.as_str(), ty.kind(), ty))
+    }
+
+    /// Iterate over the imported functions in declaration order as
+    /// `(module, field, signature)`, the signature including the leading
+    /// vmctx parameter like the ones of the exports.
+    pub fn import_types(&self) -> impl Iterator<Item = (&str, &str, &ir::Signature)> {
+        self.imported_funcs
+            .iter()
+            .map(move |(func_index, (module, field))| {
+                let signature = &self.signatures[self.functions[func_index]];
+                (module.as_str(), field.as_str(), signature)
+            })
+    }
+
+    /// The defined function exported as `name`, an error if the name exports
+    /// an imported function or nothing.
+    pub fn defined_export(&self, name: &str) -> Result<DefinedFuncIndex, ExportError> {
+        let func = *self
+            .exports
+            .get(name)
+            .ok_or_else(|| ExportError::Unknown(name.to_string()))?;
+        self.defined_func_index(func).ok_or_else(|| {
+            let (module, field) = self.imported_funcs[func].clone();
+            ExportError::Imported {
+                name: name.to_string(),
+                module,
+                field,
+            }
+        })
+    }
+
+    /// Size in bytes of the memory access operator at `module_offset`, if any.
+    pub fn memory_access_size(&self, module_offset: usize) -> Option<u32> {
+        find_site(&self.trap_sites.memory_accesses, module_offset)
+    }
+
+    /// The gas left uncharged by a trap of the operator at `module_offset`,
+    /// if it may trap with operators pending in its scope.
+    pub fn trap_pending_gas(&self, module_offset: usize) -> Option<u32> {
+        find_site(&self.trap_sites.pending_gas, module_offset)
+    }
+
+    /// Name of a function from the custom name section, if any.
+    pub fn func_name(&self, func: FuncIndex) -> Option<&str> {
+        self.func_names.get(&func).map(String::as_str)
+    }
+
     /// Convert a `DefinedFuncIndex` into a `FuncIndex`.
     pub fn func_index(&self, defined_func: DefinedFuncIndex) -> FuncIndex {
         FuncIndex::new(self.imported_funcs.len() + defined_func.index())
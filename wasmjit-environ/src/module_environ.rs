@@ -1,6 +1,8 @@
-use crate::func_environ::{BuildOption, FuncEnvironment};
-use crate::module::{MemoryPlan, Module, TableElements};
-use crate::tunables::Tunables;
+use crate::func_environ::{is_float_operator, BuildOption, FuncEnvironment};
+use crate::module::{MemoryPlan, Module, TableElements, TablePlan};
+use crate::sig_registry::SignatureRegistry;
+use crate::tunables::{Feature, Tunables};
+use core::cmp;
 use core::convert::TryFrom;
 use cranelift_codegen::ir;
 use cranelift_codegen::ir::{AbiParam, ArgumentPurpose};
@@ -10,6 +12,16 @@ use cranelift_wasm::{
     self, translate_module, DefinedFuncIndex, FuncIndex, Global, GlobalIndex, Memory, MemoryIndex,
     ModuleTranslationState, SignatureIndex, Table, TableIndex, WasmError, WasmResult,
 };
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use wasmparser::{
+    FunctionBody, ModuleReader, Name, NameSectionReader, NamingReader, Operator, SectionCode, Type,
+};
+
+macro_rules! wasm_unsupported {
+    ($($arg:tt)*) => { WasmError::Unsupported(format!($($arg)*)) }
+}
 
 /// Contains function data: byte code and its offset in the module.
 #[derive(Hash)]
@@ -21,6 +33,35 @@ pub struct FunctionBodyData<'a> {
     pub module_offset: usize,
 }
 
+impl FunctionBodyData<'_> {
+    /// Convert to owned data
+    pub fn into_owned(self) -> OwnedFunctionBodyData {
+        OwnedFunctionBodyData {
+            data: self.data.to_vec(),
+            module_offset: self.module_offset,
+        }
+    }
+}
+
+/// Contains function data like `FunctionBodyData`, owning its byte code.
+#[derive(Clone, Hash)]
+pub struct OwnedFunctionBodyData {
+    /// Body byte code.
+    pub data: Vec<u8>,
+
+    /// Body offset in the module file.
+    pub module_offset: usize,
+}
+
+impl<'data> Into<FunctionBodyData<'data>> for &'data OwnedFunctionBodyData {
+    fn into(self) -> FunctionBodyData<'data> {
+        FunctionBodyData {
+            data: &self.data,
+            module_offset: self.module_offset,
+        }
+    }
+}
+
 /// The result of translating via `ModuleEnvironment`. Function bodies are not
 /// yet translated, and data initializers have not yet been copied out of the
 /// original buffer.
@@ -34,7 +75,9 @@ pub struct ModuleTranslation<'data> {
     /// References to the function bodies.
     pub function_body_inputs: PrimaryMap<DefinedFuncIndex, FunctionBodyData<'data>>,
 
-    /// References to the data initializers.
+    /// References to the data initializers, in their declaration order in the
+    /// data section. Instantiation applies them in this order, so where
+    /// segments overlap the bytes of the last one are kept.
     pub data_initializers: Vec<DataInitializer<'data>>,
 
     /// Module translate state.
@@ -42,12 +85,169 @@ pub struct ModuleTranslation<'data> {
 
     /// Tunable parameters.
     pub tunables: Tunables,
+
+    /// Names of the locals of each function, from the custom name section.
+    pub local_names: HashMap<FuncIndex, HashMap<u32, String>>,
+
+    /// Whether a signature, global, local or operator of the module uses
+    /// floating point. Computed whatever `Tunables::reject_float`, so a module
+    /// can be routed on it without being rejected.
+    pub uses_float: bool,
+}
+
+/// Check `count` against the optional `limit` named `name`.
+fn check_limit(name: &str, count: u64, limit: Option<u64>) -> WasmResult<()> {
+    match limit {
+        Some(limit) if count > limit => Err(wasm_unsupported!(
+            "module exceeds {}: {} > {}",
+            name,
+            count,
+            limit
+        )),
+        _ => Ok(()),
+    }
 }
 
+/// Where the violations of the tunables are reported: fail fast on the first
+/// one, or collect them all.
+type Report<'a> = &'a mut dyn FnMut(WasmError) -> WasmResult<()>;
+
 impl<'data> ModuleTranslation<'data> {
+    /// Check the module against the resource limits of the tunables.
+    pub fn check_limits(&self) -> WasmResult<()> {
+        self.report_limits(&mut Err)
+    }
+
+    /// Check the module against all the resource limits of the tunables, and
+    /// return every limit it exceeds.
+    pub fn limit_violations(&self) -> Vec<WasmError> {
+        let mut violations = Vec::new();
+        let _ = self.report_limits(&mut |err| {
+            violations.push(err);
+            Ok(())
+        });
+        violations
+    }
+
+    fn report_limits(&self, report: Report) -> WasmResult<()> {
+        let tunables = &self.tunables;
+        let module = &self.module;
+        check_limit(
+            "max_functions",
+            self.function_body_inputs.len() as u64,
+            tunables.max_functions.map(u64::from),
+        )
+        .or_else(&mut *report)?;
+        let table_size = module
+            .table_plans
+            .values()
+            .map(|plan| cmp::max(plan.table.minimum, plan.table.maximum.unwrap_or(0)))
+            .max()
+            .unwrap_or(0);
+        check_limit(
+            "max_table_size",
+            u64::from(table_size),
+            tunables.max_table_size.map(u64::from),
+        )
+        .or_else(&mut *report)?;
+        check_limit(
+            "max_globals",
+            module.globals.len() as u64,
+            tunables.max_globals.map(u64::from),
+        )
+        .or_else(&mut *report)?;
+        check_limit(
+            "max_imports",
+            module.imported_funcs.len() as u64,
+            tunables.max_imports.map(u64::from),
+        )
+        .or_else(&mut *report)?;
+        check_limit("max_data_bytes", self.data_bytes(), tunables.max_data_bytes).or_else(report)
+    }
+
+    /// Total size in bytes of the data initializers, as declared: the bytes
+    /// of overlapping segments are counted once per segment, and a segment
+    /// based on a global counts its full length whatever the value of the
+    /// global at instantiation. This is the size `max_data_bytes` limits.
+    pub fn data_bytes(&self) -> u64 {
+        self.data_initializers
+            .iter()
+            .map(|init| init.data.len() as u64)
+            .sum()
+    }
+
     /// Return a new `FuncEnvironment` for translating a function.
     pub fn func_env(&self, build_option: BuildOption) -> FuncEnvironment<'_> {
-        FuncEnvironment::new(self.target_config, &self.module, build_option)
+        FuncEnvironment::new(
+            self.target_config,
+            &self.module,
+            build_option,
+            &self.tunables,
+        )
+    }
+
+    /// Copy the function bodies and the data initializers out of the module
+    /// bytes, so the translation can be kept after they are dropped.
+    pub fn into_owned(self) -> OwnedModuleTranslation {
+        OwnedModuleTranslation {
+            target_config: self.target_config,
+            module: self.module,
+            function_body_inputs: self
+                .function_body_inputs
+                .into_iter()
+                .map(|(_, body)| body.into_owned())
+                .collect(),
+            data_initializers: self
+                .data_initializers
+                .into_iter()
+                .map(DataInitializer::into_owned)
+                .collect(),
+            translate_state: Arc::new(self.translate_state),
+            tunables: self.tunables,
+            local_names: self.local_names,
+            uses_float: self.uses_float,
+        }
+    }
+}
+
+/// A `ModuleTranslation` owning its function bodies and data initializers,
+/// so it can be compiled any number of times without translating the module
+/// bytes again. Clones share the translation state.
+#[derive(Clone)]
+pub struct OwnedModuleTranslation {
+    /// Compilation setting flags.
+    pub target_config: TargetFrontendConfig,
+
+    /// Module information.
+    pub module: Module,
+
+    /// The function bodies.
+    pub function_body_inputs: PrimaryMap<DefinedFuncIndex, OwnedFunctionBodyData>,
+
+    /// The data initializers, in declaration order like in
+    /// `ModuleTranslation::data_initializers`.
+    pub data_initializers: Vec<OwnedDataInitializer>,
+
+    /// Module translate state.
+    pub translate_state: Arc<ModuleTranslationState>,
+
+    /// Tunable parameters.
+    pub tunables: Tunables,
+
+    /// Names of the locals of each function, see `ModuleTranslation`.
+    pub local_names: HashMap<FuncIndex, HashMap<u32, String>>,
+
+    /// Whether the module uses floating point, see `ModuleTranslation`.
+    pub uses_float: bool,
+}
+
+impl OwnedModuleTranslation {
+    /// References to the function bodies, in the form `compile_module` takes.
+    pub fn function_bodies(&self) -> PrimaryMap<DefinedFuncIndex, FunctionBodyData<'_>> {
+        self.function_body_inputs
+            .values()
+            .map(|body| body.into())
+            .collect()
     }
 }
 
@@ -55,6 +255,16 @@ impl<'data> ModuleTranslation<'data> {
 pub struct ModuleEnvironment<'data> {
     /// The result to be filled in.
     result: ModuleTranslation<'data>,
+
+    /// The registry interning the signatures across modules, if any.
+    signature_registry: Option<Arc<SignatureRegistry>>,
+
+    /// The violations of the tunables seen so far, when they are collected
+    /// instead of failing the translation.
+    violations: Option<Vec<WasmError>>,
+
+    /// The module being translated, to locate the errors of its entries.
+    data: &'data [u8],
 }
 
 impl<'data> ModuleEnvironment<'data> {
@@ -68,26 +278,240 @@ impl<'data> ModuleEnvironment<'data> {
                 data_initializers: Vec::new(),
                 tunables,
                 translate_state: ModuleTranslationState::new(),
+                local_names: HashMap::new(),
+                uses_float: false,
             },
+            signature_registry: None,
+            violations: None,
+            data: &[],
         }
     }
 
+    /// Intern the signatures of the module in the shared `registry`, so the
+    /// identical signatures of all the modules translated with it get the
+    /// same id in `Module::signature_ids`.
+    pub fn with_signature_registry(mut self, registry: Arc<SignatureRegistry>) -> Self {
+        self.signature_registry = Some(registry);
+        self
+    }
+
     fn pointer_type(&self) -> ir::Type {
         self.result.target_config.pointer_type()
     }
 
+    /// Report a module which breaks the tunables: fail with `err`, or collect
+    /// it and carry on with `translate_collecting`.
+    fn violation(&mut self, err: WasmError) -> WasmResult<()> {
+        match &mut self.violations {
+            Some(violations) => {
+                violations.push(err);
+                Ok(())
+            }
+            None => Err(err),
+        }
+    }
+
+    /// Export names must be unique: the exports are looked up by name, a
+    /// duplicate would silently shadow the previous export. Such a module is
+    /// invalid, it is rejected even when the violations are collected.
+    fn check_export_name(&self, name: &str) -> WasmResult<()> {
+        let module = &self.result.module;
+        if module.exports.contains_key(name) || module.global_exports.contains_key(name) {
+            return Err(WasmError::InvalidWebAssembly {
+                message: "duplicate export name",
+                offset: export_section_offset(self.data),
+            });
+        }
+        Ok(())
+    }
+
     /// Translate a wasm module using this environment. This consumes the
-    /// `ModuleEnvironment` and produces a `ModuleTranslation`.
+    /// `ModuleEnvironment` and produces a `ModuleTranslation`, checked against
+    /// the resource limits of the tunables.
     pub fn translate(mut self, data: &'data [u8]) -> WasmResult<ModuleTranslation<'data>> {
+        self.data = data;
         let state = translate_module(data, &mut self)?;
         self.result.translate_state = state;
+        self.result.check_limits()?;
 
         Ok(self.result)
     }
+
+    /// Translate like `translate`, but report every violation of the tunables
+    /// found in the module structure and its resource limits instead of
+    /// failing on the first one. Meant for tooling, `translate` is cheaper.
+    ///
+    /// A malformed module still stops the translation, its error is returned
+    /// after the violations found before it. The operators are only checked
+    /// by the compilation of the function bodies.
+    pub fn translate_collecting(
+        mut self,
+        data: &'data [u8],
+    ) -> Result<ModuleTranslation<'data>, Vec<WasmError>> {
+        self.violations = Some(Vec::new());
+        self.data = data;
+        let translated = translate_module(data, &mut self);
+        let mut violations = self.violations.take().unwrap_or_default();
+        match translated {
+            Ok(state) => {
+                self.result.translate_state = state;
+                violations.extend(self.result.limit_violations());
+            }
+            Err(err) => violations.push(err),
+        }
+
+        if violations.is_empty() {
+            Ok(self.result)
+        } else {
+            Err(violations)
+        }
+    }
+
+    fn declare_name_subsections(&mut self, mut names: NameSectionReader) {
+        while !names.eof() {
+            match names.read() {
+                Ok(Name::Function(func_names)) => {
+                    if let Ok(map) = func_names.get_map() {
+                        for (index, name) in read_naming_map(map) {
+                            self.result
+                                .module
+                                .func_names
+                                .insert(FuncIndex::from_u32(index), name);
+                        }
+                    }
+                }
+                Ok(Name::Local(local_names)) => {
+                    let mut reader = match local_names.get_function_local_reader() {
+                        Ok(reader) => reader,
+                        Err(_) => continue,
+                    };
+                    for _ in 0..reader.get_count() {
+                        let locals = match reader.read() {
+                            Ok(locals) => locals,
+                            Err(_) => break,
+                        };
+                        if let Ok(map) = locals.get_map() {
+                            self.result
+                                .local_names
+                                .entry(FuncIndex::from_u32(locals.get_func_index()))
+                                .or_default()
+                                .extend(read_naming_map(map));
+                        }
+                    }
+                }
+                Ok(Name::Module(_)) => {}
+                // the size of a broken subsection is unknown, skip the rest
+                Err(_) => return,
+            }
+        }
+    }
 }
 
-macro_rules! wasm_unsupported {
-    ($($arg:tt)*) => { WasmError::Unsupported(format!($($arg)*)) }
+/// Offset in `data` of its export section, 0 if it has none. Only looked up
+/// to report an error, the translation does not keep it.
+fn export_section_offset(data: &[u8]) -> usize {
+    let mut reader = match ModuleReader::new(data) {
+        Ok(reader) => reader,
+        Err(_) => return 0,
+    };
+    while !reader.eof() {
+        let offset = reader.current_position();
+        match reader.read() {
+            Ok(section) => {
+                if let SectionCode::Export = section.code {
+                    return offset;
+                }
+            }
+            Err(_) => return 0,
+        }
+    }
+    0
+}
+
+/// Read the `(index, name)` pairs of a name map, up to the first malformed one.
+fn read_naming_map(mut map: NamingReader) -> Vec<(u32, String)> {
+    let mut names = Vec::new();
+    for _ in 0..map.get_count() {
+        match map.read() {
+            Ok(naming) => names.push((naming.index, naming.name.to_string())),
+            Err(_) => break,
+        }
+    }
+    names
+}
+
+/// Read a function body once for what its declaration checks: the number of
+/// locals declared in its header and, unless `known_float`, whether a local
+/// or an operator uses floating point. The operators are read up to the
+/// first one using floating point, and not at all if a local does.
+fn scan_body(body_bytes: &[u8], body_offset: usize, known_float: bool) -> WasmResult<(u64, bool)> {
+    let body = FunctionBody::new(body_offset, body_bytes);
+    let mut reader = body.get_locals_reader()?;
+    let mut count = 0u64;
+    let mut uses_float = known_float;
+    for _ in 0..reader.get_count() {
+        let (locals, ty) = reader.read()?;
+        count += u64::from(locals);
+        if let Type::F32 | Type::F64 = ty {
+            uses_float = true;
+        }
+    }
+    if !uses_float {
+        let mut reader = body.get_operators_reader()?;
+        while !reader.eof() {
+            if is_float_operator(&reader.read()?) {
+                uses_float = true;
+                break;
+            }
+        }
+    }
+    Ok((count, uses_float))
+}
+
+/// Size in bytes of the linear memory access of `op`, if it makes one.
+pub(crate) fn memory_access_size(op: &Operator) -> Option<u32> {
+    let size = match op {
+        Operator::I32Load8S { .. }
+        | Operator::I32Load8U { .. }
+        | Operator::I64Load8S { .. }
+        | Operator::I64Load8U { .. }
+        | Operator::I32Store8 { .. }
+        | Operator::I64Store8 { .. }
+        | Operator::I8x16LoadSplat { .. } => 1,
+        Operator::I32Load16S { .. }
+        | Operator::I32Load16U { .. }
+        | Operator::I64Load16S { .. }
+        | Operator::I64Load16U { .. }
+        | Operator::I32Store16 { .. }
+        | Operator::I64Store16 { .. }
+        | Operator::I16x8LoadSplat { .. } => 2,
+        Operator::I32Load { .. }
+        | Operator::F32Load { .. }
+        | Operator::I64Load32S { .. }
+        | Operator::I64Load32U { .. }
+        | Operator::I32Store { .. }
+        | Operator::F32Store { .. }
+        | Operator::I64Store32 { .. }
+        | Operator::I32x4LoadSplat { .. } => 4,
+        Operator::I64Load { .. }
+        | Operator::F64Load { .. }
+        | Operator::I64Store { .. }
+        | Operator::F64Store { .. }
+        | Operator::I64x2LoadSplat { .. } => 8,
+        Operator::V128Load { .. } | Operator::V128Store { .. } => 16,
+        _ => return None,
+    };
+    Some(size)
+}
+
+/// Upper bound on the number of entries preallocated from a count announced
+/// in a section header, which the module is free to lie about.
+const MAX_RESERVED_ENTRIES: u32 = 10_000;
+
+/// Number of entries to preallocate for a section announcing `num` entries.
+/// Later declarations grow the tables past it as needed.
+fn reserved_entries(num: u32) -> usize {
+    usize::try_from(cmp::min(num, MAX_RESERVED_ENTRIES)).unwrap()
 }
 
 /// This trait is useful for `translate_module` because it tells how to translate
@@ -101,12 +525,28 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
         self.result
             .module
             .signatures
-            .reserve_exact(usize::try_from(num).unwrap());
+            .reserve_exact(reserved_entries(num));
         Ok(())
     }
 
     fn declare_signature(&mut self, sig: ir::Signature) -> WasmResult<()> {
+        let float_sig = sig
+            .params
+            .iter()
+            .chain(sig.returns.iter())
+            .any(|param| param.value_type.is_float());
+        self.result.uses_float |= float_sig;
+        if self.result.tunables.reject_float && float_sig {
+            self.violation(wasm_unsupported!(
+                "floating point type in signature {}",
+                sig
+            ))?;
+        }
         let sig = translate_signature(sig, self.pointer_type());
+        if let Some(registry) = &self.signature_registry {
+            let id = registry.register(&sig);
+            self.result.module.signature_ids.push(id);
+        }
         // TODO: Deduplicate signatures.
         self.result.module.signatures.push(sig);
         Ok(())
@@ -133,7 +573,7 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
     }
 
     fn declare_table_import(&mut self, _table: Table, module: &str, field: &str) -> WasmResult<()> {
-        Err(wasm_unsupported!(
+        self.violation(wasm_unsupported!(
             "can not import table from {}.{}",
             module,
             field
@@ -146,7 +586,7 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
         module: &str,
         field: &str,
     ) -> WasmResult<()> {
-        Err(wasm_unsupported!(
+        self.violation(wasm_unsupported!(
             "can not import memory from {}.{}",
             module,
             field
@@ -159,7 +599,7 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
         module: &str,
         field: &str,
     ) -> WasmResult<()> {
-        Err(wasm_unsupported!(
+        self.violation(wasm_unsupported!(
             "can not import global from {}.{}",
             module,
             field
@@ -175,10 +615,10 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
         self.result
             .module
             .functions
-            .reserve_exact(usize::try_from(num).unwrap());
+            .reserve_exact(reserved_entries(num));
         self.result
             .function_body_inputs
-            .reserve_exact(usize::try_from(num).unwrap());
+            .reserve_exact(reserved_entries(num));
         Ok(())
     }
 
@@ -190,13 +630,19 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
     fn reserve_tables(&mut self, num: u32) -> WasmResult<()> {
         self.result
             .module
-            .tables
-            .reserve_exact(usize::try_from(num).unwrap());
+            .table_plans
+            .reserve_exact(reserved_entries(num));
         Ok(())
     }
 
-    fn declare_table(&mut self, table: Table) -> WasmResult<()> {
-        self.result.module.tables.push(table);
+    fn declare_table(&mut self, mut table: Table) -> WasmResult<()> {
+        // The declared sizes are checked with the other resource limits, the
+        // cap also bounds the growth of a table declared without a maximum.
+        if let Some(max_size) = self.result.tunables.max_table_size {
+            table.maximum = Some(table.maximum.unwrap_or(max_size));
+        }
+        let plan = TablePlan::for_table(table, &self.result.tunables);
+        self.result.module.table_plans.push(plan);
         Ok(())
     }
 
@@ -204,11 +650,27 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
         self.result
             .module
             .memory_plans
-            .reserve_exact(usize::try_from(num).unwrap());
+            .reserve_exact(reserved_entries(num));
         Ok(())
     }
 
-    fn declare_memory(&mut self, memory: Memory) -> WasmResult<()> {
+    fn declare_memory(&mut self, mut memory: Memory) -> WasmResult<()> {
+        if memory.shared {
+            let features = &self.result.tunables.features;
+            if let Err(err) = features.require(Feature::Threads, "shared memory") {
+                self.violation(err)?;
+            }
+        }
+        if let Some(max_pages) = self.result.tunables.max_memory_pages {
+            if memory.minimum > max_pages {
+                self.violation(wasm_unsupported!(
+                    "memory exceeds max_memory_pages: {} > {}",
+                    memory.minimum,
+                    max_pages
+                ))?;
+            }
+            memory.maximum = Some(memory.maximum.map_or(max_pages, |max| max.min(max_pages)));
+        }
         let plan = MemoryPlan::for_memory(memory, &self.result.tunables);
         self.result.module.memory_plans.push(plan);
         Ok(())
@@ -218,22 +680,30 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
         self.result
             .module
             .globals
-            .reserve_exact(usize::try_from(num).unwrap());
+            .reserve_exact(reserved_entries(num));
         Ok(())
     }
 
     fn declare_global(&mut self, global: Global) -> WasmResult<()> {
+        self.result.uses_float |= global.ty.is_float();
+        if self.result.tunables.reject_float && global.ty.is_float() {
+            self.violation(wasm_unsupported!(
+                "floating point global of type {}",
+                global.ty
+            ))?;
+        }
         self.result.module.globals.push(global);
         Ok(())
     }
 
     fn reserve_exports(&mut self, num: u32) -> WasmResult<()> {
-        self.result.module.exports.reserve(num as usize);
+        self.result.module.exports.reserve(reserved_entries(num));
 
         Ok(())
     }
 
     fn declare_func_export(&mut self, func_index: FuncIndex, name: &str) -> WasmResult<()> {
+        self.check_export_name(name)?;
         self.result
             .module
             .exports
@@ -242,26 +712,31 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
     }
 
     fn declare_table_export(&mut self, _table_index: TableIndex, name: &str) -> WasmResult<()> {
-        Err(wasm_unsupported!("can not export table {}", name))
+        self.violation(wasm_unsupported!("can not export table {}", name))
     }
 
     fn declare_memory_export(&mut self, _memory_index: MemoryIndex, name: &str) -> WasmResult<()> {
-        Err(wasm_unsupported!("can not export memory {}", name))
+        self.violation(wasm_unsupported!("can not export memory {}", name))
     }
 
-    fn declare_global_export(&mut self, _global_index: GlobalIndex, name: &str) -> WasmResult<()> {
-        Err(wasm_unsupported!("can not export global {}", name))
+    fn declare_global_export(&mut self, global_index: GlobalIndex, name: &str) -> WasmResult<()> {
+        self.check_export_name(name)?;
+        self.result
+            .module
+            .global_exports
+            .insert(name.to_string(), global_index);
+        Ok(())
     }
 
     fn declare_start_func(&mut self, _func_index: FuncIndex) -> WasmResult<()> {
-        Err(wasm_unsupported!("can not has start func"))
+        self.violation(wasm_unsupported!("can not has start func"))
     }
 
     fn reserve_table_elements(&mut self, num: u32) -> WasmResult<()> {
         self.result
             .module
             .table_elements
-            .reserve_exact(usize::try_from(num).unwrap());
+            .reserve_exact(reserved_entries(num));
         Ok(())
     }
 
@@ -287,6 +762,30 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
         body_bytes: &'data [u8],
         body_offset: usize,
     ) -> WasmResult<()> {
+        if let Some(limit) = self.result.tunables.max_function_body_bytes {
+            if body_bytes.len() as u64 > u64::from(limit) {
+                let index = self.result.function_body_inputs.len();
+                self.violation(wasm_unsupported!(
+                    "body of defined function {} exceeds max_function_body_bytes: {} > {}",
+                    index,
+                    body_bytes.len(),
+                    limit
+                ))?;
+            }
+        }
+        let (count, uses_float) = scan_body(body_bytes, body_offset, self.result.uses_float)?;
+        self.result.uses_float = uses_float;
+        if let Some(limit) = self.result.tunables.max_locals {
+            if count > u64::from(limit) {
+                let index = self.result.function_body_inputs.len();
+                self.violation(wasm_unsupported!(
+                    "defined function {} exceeds max_locals: {} > {}",
+                    index,
+                    count,
+                    limit
+                ))?;
+            }
+        }
         self.result.function_body_inputs.push(FunctionBodyData {
             data: body_bytes,
             module_offset: body_offset,
@@ -297,7 +796,23 @@ impl<'data> cranelift_wasm::ModuleEnvironment<'data> for ModuleEnvironment<'data
     fn reserve_data_initializers(&mut self, num: u32) -> WasmResult<()> {
         self.result
             .data_initializers
-            .reserve_exact(usize::try_from(num).unwrap());
+            .reserve_exact(reserved_entries(num));
+        Ok(())
+    }
+
+    /// Collect the function and local names from the custom name section,
+    /// the other custom sections are skipped.
+    ///
+    /// The name section is only used for diagnostics, so a malformed one is
+    /// ignored instead of rejecting the module: the names read before the
+    /// first error are kept.
+    fn custom_section(&mut self, name: &'data str, data: &'data [u8]) -> WasmResult<()> {
+        if name == "name" {
+            // the errors are ignored, their offset does not matter
+            if let Ok(names) = NameSectionReader::new(data, 0) {
+                self.declare_name_subsections(names);
+            }
+        }
         Ok(())
     }
 
@@ -332,7 +847,7 @@ pub fn translate_signature(mut sig: ir::Signature, pointer_type: ir::Type) -> ir
 
 /// A memory index and offset within that memory where a data initialization
 /// should is to be performed.
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct DataInitializerLocation {
     /// The index of the memory to initialize.
     pub memory_index: MemoryIndex,
@@ -364,6 +879,7 @@ impl DataInitializer<'_> {
 }
 
 /// A data initializer for linear memory.
+#[derive(Serialize, Deserialize, Clone)]
 pub struct OwnedDataInitializer {
     /// The location where the initialization is to be performed.
     pub location: DataInitializerLocation,
@@ -380,3 +896,48 @@ impl<'data> Into<DataInitializer<'data>> for &'data OwnedDataInitializer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ModuleEnvironment;
+    use crate::tunables::Tunables;
+    use cranelift_codegen::ir;
+    use cranelift_codegen::isa::{CallConv, TargetFrontendConfig};
+    use cranelift_entity::EntityRef;
+    use cranelift_wasm::{FuncIndex, ModuleEnvironment as _, SignatureIndex};
+    use target_lexicon::PointerWidth;
+
+    #[test]
+    fn reserve_count_exceeds_declarations() {
+        let config = TargetFrontendConfig {
+            default_call_conv: CallConv::SystemV,
+            pointer_width: PointerWidth::U64,
+        };
+        let mut env = ModuleEnvironment::new(config, Tunables::default());
+
+        env.reserve_signatures(u32::max_value()).unwrap();
+        let mut sig = ir::Signature::new(CallConv::SystemV);
+        sig.returns.push(ir::AbiParam::new(ir::types::I32));
+        env.declare_signature(sig).unwrap();
+        env.reserve_func_types(u32::max_value()).unwrap();
+        env.declare_func_type(SignatureIndex::new(0)).unwrap();
+        env.reserve_exports(u32::max_value()).unwrap();
+        env.declare_func_export(FuncIndex::new(0), "invoke")
+            .unwrap();
+        env.reserve_globals(u32::max_value()).unwrap();
+        env.reserve_tables(u32::max_value()).unwrap();
+        env.reserve_memories(u32::max_value()).unwrap();
+        env.reserve_table_elements(u32::max_value()).unwrap();
+        env.reserve_data_initializers(u32::max_value()).unwrap();
+
+        let module = &env.result.module;
+        assert_eq!(module.signatures.len(), 1);
+        assert_eq!(module.functions.len(), 1);
+        assert_eq!(module.functions[FuncIndex::new(0)], SignatureIndex::new(0));
+        assert_eq!(module.exports.get("invoke"), Some(&FuncIndex::new(0)));
+        assert!(module.globals.is_empty());
+        assert!(module.table_plans.is_empty());
+        assert!(module.memory_plans.is_empty());
+        assert!(env.result.data_initializers.is_empty());
+    }
+}
@@ -0,0 +1,49 @@
+//! A registry of function signatures shared by the compilations of several
+//! modules.
+
+use cranelift_codegen::ir;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::RwLock;
+
+/// Interns the signatures declared by the modules compiled with it, so that
+/// identical signatures of different modules get the same id.
+///
+/// The ids are only meaningful within the process, they are not part of the
+/// serialized module. The registry can be shared by threads compiling
+/// modules concurrently.
+#[derive(Debug, Default)]
+pub struct SignatureRegistry {
+    ids: RwLock<HashMap<ir::Signature, u32>>,
+}
+
+impl SignatureRegistry {
+    /// Create an empty `SignatureRegistry`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the id of `sig`, registering it if it is new.
+    pub fn register(&self, sig: &ir::Signature) -> u32 {
+        if let Some(&id) = self.ids.read().unwrap().get(sig) {
+            return id;
+        }
+        let mut ids = self.ids.write().unwrap();
+        let len = ids.len();
+        *ids.entry(sig.clone()).or_insert_with(|| {
+            // `u32::MAX` is reserved for the default `VMSharedSignatureIndex`.
+            debug_assert!(len < u32::max_value() as usize);
+            u32::try_from(len).unwrap()
+        })
+    }
+
+    /// The number of distinct signatures registered.
+    pub fn len(&self) -> usize {
+        self.ids.read().unwrap().len()
+    }
+
+    /// Whether no signature is registered yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
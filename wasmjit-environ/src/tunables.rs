@@ -1,5 +1,156 @@
+use crate::WASM_MAX_PAGES;
+use cranelift_wasm::{WasmError, WasmResult};
+use serde::{Deserialize, Serialize};
+
+/// Optimization level of the code generated by cranelift.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptLevel {
+    /// No optimization, fastest compilation.
+    None,
+    /// Optimize for the speed of the generated code.
+    Speed,
+    /// Optimize for both speed and size of the generated code.
+    SpeedAndSize,
+}
+
+impl OptLevel {
+    /// Value of the cranelift `opt_level` setting.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OptLevel::None => "none",
+            OptLevel::Speed => "speed",
+            OptLevel::SpeedAndSize => "speed_and_size",
+        }
+    }
+}
+
+/// A WebAssembly proposal beyond the MVP, a module can only use it when it is
+/// enabled in `Features`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feature {
+    /// The fixed-width SIMD proposal.
+    Simd,
+    /// The sign-extension operators proposal.
+    SignExtension,
+    /// The non-trapping float-to-int conversions proposal.
+    SaturatingFloatToInt,
+    /// The bulk memory operations proposal.
+    BulkMemory,
+    /// The reference types proposal.
+    ReferenceTypes,
+    /// The threads proposal.
+    Threads,
+}
+
+impl Feature {
+    /// Name of the feature, the one of its field in `Features`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Simd => "simd",
+            Feature::SignExtension => "sign_extension",
+            Feature::SaturatingFloatToInt => "saturating_float_to_int",
+            Feature::BulkMemory => "bulk_memory",
+            Feature::ReferenceTypes => "reference_types",
+            Feature::Threads => "threads",
+        }
+    }
+
+    /// Name of the proposal.
+    pub fn proposal(self) -> &'static str {
+        match self {
+            Feature::Simd => "SIMD",
+            Feature::SignExtension => "sign-extension",
+            Feature::SaturatingFloatToInt => "non-trapping float-to-int",
+            Feature::BulkMemory => "bulk memory",
+            Feature::ReferenceTypes => "reference types",
+            Feature::Threads => "threads",
+        }
+    }
+
+    /// Whether the engine can execute the proposal once enabled. The pinned
+    /// cranelift-wasm has no lowering for the others, see the limitations in
+    /// the README.
+    pub fn is_supported(self) -> bool {
+        match self {
+            Feature::Simd | Feature::SignExtension | Feature::SaturatingFloatToInt => true,
+            Feature::BulkMemory | Feature::ReferenceTypes | Feature::Threads => false,
+        }
+    }
+}
+
+/// The proposals a module may use. A module using a disabled one is rejected
+/// at translation with an error naming the feature.
+///
+/// The defaults are part of the consensus: enabling a proposal by default
+/// changes which contracts can be deployed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Features {
+    /// Accept the fixed-width SIMD operators. The float lanes are rejected
+    /// along with the scalar floats.
+    pub simd: bool,
+    /// Accept the sign-extension operators, `i32.extend8_s` and the like.
+    pub sign_extension: bool,
+    /// Accept the saturating float to int conversions, `i32.trunc_sat_f32_s`
+    /// and the like.
+    pub saturating_float_to_int: bool,
+    /// The bulk memory operators, not supported.
+    pub bulk_memory: bool,
+    /// The reference types operators, not supported.
+    pub reference_types: bool,
+    /// Shared memories and atomic operators, not supported.
+    pub threads: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            simd: false,
+            sign_extension: true,
+            saturating_float_to_int: true,
+            bulk_memory: false,
+            reference_types: false,
+            threads: false,
+        }
+    }
+}
+
+impl Features {
+    /// Whether `feature` is enabled.
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::Simd => self.simd,
+            Feature::SignExtension => self.sign_extension,
+            Feature::SaturatingFloatToInt => self.saturating_float_to_int,
+            Feature::BulkMemory => self.bulk_memory,
+            Feature::ReferenceTypes => self.reference_types,
+            Feature::Threads => self.threads,
+        }
+    }
+
+    /// Check that the module can use `feature` for `what`, the use is named
+    /// in the error.
+    pub(crate) fn require(&self, feature: Feature, what: &str) -> WasmResult<()> {
+        if !self.is_enabled(feature) {
+            Err(WasmError::Unsupported(format!(
+                "{} proposal: {}, feature `{}` is disabled",
+                feature.proposal(),
+                what,
+                feature.name()
+            )))
+        } else if !feature.is_supported() {
+            Err(WasmError::Unsupported(format!(
+                "{} proposal: {} is not supported",
+                feature.proposal(),
+                what
+            )))
+        } else {
+            Ok(())
+        }
+    }
+}
+
 /// Tunable parameters for WebAssembly compilation.
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Tunables {
     /// For static heaps, the size of the heap protected by bounds checking.
     pub static_memory_bound: u32,
@@ -9,6 +160,106 @@ pub struct Tunables {
 
     /// The size of the offset guard for dynamic heaps.
     pub dynamic_memory_offset_guard_size: u64,
+
+    /// Minimum number of wasm pages of address space reserved up front for
+    /// a dynamic heap, capped by its declared maximum. The grows within the
+    /// reservation only make pages accessible instead of moving the memory,
+    /// the accessible size is still the declared minimum until grown.
+    pub dynamic_memory_reserved_pages: u32,
+
+    /// Reserve guard pages around the linear memories so that the code can
+    /// skip bounds checks. When disabled all memories are dynamic without
+    /// offset guard, every access is bounds checked explicitly and no large
+    /// address space reservation is made.
+    pub guard_pages: bool,
+
+    /// Optimization level passed to cranelift.
+    pub opt_level: OptLevel,
+
+    /// For tables with a declared maximum, the number of elements up to which
+    /// the whole table storage is reserved up front.
+    pub static_table_bound: u32,
+
+    /// Canonicalize the NaN values produced by float operations.
+    pub nan_canonicalization: bool,
+
+    /// Reject modules using floating point types or operators.
+    pub reject_float: bool,
+
+    /// Emit explicit checks for the division traps instead of relying on the
+    /// hardware, so that the trap code does not depend on the target.
+    pub avoid_div_traps: bool,
+
+    /// Whether the consensus-safe settings are locked in, see `set_deterministic`.
+    pub deterministic: bool,
+
+    /// The proposals the modules may use.
+    pub features: Features,
+
+    /// Maximum number of functions defined by a module.
+    pub max_functions: Option<u32>,
+
+    /// Maximum number of elements of a table, checked against the declared
+    /// minimum and maximum of each table. It caps the declared maximum, the
+    /// growth of a table beyond it fails.
+    pub max_table_size: Option<u32>,
+
+    /// Maximum number of globals of a module.
+    pub max_globals: Option<u32>,
+
+    /// Maximum number of imports of a module.
+    pub max_imports: Option<u32>,
+
+    /// Maximum total size in bytes of the data initializers of a module, as
+    /// declared, see `ModuleTranslation::data_bytes`.
+    pub max_data_bytes: Option<u64>,
+
+    /// Maximum size in bytes of a function body.
+    pub max_function_body_bytes: Option<u32>,
+
+    /// Maximum number of locals declared by a function, parameters excluded.
+    pub max_locals: Option<u32>,
+
+    /// Maximum number of pages of a linear memory. It caps the declared
+    /// maximum, `memory.grow` beyond it fails without charging gas.
+    pub max_memory_pages: Option<u32>,
+
+    /// Size in bytes of the native stack the calls run on, on a dedicated
+    /// thread. `None` runs them on the stack of the calling thread.
+    ///
+    /// The call depth limit of the execution is the guard against deep
+    /// recursion, the native stack must be large enough for it to trip first:
+    /// provision the depth limit times the largest frame of the module (its
+    /// locals, spills and argument area), plus the stack of the host
+    /// functions. An exhausted native stack traps with `StackOverflow`
+    /// instead of the depth trap.
+    pub native_stack_bytes: Option<usize>,
+}
+
+impl Tunables {
+    /// Start building tunables from the defaults, see `TunablesBuilder`.
+    pub fn builder() -> TunablesBuilder {
+        TunablesBuilder::default()
+    }
+
+    /// Create the default tunables with the consensus-safe settings turned
+    /// on or off, see `set_deterministic`.
+    pub fn deterministic(enable: bool) -> Self {
+        let mut tunables = Self::default();
+        tunables.set_deterministic(enable);
+        tunables
+    }
+
+    /// Turn on or off in one go all the settings a deterministic execution
+    /// across nodes depends on: NaN canonicalization, float rejection and
+    /// explicit division traps. The code is also generated for the baseline
+    /// x86-64, without the extensions the host may support.
+    pub fn set_deterministic(&mut self, enable: bool) {
+        self.deterministic = enable;
+        self.nan_canonicalization = enable;
+        self.reject_float = enable;
+        self.avoid_div_traps = enable;
+    }
 }
 
 impl Default for Tunables {
@@ -39,6 +290,319 @@ impl Default for Tunables {
             /// Allocate a small guard to optimize common cases but without
             /// wasting too much memor.
             dynamic_memory_offset_guard_size: 0x1_0000,
+
+            /// Reserve no more than the declared minimum, a dynamic memory
+            /// moves on every grow.
+            dynamic_memory_reserved_pages: 0,
+
+            guard_pages: true,
+
+            /// Skip the optimization passes, compile time matters more than
+            /// the code quality for most contracts.
+            opt_level: OptLevel::None,
+
+            /// Number of elements of the biggest table reserved up front.
+            ///
+            /// Such table never moves, and if its size can not change it is
+            /// bounds checked against a constant.
+            static_table_bound: 0x1000,
+
+            nan_canonicalization: false,
+            reject_float: false,
+            avoid_div_traps: false,
+            deterministic: false,
+            features: Features::default(),
+
+            /// The resource limits are left to the embedder.
+            max_functions: None,
+            max_table_size: None,
+            max_globals: None,
+            max_imports: None,
+            max_data_bytes: None,
+            max_function_body_bytes: None,
+            max_locals: None,
+            max_memory_pages: None,
+            native_stack_bytes: None,
+        }
+    }
+}
+
+/// Inconsistent settings rejected by `TunablesBuilder::build`.
+#[derive(Fail, Debug, PartialEq, Eq)]
+#[fail(display = "invalid tunables: {}", _0)]
+pub struct InvalidTunables(pub String);
+
+/// Builds `Tunables` with fluent setters, checking the settings against each
+/// other in `build`.
+///
+/// The settings left unset keep the values of `Tunables::default`, except
+/// that `deterministic` turns on the settings it depends on unless they are
+/// set explicitly, which is then an error.
+#[derive(Default)]
+pub struct TunablesBuilder {
+    tunables: Tunables,
+    deterministic: bool,
+    nan_canonicalization: Option<bool>,
+    reject_float: Option<bool>,
+    avoid_div_traps: Option<bool>,
+}
+
+macro_rules! setters {
+    ($($(#[$doc:meta])* $name:ident: $ty:ty => $value:expr;)*) => {
+        $(
+            $(#[$doc])*
+            pub fn $name(mut self, $name: $ty) -> Self {
+                self.tunables.$name = $value;
+                self
+            }
+        )*
+    };
+}
+
+impl TunablesBuilder {
+    setters! {
+        /// See `Tunables::static_memory_bound`.
+        static_memory_bound: u32 => static_memory_bound;
+        /// See `Tunables::static_memory_offset_guard_size`.
+        static_memory_offset_guard_size: u64 => static_memory_offset_guard_size;
+        /// See `Tunables::dynamic_memory_offset_guard_size`.
+        dynamic_memory_offset_guard_size: u64 => dynamic_memory_offset_guard_size;
+        /// See `Tunables::dynamic_memory_reserved_pages`.
+        dynamic_memory_reserved_pages: u32 => dynamic_memory_reserved_pages;
+        /// See `Tunables::guard_pages`.
+        guard_pages: bool => guard_pages;
+        /// See `Tunables::opt_level`.
+        opt_level: OptLevel => opt_level;
+        /// See `Tunables::static_table_bound`.
+        static_table_bound: u32 => static_table_bound;
+        /// See `Tunables::features`.
+        features: Features => features;
+        /// See `Tunables::max_functions`.
+        max_functions: u32 => Some(max_functions);
+        /// See `Tunables::max_table_size`.
+        max_table_size: u32 => Some(max_table_size);
+        /// See `Tunables::max_globals`.
+        max_globals: u32 => Some(max_globals);
+        /// See `Tunables::max_imports`.
+        max_imports: u32 => Some(max_imports);
+        /// See `Tunables::max_data_bytes`.
+        max_data_bytes: u64 => Some(max_data_bytes);
+        /// See `Tunables::max_function_body_bytes`.
+        max_function_body_bytes: u32 => Some(max_function_body_bytes);
+        /// See `Tunables::max_locals`.
+        max_locals: u32 => Some(max_locals);
+        /// See `Tunables::max_memory_pages`.
+        max_memory_pages: u32 => Some(max_memory_pages);
+        /// See `Tunables::native_stack_bytes`.
+        native_stack_bytes: usize => Some(native_stack_bytes);
+    }
+
+    /// See `Tunables::nan_canonicalization`.
+    pub fn nan_canonicalization(mut self, enable: bool) -> Self {
+        self.nan_canonicalization = Some(enable);
+        self
+    }
+
+    /// See `Tunables::reject_float`.
+    pub fn reject_float(mut self, enable: bool) -> Self {
+        self.reject_float = Some(enable);
+        self
+    }
+
+    /// See `Tunables::avoid_div_traps`.
+    pub fn avoid_div_traps(mut self, enable: bool) -> Self {
+        self.avoid_div_traps = Some(enable);
+        self
+    }
+
+    /// Lock in the consensus-safe settings, see `Tunables::set_deterministic`.
+    pub fn deterministic(mut self, enable: bool) -> Self {
+        self.deterministic = enable;
+        self
+    }
+
+    /// Build the tunables, rejecting inconsistent settings:
+    ///
+    /// - `deterministic` with any of the settings it depends on turned off,
+    /// - a `max_memory_pages` of zero pages, or reaching the 4 GiB of a 32
+    ///   bits memory, which a memory can never grow to,
+    /// - a `max_memory_pages` smaller than the `static_memory_bound` with the
+    ///   guard pages on, or than the `dynamic_memory_reserved_pages`, the
+    ///   reservation would be partly unusable,
+    /// - a `dynamic_memory_reserved_pages` beyond the 4 GiB of a 32 bits
+    ///   memory,
+    /// - a `native_stack_bytes` of zero,
+    /// - an enabled feature the engine does not support.
+    pub fn build(self) -> Result<Tunables, InvalidTunables> {
+        let mut tunables = self.tunables;
+        tunables.set_deterministic(self.deterministic);
+        let deterministic = self.deterministic;
+        let set = |name, value: Option<bool>, setting: &mut bool| match value {
+            Some(false) if deterministic => Err(InvalidTunables(format!(
+                "{} can not be turned off for a deterministic execution",
+                name
+            ))),
+            Some(value) => {
+                *setting = value;
+                Ok(())
+            }
+            None => Ok(()),
+        };
+        set(
+            "nan_canonicalization",
+            self.nan_canonicalization,
+            &mut tunables.nan_canonicalization,
+        )?;
+        set(
+            "reject_float",
+            self.reject_float,
+            &mut tunables.reject_float,
+        )?;
+        set(
+            "avoid_div_traps",
+            self.avoid_div_traps,
+            &mut tunables.avoid_div_traps,
+        )?;
+
+        if let Some(pages) = tunables.max_memory_pages {
+            if pages == 0 || pages >= WASM_MAX_PAGES {
+                return Err(InvalidTunables(format!(
+                    "max_memory_pages {} out of 1..{}",
+                    pages, WASM_MAX_PAGES
+                )));
+            }
+            if tunables.guard_pages && pages < tunables.static_memory_bound {
+                return Err(InvalidTunables(format!(
+                    "max_memory_pages {} smaller than static_memory_bound {}",
+                    pages, tunables.static_memory_bound
+                )));
+            }
+            if pages < tunables.dynamic_memory_reserved_pages {
+                return Err(InvalidTunables(format!(
+                    "max_memory_pages {} smaller than dynamic_memory_reserved_pages {}",
+                    pages, tunables.dynamic_memory_reserved_pages
+                )));
+            }
+        }
+        if tunables.dynamic_memory_reserved_pages > WASM_MAX_PAGES {
+            return Err(InvalidTunables(format!(
+                "dynamic_memory_reserved_pages {} beyond {}",
+                tunables.dynamic_memory_reserved_pages, WASM_MAX_PAGES
+            )));
+        }
+        if tunables.native_stack_bytes == Some(0) {
+            return Err(InvalidTunables("native_stack_bytes of zero".to_string()));
         }
+        let unsupported = [
+            Feature::BulkMemory,
+            Feature::ReferenceTypes,
+            Feature::Threads,
+        ];
+        if let Some(feature) = unsupported
+            .iter()
+            .find(|&&feature| tunables.features.is_enabled(feature))
+        {
+            return Err(InvalidTunables(format!(
+                "feature `{}` is not supported",
+                feature.name()
+            )));
+        }
+        Ok(tunables)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Features, InvalidTunables, OptLevel, Tunables, TunablesBuilder};
+
+    #[test]
+    fn default_features() {
+        // enabling a proposal by default must be a deliberate change
+        assert_eq!(
+            Features::default(),
+            Features {
+                simd: false,
+                sign_extension: true,
+                saturating_float_to_int: true,
+                bulk_memory: false,
+                reference_types: false,
+                threads: false,
+            }
+        );
+        assert_eq!(Tunables::default().features, Features::default());
+    }
+
+    #[test]
+    fn builder_defaults() {
+        let tunables = Tunables::builder().build().unwrap();
+        let default = Tunables::default();
+        assert_eq!(tunables.static_memory_bound, default.static_memory_bound);
+        assert_eq!(tunables.guard_pages, default.guard_pages);
+        assert_eq!(tunables.opt_level, OptLevel::None);
+        assert!(!tunables.deterministic);
+        assert!(!tunables.reject_float);
+        assert_eq!(tunables.max_memory_pages, None);
+        assert_eq!(tunables.native_stack_bytes, None);
+    }
+
+    #[test]
+    fn builder_setters() {
+        let tunables = Tunables::builder()
+            .guard_pages(false)
+            .opt_level(OptLevel::Speed)
+            .max_memory_pages(16)
+            .max_table_size(8)
+            .native_stack_bytes(1 << 20)
+            .deterministic(true)
+            .build()
+            .unwrap();
+        assert!(!tunables.guard_pages);
+        assert_eq!(tunables.opt_level, OptLevel::Speed);
+        assert_eq!(tunables.max_memory_pages, Some(16));
+        assert_eq!(tunables.max_table_size, Some(8));
+        assert_eq!(tunables.native_stack_bytes, Some(1 << 20));
+        assert!(tunables.deterministic);
+        assert!(tunables.nan_canonicalization && tunables.reject_float && tunables.avoid_div_traps);
+
+        let tunables = Tunables::builder().reject_float(true).build().unwrap();
+        assert!(tunables.reject_float && !tunables.deterministic);
+    }
+
+    #[test]
+    fn builder_validation() {
+        let error = |builder: TunablesBuilder| builder.build().err().map(|e| e.0);
+        assert_eq!(
+            error(Tunables::builder().deterministic(true).reject_float(false)),
+            Some("reject_float can not be turned off for a deterministic execution".to_string())
+        );
+        let capped = |pages| {
+            Tunables::builder()
+                .static_memory_bound(0x100)
+                .max_memory_pages(pages)
+        };
+        assert!(error(capped(0)).is_some());
+        assert!(error(capped(0x1_0001)).is_some());
+        // a memory can not grow to the 4 GiB
+        assert!(error(capped(0x1_0000)).is_some());
+        assert!(error(capped(0xffff)).is_none());
+        assert!(error(capped(0x100)).is_none());
+        assert_eq!(
+            error(capped(0x80)),
+            Some("max_memory_pages 128 smaller than static_memory_bound 256".to_string())
+        );
+        assert!(error(capped(0x80).guard_pages(false)).is_none());
+        assert_eq!(
+            error(capped(0x100).dynamic_memory_reserved_pages(0x200)),
+            Some("max_memory_pages 256 smaller than dynamic_memory_reserved_pages 512".to_string())
+        );
+        assert!(error(Tunables::builder().native_stack_bytes(0)).is_some());
+        let threads = Features {
+            threads: true,
+            ..Features::default()
+        };
+        assert_eq!(
+            error(Tunables::builder().features(threads)),
+            Some("feature `threads` is not supported".to_string())
+        );
     }
 }
@@ -42,7 +42,7 @@ impl VMOffsets {
             pointer_size,
             num_signature_ids: cast_to_u32(module.signatures.len()),
             num_imported_functions: cast_to_u32(module.imported_funcs.len()),
-            num_defined_tables: cast_to_u32(module.tables.len()),
+            num_defined_tables: cast_to_u32(module.table_plans.len()),
             num_defined_memories: cast_to_u32(module.memory_plans.len()),
             num_defined_globals: cast_to_u32(module.globals.len()),
         }
@@ -57,11 +57,16 @@ impl VMOffsets {
         0 * self.pointer_size
     }
 
-    /// Return the size of `VMFunctionImport`.
+    /// The offset of the `vmctx` field.
     #[allow(clippy::identity_op)]
-    pub fn size_of_vmfunction_import(&self) -> u8 {
+    pub fn vmfunction_import_vmctx(&self) -> u8 {
         1 * self.pointer_size
     }
+
+    /// Return the size of `VMFunctionImport`.
+    pub fn size_of_vmfunction_import(&self) -> u8 {
+        2 * self.pointer_size
+    }
 }
 
 /// Offsets for `*const VMFunctionBody`.
@@ -323,6 +328,13 @@ impl VMOffsets {
             .unwrap()
     }
 
+    /// Return the offset to the `vmctx` field in `*const VMFunctionBody` index `index`.
+    pub fn vmctx_vmfunction_import_vmctx(&self, index: FuncIndex) -> u32 {
+        self.vmctx_vmfunction_import(index)
+            .checked_add(u32::from(self.vmfunction_import_vmctx()))
+            .unwrap()
+    }
+
     /// Return the offset to the `base` field in `VMTableDefinition` index `index`.
     pub fn vmctx_vmtable_definition_base(&self, index: DefinedTableIndex) -> u32 {
         self.vmctx_vmtable_definition(index)
@@ -60,12 +60,8 @@ fn evaluate_expressions(exps: &[Expression<'_>]) -> Option<Vec<i64>> {
     Some(args.into_iter().map(Option::unwrap).collect())
 }
 
-fn run_spec_file(file: &str, test_count: &mut usize) -> Result<Vec<TestDescAndFn>> {
-    let path = if file == "gas_test.wast" || file == "depth_check.wast" || file == "test.wast" {
-        format!("tests/{}", file)
-    } else {
-        format!("spectests/{}", file)
-    };
+fn run_spec_file(dir: &str, file: &str, test_count: &mut usize) -> Result<Vec<TestDescAndFn>> {
+    let path = format!("{}/{}", dir, file);
     println!("test file path: {}", path);
 
     let mut contents = String::new();
@@ -253,14 +249,14 @@ fn main() {
                 continue;
             }
 
-            funcs.extend(run_spec_file(file, &mut test_count).unwrap());
+            funcs.extend(run_spec_file("spectests", file, &mut test_count).unwrap());
         }
     }
 
     for entry in glob("tests/*.wast").unwrap() {
         if let Ok(path) = entry {
             let file = path.file_name().unwrap().to_str().unwrap();
-            funcs.extend(run_spec_file(file, &mut test_count).unwrap());
+            funcs.extend(run_spec_file("tests", file, &mut test_count).unwrap());
         }
     }
 
@@ -1,5 +1,6 @@
 #![cfg(test)]
 mod tests {
+    use std::ptr;
     use std::sync::{Arc, Barrier};
     use std::thread;
 
@@ -75,6 +76,7 @@ mod tests {
         match field {
             "ontio_invoke" => Some(VMFunctionImport {
                 body: ontio_invoke as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             _ => None,
         }
@@ -84,6 +86,7 @@ mod tests {
         match field {
             "ontio_invoke" => Some(VMFunctionImport {
                 body: ontio_invoke_host_panic as *const VMFunctionBody,
+                vmctx: ptr::null_mut(),
             }),
             _ => None,
         }
@@ -404,7 +404,7 @@ static
 __attribute__ ((warn_unused_result))
 #endif
 bool
-HandleTrap(CONTEXT* context)
+HandleTrap(CONTEXT* context, const uint8_t* faultAddr)
 {
     assert(sAlreadyHandlingTrap);
 
@@ -412,7 +412,7 @@ HandleTrap(CONTEXT* context)
         return false;
     }
 
-    RecordTrap(ContextToPC(context));
+    RecordTrap(ContextToPC(context), faultAddr);
 
     // Unwind calls longjmp, so it doesn't run the automatic
     // sAlreadhHanldingTrap cleanups, so reset it manually before doing
@@ -467,7 +467,12 @@ WasmTrapHandler(LPEXCEPTION_POINTERS exception)
         return EXCEPTION_CONTINUE_SEARCH;
     }
 
-    if (!HandleTrap(exception->ContextRecord)) {
+    const uint8_t* faultAddr = nullptr;
+    if (record->ExceptionCode == EXCEPTION_ACCESS_VIOLATION) {
+        faultAddr = reinterpret_cast<const uint8_t*>(record->ExceptionInformation[1]);
+    }
+
+    if (!HandleTrap(exception->ContextRecord, faultAddr)) {
         return EXCEPTION_CONTINUE_SEARCH;
     }
 
@@ -549,7 +554,7 @@ HandleMachException(const ExceptionRequest& request)
 
     {
         AutoHandlingTrap aht;
-        if (!HandleTrap(&context)) {
+        if (!HandleTrap(&context, nullptr)) {
             return false;
         }
     }
@@ -632,7 +637,11 @@ WasmTrapHandler(int signum, siginfo_t* info, void* context)
     if (!sAlreadyHandlingTrap) {
         AutoHandlingTrap aht;
         assert(signum == SIGSEGV || signum == SIGBUS || signum == SIGFPE || signum == SIGILL);
-        if (HandleTrap(static_cast<CONTEXT*>(context))) {
+        const uint8_t* faultAddr = nullptr;
+        if (signum == SIGSEGV || signum == SIGBUS) {
+            faultAddr = static_cast<const uint8_t*>(info->si_addr);
+        }
+        if (HandleTrap(static_cast<CONTEXT*>(context), faultAddr)) {
             return;
         }
     }
@@ -12,8 +12,9 @@ extern "C" {
 #endif
 
 int8_t CheckIfTrapAtAddress(const uint8_t* pc);
-// Record the Trap code and wasm bytecode offset in TLS somewhere
-void RecordTrap(const uint8_t* pc);
+// Record the Trap code and wasm bytecode offset in TLS somewhere, along with
+// the faulting data address if known, or null.
+void RecordTrap(const uint8_t* pc, const uint8_t* faultAddr);
 
 void* EnterScope(void*);
 void LeaveScope(void*);
@@ -3,11 +3,13 @@
 //! inline rather than calling them, particularly when CPUs have special
 //! instructions which compute them directly.
 
-use crate::instance::Instance;
+use crate::instance::{HostCallPhase, Instance};
+use crate::traphandlers::{wasmjit_trap, TrapCode};
 use crate::vmcontext::VMContext;
 use crate::wasmjit_unwind;
-use cranelift_wasm::DefinedMemoryIndex;
+use cranelift_wasm::{DefinedMemoryIndex, FuncIndex};
 use std::panic::{self, AssertUnwindSafe};
+use std::slice;
 use std::sync::atomic::Ordering;
 
 /// trap_kind
@@ -59,6 +61,22 @@ where
     }
 }
 
+/// Run a host function which may trap the guest: an `Err(code)` unwinds the
+/// wasm stack back to the embedder as a trap with `code`, like a trap raised
+/// by the wasm code itself. A panic is caught like `check_host_panic` does.
+pub fn check_host_trap<F, U>(instance: &mut Instance, func: F) -> U
+where
+    F: FnOnce(&mut Instance) -> Result<U, TrapCode> + panic::UnwindSafe,
+{
+    match check_host_panic(instance, |instance| Ok(func(instance))) {
+        Ok(u) => u,
+        Err(code) => unsafe {
+            instance.set_trap_kind(wasmjit_result_err_trap);
+            wasmjit_trap(code, format!("wasmjit: host function trapped: {:?}", code))
+        },
+    }
+}
+
 /// catch panic of rust host/builtins function.
 pub fn check_internel_panic<F, U>(f: F) -> Result<U, String>
 where
@@ -78,6 +96,14 @@ where
 }
 
 /// Implementation of memory.grow for locally-defined 32-bit memories.
+///
+/// A grow beyond the maximum of the memory fails before any gas is charged,
+/// otherwise `delta * gas_factor` is charged before the memory grows, so that
+/// running out of gas traps with the memory unchanged.
+///
+/// The charge only depends on the requested pages and the declared maximum,
+/// never on the allocation: a grow the host fails to allocate returns -1 with
+/// its gas already charged, the same on every node.
 #[no_mangle]
 pub unsafe extern "C" fn wasmjit_memory32_grow(
     vmctx: *mut VMContext,
@@ -86,17 +112,22 @@ pub unsafe extern "C" fn wasmjit_memory32_grow(
     gas_factor: u32,
 ) -> u32 {
     check_host_panic((&mut *vmctx).instance(), |instance| {
-        match instance.memory_slice_mut(DefinedMemoryIndex::from_u32(memory_index)) {
+        let index = DefinedMemoryIndex::from_u32(memory_index);
+        match instance.memory_slice_mut(index) {
             Some(_) => {}
             None => return Err(String::from("wasmjit: grow of memory index not defined")),
         };
 
+        if !instance.memory_can_grow(index, delta) {
+            return Ok(u32::max_value());
+        }
+
         if gas_factor != 0 {
-            wasmjit_check_gas(vmctx, delta * gas_factor);
+            wasmjit_check_gas(vmctx, delta.saturating_mul(gas_factor));
         }
 
         Ok(instance
-            .memory_grow(DefinedMemoryIndex::from_u32(memory_index), delta)
+            .memory_grow(index, delta)
             .unwrap_or(u32::max_value()))
     })
 }
@@ -118,6 +149,25 @@ pub unsafe extern "C" fn wasmjit_memory32_size(vmctx: *mut VMContext, memory_ind
 #[no_mangle]
 pub unsafe extern "C" fn wasmjit_check_gas(vmctx: *mut VMContext, costs: u32) {
     check_host_panic((&mut *vmctx).instance(), |instance| {
+        if instance.exec_metrics.timed_out.load(Ordering::Relaxed) {
+            instance.set_trap_kind(wasmjit_result_err_trap);
+            wasmjit_trap(
+                TrapCode::Timeout,
+                String::from("wasmjit: execution timeout"),
+            );
+        }
+        let cancelled = match instance.cancel_flag {
+            Some(ref flag) => flag.load(Ordering::Relaxed),
+            None => false,
+        };
+        if cancelled {
+            instance.set_trap_kind(wasmjit_result_err_trap);
+            wasmjit_trap(
+                TrapCode::Cancelled,
+                String::from("wasmjit: execution cancelled"),
+            );
+        }
+
         let costs = costs as u64;
 
         if instance.exec_metrics.exec_step_left.load(Ordering::Relaxed) < costs {
@@ -133,17 +183,9 @@ pub unsafe extern "C" fn wasmjit_check_gas(vmctx: *mut VMContext, costs: u32) {
                 .fetch_sub(costs, Ordering::Relaxed);
         }
 
-        instance.local_gas_counter += costs;
-
-        let gas_factor = instance.exec_metrics.gas_factor.load(Ordering::Relaxed);
-        let normalize_costs = instance.local_gas_counter / gas_factor;
-        if normalize_costs == 0 {
-            return Ok(());
-        }
-
-        instance.local_gas_counter %= gas_factor;
-        if !instance.check_gas(normalize_costs) {
-            return Err(String::from("wasmjit: gas exhausted"));
+        if !instance.charge_gas(costs) {
+            instance.set_trap_kind(wasmjit_result_err_trap);
+            wasmjit_trap(TrapCode::OutOfGas, String::from("wasmjit: gas exhausted"));
         }
 
         Ok(())
@@ -174,3 +216,54 @@ pub unsafe extern "C" fn wasmjit_check_depth(vmctx: *mut VMContext, count: i32)
         Ok(())
     });
 }
+
+/// Implementation of host call tracing, reports the values of a call of an
+/// imported function to the host call hook of the instance.
+#[no_mangle]
+pub unsafe extern "C" fn wasmjit_trace_host_call(
+    vmctx: *mut VMContext,
+    func_index: u32,
+    values: *const u64,
+    count: u32,
+    phase: u32,
+) {
+    check_host_panic((&mut *vmctx).instance(), |instance| {
+        let values = if count == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(values, count as usize)
+        };
+        let phase = if phase == 0 {
+            HostCallPhase::Entry
+        } else {
+            HostCallPhase::Exit
+        };
+        instance.trace_host_call(FuncIndex::from_u32(func_index), phase, values);
+        Ok(())
+    });
+}
+
+/// Implementation of the host call charge, charges the gas cost of a call of
+/// an imported function before it is called, from its `count` arguments if
+/// the code passes them.
+#[no_mangle]
+pub unsafe extern "C" fn wasmjit_charge_host_call(
+    vmctx: *mut VMContext,
+    func_index: u32,
+    args: *const u64,
+    count: u32,
+) {
+    check_host_panic((&mut *vmctx).instance(), |instance| {
+        let args = if count == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(args, count as usize)
+        };
+        let cost = instance.host_call_cost(FuncIndex::from_u32(func_index), args);
+        if !instance.check_gas(cost) {
+            instance.set_trap_kind(wasmjit_result_err_trap);
+            wasmjit_trap(TrapCode::OutOfGas, String::from("wasmjit: gas exhausted"));
+        }
+        Ok(())
+    });
+}
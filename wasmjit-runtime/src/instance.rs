@@ -4,7 +4,7 @@
 
 use crate::builtins::{wasmjit_result_err_trap, wasmjit_result_kind};
 use crate::export::ExportFunc;
-use crate::memory::LinearMemory;
+use crate::memory::{LinearMemory, MemoryBudget};
 use crate::mmap::Mmap;
 use crate::signalhandlers::{wasmjit_init_eager, wasmjit_init_finish};
 use crate::table::Table;
@@ -14,15 +14,14 @@ use crate::vmcontext::{
 };
 use crate::SignatureRegistry;
 
-use std::sync::{atomic::AtomicU64, atomic::Ordering, Arc};
+use std::sync::{atomic::AtomicBool, atomic::AtomicU64, atomic::Ordering, Arc};
 use std::{any::Any, borrow::ToOwned, convert::TryFrom, mem, ptr, slice};
 
-use cranelift_codegen::ir;
 use cranelift_entity::EntityRef;
 use cranelift_entity::{BoxedSlice, PrimaryMap};
 use cranelift_wasm::{
     DefinedFuncIndex, DefinedGlobalIndex, DefinedMemoryIndex, DefinedTableIndex, FuncIndex,
-    GlobalInit, SignatureIndex,
+    GlobalIndex, GlobalInit, SignatureIndex,
 };
 use ontio_wasmjit_environ::{DataInitializer, Module, TableElements, VMOffsets};
 
@@ -37,6 +36,9 @@ pub struct ExecMetrics {
     pub gas_left: AtomicU64,
     /// function call depth left for execution
     pub depth_left: AtomicU64,
+    /// Set by a watchdog when the wall-clock timeout of the execution
+    /// expires, checked along with the gas.
+    pub timed_out: AtomicBool,
 }
 
 impl ExecMetrics {
@@ -47,10 +49,54 @@ impl ExecMetrics {
             gas_factor: AtomicU64::new(gas_factor),
             gas_left: AtomicU64::new(gas_left),
             depth_left: AtomicU64::new(depth_left),
+            timed_out: AtomicBool::new(false),
         }
     }
 }
 
+/// Which side of an imported function call a `HostCall` reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostCallPhase {
+    /// Before the call, the values are the arguments.
+    Entry,
+    /// After the call returned, the values are the results.
+    Exit,
+}
+
+/// A call of an imported function, reported to the `HostCallHook`.
+#[derive(Debug)]
+pub struct HostCall<'a> {
+    /// Module name of the import.
+    pub module: &'a str,
+    /// Field name of the import.
+    pub field: &'a str,
+    /// Whether the call is entered or exited.
+    pub phase: HostCallPhase,
+    /// Raw values in 64 bits slots: integers are zero extended and floats
+    /// are taken as their bit patterns.
+    pub values: &'a [u64],
+}
+
+/// Callback observing the calls of imported functions, for debugging.
+///
+/// The hook only sees copies of the values and no gas is charged for the
+/// tracing, so a traced execution has the same results as an untraced one.
+pub type HostCallHook = Box<dyn FnMut(&HostCall) + Send>;
+
+/// The gas charged for a call of an imported function.
+///
+/// It is charged before the function is called, so the same gas is charged
+/// whether the call returns or traps.
+#[derive(Clone)]
+pub enum HostCallCost {
+    /// The same gas for every call.
+    Fixed(u64),
+    /// The gas computed from the raw arguments of the call, encoded like
+    /// `HostCall::values`. It must only depend on the arguments for the gas
+    /// of an execution to be the same on every node.
+    Arguments(Arc<dyn Fn(&[u64]) -> u64 + Send + Sync>),
+}
+
 fn signature_id(
     vmctx: &VMContext,
     offsets: &VMOffsets,
@@ -188,6 +234,27 @@ pub struct Instance {
     /// Hosts can store arbitrary per-instance information here.
     host_state: Box<dyn Any>,
 
+    /// Embedder context of the current call, only set for its duration.
+    host_context: Option<*mut dyn Any>,
+
+    /// Whether a call into this instance is executing.
+    pub(crate) executing: bool,
+
+    /// Trap calls which reenter this instance while it is executing.
+    pub(crate) reentrancy_guard: bool,
+
+    /// Flag set by the embedder to cancel the current call, checked with the
+    /// gas.
+    pub(crate) cancel_flag: Option<Arc<AtomicBool>>,
+
+    /// Observer of the imported function calls, only consulted by code
+    /// compiled with host call tracing.
+    host_call_hook: Option<HostCallHook>,
+
+    /// Gas charged for the calls of each imported function, only charged by
+    /// code compiled with a `HostCallCharge`.
+    host_call_costs: BoxedSlice<FuncIndex, Option<HostCallCost>>,
+
     /// Additional context used by compiled wasm code. This field is last, and
     /// represents a dynamically-sized array that extends beyond the nominal
     /// end of the struct (similar to a flexible array member).
@@ -261,7 +328,6 @@ impl Instance {
     }
 
     /// Return the indexed `VMGlobalDefinition`.
-    #[allow(dead_code)]
     fn global(&self, index: DefinedGlobalIndex) -> &VMGlobalDefinition {
         global(&self.vmctx, &self.offsets, index)
     }
@@ -319,6 +385,13 @@ impl Instance {
         Some(self.lookup_by_declaration(export))
     }
 
+    /// Lookup the export at `index` in the declaration order of the export
+    /// section, the order of `exports`.
+    pub fn lookup_by_index(&mut self, index: usize) -> Option<ExportFunc> {
+        let export = *self.module.exports.get_index(index)?.1;
+        Some(self.lookup_by_declaration(export))
+    }
+
     /// Lookup an export with the given export declaration.
     pub fn lookup_by_declaration(&mut self, export: FuncIndex) -> ExportFunc {
         lookup_by_declaration(
@@ -344,11 +417,78 @@ impl Instance {
         self.host_state = host_state;
     }
 
+    /// Return the context of the current call if it is a `C`, `None` outside
+    /// of a call made with a context.
+    pub fn host_context<C: Any>(&mut self) -> Option<&mut C> {
+        let ctx = self.host_context?;
+        unsafe { (*ctx).downcast_mut() }
+    }
+
+    /// Replace the context of the current call and return the previous one.
+    ///
+    /// # Safety
+    ///
+    /// `ctx` must stay valid and not be accessed otherwise until it is
+    /// replaced again.
+    pub unsafe fn set_host_context(&mut self, ctx: Option<*mut dyn Any>) -> Option<*mut dyn Any> {
+        mem::replace(&mut self.host_context, ctx)
+    }
+
     /// Return a reference to the custom state attached to this instance.
     pub fn host_state(&mut self) -> &mut dyn Any {
         &mut *self.host_state
     }
 
+    /// Set the hook observing the imported function calls, `None` removes it.
+    pub fn set_host_call_hook(&mut self, hook: Option<HostCallHook>) {
+        self.host_call_hook = hook;
+    }
+
+    /// Set the gas charged for the calls of each imported function.
+    pub fn set_host_call_costs(&mut self, costs: BoxedSlice<FuncIndex, Option<HostCallCost>>) {
+        self.host_call_costs = costs;
+    }
+
+    /// The gas to charge for a call of the imported function `index` with
+    /// the arguments `args`, zero if it has no cost.
+    pub(crate) fn host_call_cost(&self, index: FuncIndex, args: &[u64]) -> u64 {
+        match self.host_call_costs.get(index) {
+            Some(Some(HostCallCost::Fixed(cost))) => *cost,
+            Some(Some(HostCallCost::Arguments(cost))) => cost(args),
+            _ => 0,
+        }
+    }
+
+    /// Report a call of the imported function `index` to the host call hook.
+    pub(crate) fn trace_host_call(
+        &mut self,
+        index: FuncIndex,
+        phase: HostCallPhase,
+        values: &[u64],
+    ) {
+        if let Some(hook) = self.host_call_hook.as_mut() {
+            let (module, field) = &self.module.imported_funcs[index];
+            hook(&HostCall {
+                module,
+                field,
+                phase,
+                values,
+            });
+        }
+    }
+
+    /// Trap with `TrapCode::Reentrancy` the calls which reenter this instance
+    /// from a host function while it is executing. Off by default.
+    pub fn set_reentrancy_guard(&mut self, enable: bool) {
+        self.reentrancy_guard = enable;
+    }
+
+    /// Trap with `TrapCode::Cancelled` at the next gas check once `flag` is
+    /// set, `None` removes it.
+    pub fn set_cancel_flag(&mut self, flag: Option<Arc<AtomicBool>>) {
+        self.cancel_flag = flag;
+    }
+
     /// Set the kind of trap
     pub fn set_trap_kind(&mut self, trap_kind: wasmjit_result_kind) {
         self.trap_kind = trap_kind;
@@ -411,6 +551,14 @@ impl Instance {
         self.memories.get(memory_index).map(|t| t.as_ref())
     }
 
+    /// Offset in its linear memory of the native `address`, if it falls in
+    /// the reservation of one of the memories.
+    pub fn memory_offset_of(&self, address: usize) -> Option<u64> {
+        self.memories
+            .values()
+            .find_map(|memory| memory.offset_of(address))
+    }
+
     /// Return the defined memory as mutable byte slice.
     ///
     /// Panic if `memory_index` is out of bound.
@@ -439,6 +587,14 @@ impl Instance {
         result
     }
 
+    /// Whether the memory can grow by the specified amount of pages without
+    /// exceeding its maximum.
+    pub(crate) fn memory_can_grow(&self, memory_index: DefinedMemoryIndex, delta: u32) -> bool {
+        self.memories
+            .get(memory_index)
+            .map_or(false, |memory| memory.can_grow(delta))
+    }
+
     /// Returns the number of allocated wasm pages.
     pub(crate) fn memory_size(&mut self, memory_index: DefinedMemoryIndex) -> u32 {
         self.memories
@@ -447,6 +603,47 @@ impl Instance {
             .size()
     }
 
+    /// Returns the number of allocated wasm pages of the memory, `None` if it
+    /// is not defined.
+    pub fn memory_pages(&self, memory_index: DefinedMemoryIndex) -> Option<u32> {
+        self.memories.get(memory_index).map(LinearMemory::size)
+    }
+
+    /// Returns the number of wasm pages the memory can grow to at most,
+    /// `None` if it is not defined.
+    pub fn memory_max_pages(&self, memory_index: DefinedMemoryIndex) -> Option<u32> {
+        self.memories.get(memory_index).map(LinearMemory::maximum)
+    }
+
+    /// Returns the number of elements of the table, `None` if it is not
+    /// defined.
+    pub(crate) fn table_size(&self, table_index: DefinedTableIndex) -> Option<u32> {
+        self.tables.get(table_index).map(Table::size)
+    }
+
+    /// Return the storage of the global `index`, `None` if it is not declared.
+    pub(crate) fn global_definition(&self, index: GlobalIndex) -> Option<&VMGlobalDefinition> {
+        if index.index() < self.module.globals.len() {
+            Some(self.global(self.module.defined_global_index(index)))
+        } else {
+            None
+        }
+    }
+
+    /// Return the mutable storage of the global `index`, `None` if it is not
+    /// declared. The mutability of the global is not checked.
+    pub(crate) fn global_definition_mut(
+        &mut self,
+        index: GlobalIndex,
+    ) -> Option<&mut VMGlobalDefinition> {
+        if index.index() < self.module.globals.len() {
+            let index = self.module.defined_global_index(index);
+            Some(self.global_mut(index))
+        } else {
+            None
+        }
+    }
+
     /// Grow table by the specified amount of elements.
     ///
     /// Returns `None` if table can't be grown by the specified amount
@@ -488,6 +685,34 @@ impl Instance {
             .get_mut(index)
     }
 
+    /// Set the gas left for the execution and drop the costs counted below
+    /// the gas factor, so the next call is charged from a clean state.
+    pub fn set_gas(&mut self, gas: u64) {
+        self.exec_metrics.gas_left.store(gas, Ordering::Relaxed);
+        self.clear_gas_counter();
+    }
+
+    /// Drop the costs counted below the gas factor, which are charged with
+    /// the next costs otherwise.
+    pub fn clear_gas_counter(&mut self) {
+        self.local_gas_counter = 0;
+    }
+
+    /// Count `costs` in units of the gas factor and substract the gas they
+    /// amount to, keeping the remainder for the next costs. Returns false,
+    /// leaving no gas, if they exceed the gas left.
+    pub fn charge_gas(&mut self, costs: u64) -> bool {
+        self.local_gas_counter += costs;
+        let gas_factor = self.exec_metrics.gas_factor.load(Ordering::Relaxed);
+        let normalize_costs = self.local_gas_counter / gas_factor;
+        if normalize_costs == 0 {
+            return true;
+        }
+
+        self.local_gas_counter %= gas_factor;
+        self.check_gas(normalize_costs)
+    }
+
     /// Check and substract the gas costs.
     pub fn check_gas(&mut self, costs: u64) -> bool {
         let origin = self
@@ -504,6 +729,81 @@ impl Instance {
     }
 }
 
+/// A copy of the mutable state of an `Instance`: the content and size of its
+/// linear memories, the size of its tables and the value of its mutable
+/// globals.
+///
+/// The elements of the tables are not copied, they point to the functions of
+/// the instance and can not be written by the supported operators. So a
+/// snapshot can be restored on any instance of the same module.
+#[derive(Debug, Clone)]
+pub struct InstanceSnapshot {
+    memories: PrimaryMap<DefinedMemoryIndex, (u32, Vec<u8>)>,
+    tables: PrimaryMap<DefinedTableIndex, u32>,
+    globals: Vec<(DefinedGlobalIndex, VMGlobalDefinition)>,
+}
+
+impl Instance {
+    /// Take a snapshot of the linear memories, table sizes and mutable globals.
+    pub fn snapshot(&mut self) -> InstanceSnapshot {
+        let memories = self
+            .memories
+            .values()
+            .map(|memory| (memory.size(), memory.to_vec()))
+            .collect();
+        let tables = self.tables.values().map(Table::size).collect();
+
+        let module = Arc::clone(&self.module);
+        let globals = module
+            .globals
+            .iter()
+            .filter(|(_, global)| global.mutability)
+            .map(|(index, _)| {
+                let def_index = module.defined_global_index(index);
+                (def_index, *self.global(def_index))
+            })
+            .collect();
+
+        InstanceSnapshot {
+            memories,
+            tables,
+            globals,
+        }
+    }
+
+    /// Restore the linear memories, table sizes and mutable globals from
+    /// `snapshot`, shrinking the memories and tables back if they have grown
+    /// since.
+    pub fn restore(&mut self, snapshot: &InstanceSnapshot) -> Result<(), String> {
+        if snapshot.memories.len() != self.memories.len()
+            || snapshot.tables.len() != self.tables.len()
+            || snapshot
+                .globals
+                .iter()
+                .any(|(index, _)| index.index() >= self.module.globals.len())
+        {
+            return Err(String::from("snapshot does not belong to this instance"));
+        }
+
+        for (index, (pages, data)) in snapshot.memories.iter() {
+            self.memories[index].restore(*pages, data)?;
+            // Keep current the VMContext pointers used by compiled wasm code.
+            *self.memory_mut(index) = self.memories[index].vmmemory();
+        }
+
+        for (index, size) in snapshot.tables.iter() {
+            self.tables[index].restore(*size);
+            *self.table_mut(index) = self.tables[index].vmtable();
+        }
+
+        for (index, value) in snapshot.globals.iter() {
+            *self.global_mut(*index) = *value;
+        }
+
+        Ok(())
+    }
+}
+
 /// A handle holding an `Instance` of a WebAssembly module.
 #[derive(Hash, PartialEq, Eq)]
 pub struct InstanceHandle {
@@ -511,11 +811,21 @@ pub struct InstanceHandle {
 }
 
 fn generate_shared_signatures(
-    map: &PrimaryMap<SignatureIndex, ir::Signature>,
+    module: &Module,
 ) -> BoxedSlice<SignatureIndex, VMSharedSignatureIndex> {
+    // The ids of a shared registry are the same for all the modules compiled
+    // with it, so their instances agree on the signatures.
+    if module.signature_ids.len() == module.signatures.len() {
+        return module
+            .signature_ids
+            .values()
+            .map(|&id| VMSharedSignatureIndex::new(id))
+            .collect::<PrimaryMap<_, _>>()
+            .into_boxed_slice();
+    }
     let mut registry = SignatureRegistry::new();
     let mut shared_map = PrimaryMap::new();
-    for sig in map.values() {
+    for sig in module.signatures.values() {
         let shared = registry.register(sig);
         shared_map.push(shared);
     }
@@ -524,7 +834,8 @@ fn generate_shared_signatures(
 }
 
 impl InstanceHandle {
-    /// Create a new `InstanceHandle` pointing at a new `Instance`.
+    /// Create a new `InstanceHandle` pointing at a new `Instance`, the pages
+    /// of its memories are taken from `memory_budget` if any.
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         module: Arc<Module>,
@@ -533,10 +844,11 @@ impl InstanceHandle {
         data_initializers: &[DataInitializer<'_>],
         exec_metrics: Arc<ExecMetrics>,
         host_state: Box<dyn Any>,
+        memory_budget: Option<Arc<MemoryBudget>>,
     ) -> Result<Self, InstantiationError> {
         let mut tables = create_tables(&module);
-        let mut memories = create_memories(&module)?;
-        let vmshared_signatures = generate_shared_signatures(&module.signatures);
+        let mut memories = create_memories(&module, memory_budget)?;
+        let vmshared_signatures = generate_shared_signatures(&module);
 
         let vmctx_tables = tables
             .values_mut()
@@ -578,6 +890,12 @@ impl InstanceHandle {
                 exec_metrics,
                 trap_kind,
                 host_state,
+                host_context: None,
+                executing: false,
+                reentrancy_guard: false,
+                cancel_flag: None,
+                host_call_hook: None,
+                host_call_costs: PrimaryMap::new().into_boxed_slice(),
                 vmctx: VMContext { _priv: [] },
             };
             unsafe {
@@ -586,6 +904,20 @@ impl InstanceHandle {
             }
         };
 
+        // Host functions are called with the vmctx of this instance.
+        let vmctx_ptr = instance.vmctx_mut_ptr();
+        let imports: Vec<_> = imports
+            .values()
+            .map(|import| VMFunctionImport {
+                body: import.body,
+                vmctx: if import.vmctx.is_null() {
+                    vmctx_ptr
+                } else {
+                    import.vmctx
+                },
+            })
+            .collect();
+
         unsafe {
             ptr::copy(
                 vmshared_signatures.values().as_slice().as_ptr(),
@@ -593,7 +925,7 @@ impl InstanceHandle {
                 vmshared_signatures.len(),
             );
             ptr::copy(
-                imports.values().as_slice().as_ptr(),
+                imports.as_ptr(),
                 instance.imported_functions_ptr() as *mut VMFunctionImport,
                 imports.len(),
             );
@@ -618,6 +950,9 @@ impl InstanceHandle {
             );
         }
 
+        // The globals come first, the initializers may use them as base.
+        initialize_globals(instance);
+
         // Check initializer bounds before initializing anything.
         check_table_init_bounds(instance)?;
         check_memory_init_bounds(instance, data_initializers)?;
@@ -625,7 +960,6 @@ impl InstanceHandle {
         // Apply the initializers.
         initialize_tables(instance)?;
         initialize_memories(instance, data_initializers)?;
-        initialize_globals(instance);
 
         // Ensure that our signal handlers are ready for action.
         // TODO: Move these calls out of `InstanceHandle`.
@@ -640,6 +974,33 @@ impl InstanceHandle {
         self.instance_mut().set_host_state(host_state);
     }
 
+    /// Replace the context of the current call and return the previous one.
+    ///
+    /// # Safety
+    ///
+    /// `ctx` must stay valid and not be accessed otherwise until it is
+    /// replaced again.
+    pub unsafe fn set_host_context(&mut self, ctx: Option<*mut dyn Any>) -> Option<*mut dyn Any> {
+        self.instance_mut().set_host_context(ctx)
+    }
+
+    /// Trap with `TrapCode::Reentrancy` the calls which reenter this instance
+    /// from a host function while it is executing. Off by default.
+    pub fn set_reentrancy_guard(&mut self, enable: bool) {
+        self.instance_mut().set_reentrancy_guard(enable);
+    }
+
+    /// Trap with `TrapCode::Cancelled` at the next gas check once `flag` is
+    /// set, `None` removes it.
+    pub fn set_cancel_flag(&mut self, flag: Option<Arc<AtomicBool>>) {
+        self.instance_mut().set_cancel_flag(flag);
+    }
+
+    /// Set the hook observing the imported function calls, `None` removes it.
+    pub fn set_host_call_hook(&mut self, hook: Option<HostCallHook>) {
+        self.instance_mut().set_host_call_hook(hook);
+    }
+
     /// Get trap kind
     pub fn trap_kind(&mut self) -> wasmjit_result_kind {
         self.instance_mut().trap_kind()
@@ -680,6 +1041,12 @@ impl InstanceHandle {
         self.instance_mut().lookup(field)
     }
 
+    /// Lookup the export at `index` in the declaration order of the export
+    /// section, the order of `exports`.
+    pub fn lookup_by_index(&mut self, index: usize) -> Option<ExportFunc> {
+        self.instance_mut().lookup_by_index(index)
+    }
+
     /// Lookup an export with the given export declaration.
     pub fn lookup_by_declaration(&mut self, export: FuncIndex) -> ExportFunc {
         self.instance_mut().lookup_by_declaration(export)
@@ -712,11 +1079,34 @@ impl InstanceHandle {
         self.instance_mut().memory_grow(memory_index, delta)
     }
 
+    /// Offset in its linear memory of the native `address`, if it falls in
+    /// the reservation of one of the memories of this instance.
+    pub fn memory_offset_of(&self, address: usize) -> Option<u64> {
+        self.instance().memory_offset_of(address)
+    }
+
     /// Return the table index for the given `VMTableDefinition` in this instance.
     pub fn table_index(&self, table: &VMTableDefinition) -> DefinedTableIndex {
         self.instance().table_index(table)
     }
 
+    /// Returns the number of elements of the table, `None` if it is not
+    /// defined.
+    pub fn table_size(&self, table_index: DefinedTableIndex) -> Option<u32> {
+        self.instance().table_size(table_index)
+    }
+
+    /// Return the storage of the global `index`, `None` if it is not declared.
+    pub fn global_definition(&self, index: GlobalIndex) -> Option<&VMGlobalDefinition> {
+        self.instance().global_definition(index)
+    }
+
+    /// Return the mutable storage of the global `index`, `None` if it is not
+    /// declared. The mutability of the global is not checked.
+    pub fn global_definition_mut(&mut self, index: GlobalIndex) -> Option<&mut VMGlobalDefinition> {
+        self.instance_mut().global_definition_mut(index)
+    }
+
     /// Grow table in this instance by the specified amount of pages.
     ///
     /// Returns `None` if memory can't be grown by the specified amount
@@ -781,7 +1171,7 @@ fn lookup_by_declaration(
         (finished_functions[def_index], vmctx as *mut VMContext)
     } else {
         let import = imported_function(vmctx, offsets, index);
-        (import.body, vmctx as *mut VMContext)
+        (import.body, import.vmctx)
     };
     ExportFunc::new(address, vmctx, signature)
 }
@@ -802,16 +1192,17 @@ fn check_table_init_bounds(instance: &mut Instance) -> Result<(), InstantiationE
     Ok(())
 }
 
-/// Compute the offset for a memory data initializer.
-fn get_memory_init_start(init: &DataInitializer<'_>, instance: &mut Instance) -> usize {
-    let mut start = init.location.offset;
+/// Compute the offset for a memory data initializer, `None` if it overflows.
+fn get_memory_init_start(init: &DataInitializer<'_>, instance: &mut Instance) -> Option<usize> {
+    let start = init.location.offset;
 
-    if let Some(base) = init.location.base {
-        let global = instance.global_mut(instance.module.defined_global_index(base));
-        start += usize::try_from(*unsafe { (*global).as_u32() }).unwrap();
+    match init.location.base {
+        Some(base) => {
+            let global = instance.global_mut(instance.module.defined_global_index(base));
+            start.checked_add(usize::try_from(*unsafe { (*global).as_u32() }).unwrap())
+        }
+        None => Some(start),
     }
-
-    start
 }
 
 /// Return a byte-slice view of a memory's data.
@@ -832,12 +1223,16 @@ fn check_memory_init_bounds(
 ) -> Result<(), InstantiationError> {
     for init in data_initializers {
         let start = get_memory_init_start(init, instance);
-        let mem_slice = get_memory_slice(init, instance);
-
-        if mem_slice.get_mut(start..start + init.data.len()).is_none() {
-            return Err(InstantiationError::Link(LinkError(
-                "data segment does not fit".to_owned(),
-            )));
+        let mem_len = get_memory_slice(init, instance).len();
+
+        let end = start.and_then(|start| start.checked_add(init.data.len()));
+        if end.map_or(true, |end| end > mem_len) {
+            return Err(InstantiationError::Link(LinkError(format!(
+                "data segment does not fit: offset {} and length {} exceed memory size {}",
+                start.map_or_else(|| String::from("overflow"), |start| start.to_string()),
+                init.data.len(),
+                mem_len
+            ))));
         }
     }
 
@@ -847,9 +1242,9 @@ fn check_memory_init_bounds(
 /// Allocate memory for just the tables of the current module.
 fn create_tables(module: &Module) -> BoxedSlice<DefinedTableIndex, Table> {
     let mut tables: PrimaryMap<DefinedTableIndex, _> =
-        PrimaryMap::with_capacity(module.tables.len());
-    for table in module.tables.values() {
-        tables.push(Table::new(table));
+        PrimaryMap::with_capacity(module.table_plans.len());
+    for plan in module.table_plans.values() {
+        tables.push(Table::new(plan));
     }
     tables.into_boxed_slice()
 }
@@ -887,18 +1282,19 @@ fn initialize_tables(instance: &mut Instance) -> Result<(), InstantiationError>
         let subslice = &mut slice[start..start + init.elements.len()];
         for (i, func_idx) in init.elements.iter().enumerate() {
             let callee_sig = instance.module.functions[*func_idx];
-            let callee_ptr = if let Some(index) = instance.module.defined_func_index(*func_idx) {
-                instance.finished_functions[index]
-            } else {
-                let imported_func =
-                    imported_function(&instance.vmctx, &instance.offsets, *func_idx);
-                imported_func.body
-            };
+            let (callee_ptr, callee_vmctx) =
+                if let Some(index) = instance.module.defined_func_index(*func_idx) {
+                    (instance.finished_functions[index], vmctx)
+                } else {
+                    let imported_func =
+                        imported_function(&instance.vmctx, &instance.offsets, *func_idx);
+                    (imported_func.body, imported_func.vmctx)
+                };
             let type_index = signature_id(&instance.vmctx, &instance.offsets, callee_sig);
             subslice[i] = VMCallerCheckedAnyfunc {
                 func_ptr: callee_ptr,
                 type_index,
-                vmctx,
+                vmctx: callee_vmctx,
             };
         }
     }
@@ -909,22 +1305,31 @@ fn initialize_tables(instance: &mut Instance) -> Result<(), InstantiationError>
 /// Allocate memory for just the memories of the current module.
 fn create_memories(
     module: &Module,
+    budget: Option<Arc<MemoryBudget>>,
 ) -> Result<BoxedSlice<DefinedMemoryIndex, LinearMemory>, InstantiationError> {
     let mut memories: PrimaryMap<DefinedMemoryIndex, _> =
         PrimaryMap::with_capacity(module.memory_plans.len());
     for plan in module.memory_plans.values() {
-        memories.push(LinearMemory::new(plan).map_err(InstantiationError::Resource)?);
+        let memory = LinearMemory::with_budget(plan, budget.clone())
+            .map_err(InstantiationError::Resource)?;
+        memories.push(memory);
     }
     Ok(memories.into_boxed_slice())
 }
 
-/// Initialize the table memory from the provided initializers.
+/// Initialize the memories from the provided initializers.
+///
+/// The initializers are written one after the other in the order they are
+/// given, the declaration order of the module, so overlapping segments end
+/// up with the bytes of the last segment. This is part of the consensus: the
+/// order must never change. All of them are bounds checked before the first
+/// one is written.
 fn initialize_memories(
     instance: &mut Instance,
     data_initializers: &[DataInitializer<'_>],
 ) -> Result<(), InstantiationError> {
     for init in data_initializers {
-        let start = get_memory_init_start(init, instance);
+        let start = get_memory_init_start(init, instance).unwrap();
         let mem_slice = get_memory_slice(init, instance);
 
         let to_init = &mut mem_slice[start..start + init.data.len()];
@@ -52,12 +52,19 @@ mod vmcontext;
 pub mod builtins;
 
 pub use crate::export::ExportFunc;
-pub use crate::instance::{ExecMetrics, Instance, InstanceHandle, InstantiationError, LinkError};
-pub use crate::mmap::Mmap;
+pub use crate::instance::{
+    ExecMetrics, HostCall, HostCallCost, HostCallHook, HostCallPhase, Instance, InstanceHandle,
+    InstanceSnapshot, InstantiationError, LinkError,
+};
+pub use crate::memory::MemoryBudget;
+pub use crate::mmap::{prefault, Mmap};
 use crate::sig_registry::SignatureRegistry;
 pub use crate::signalhandlers::{wasmjit_init_eager, wasmjit_init_finish};
 pub use crate::trap_registry::{get_mut_trap_registry, get_trap_registry, TrapRegistrationGuard};
-pub use crate::traphandlers::{wasmjit_call, wasmjit_call_trampoline, wasmjit_unwind};
+pub use crate::traphandlers::{
+    wasmjit_call, wasmjit_call_trampoline, wasmjit_trap, wasmjit_unwind, FrameInfo, MemoryAccess,
+    Trap, TrapCode,
+};
 pub use crate::vmcontext::{
     VMCallerCheckedAnyfunc, VMContext, VMFunctionBody, VMFunctionImport, VMGlobalDefinition,
     VMInvokeArgument, VMMemoryDefinition, VMSharedSignatureIndex, VMTableDefinition,
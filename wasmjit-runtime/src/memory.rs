@@ -8,6 +8,68 @@ use alloc::string::String;
 use core::convert::TryFrom;
 use ontio_wasmjit_environ::{MemoryPlan, MemoryStyle, WASM_MAX_PAGES, WASM_PAGE_SIZE};
 use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A ceiling on the total number of wasm pages of the linear memories sharing
+/// it, whatever their instance and thread.
+///
+/// The pages of a memory are taken from the budget when it is created and
+/// grown, and given back when it is dropped or restored to fewer pages. A
+/// grow beyond the budget fails like a failed allocation, and an instance
+/// whose initial memory does not fit fails to instantiate.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    limit_pages: u64,
+    used_pages: AtomicU64,
+}
+
+impl MemoryBudget {
+    /// Create a budget of `limit_pages` wasm pages.
+    pub fn new(limit_pages: u64) -> Self {
+        Self {
+            limit_pages,
+            used_pages: AtomicU64::new(0),
+        }
+    }
+
+    /// The ceiling, in wasm pages.
+    pub fn limit_pages(&self) -> u64 {
+        self.limit_pages
+    }
+
+    /// The wasm pages currently taken by the memories.
+    pub fn used_pages(&self) -> u64 {
+        self.used_pages.load(Ordering::SeqCst)
+    }
+
+    /// Take `pages` from the budget, false if they do not fit.
+    fn take(&self, pages: u64) -> bool {
+        let mut used = self.used_pages.load(Ordering::SeqCst);
+        loop {
+            match used.checked_add(pages) {
+                Some(new_used) if new_used <= self.limit_pages => {
+                    match self.used_pages.compare_exchange_weak(
+                        used,
+                        new_used,
+                        Ordering::SeqCst,
+                        Ordering::SeqCst,
+                    ) {
+                        Ok(_) => return true,
+                        Err(current) => used = current,
+                    }
+                }
+                _ => return false,
+            }
+        }
+    }
+
+    /// Give back `pages` taken from the budget.
+    fn give_back(&self, pages: u64) {
+        let prev = self.used_pages.fetch_sub(pages, Ordering::SeqCst);
+        debug_assert!(prev >= pages);
+    }
+}
 
 /// A linear memory instance.
 #[derive(Debug)]
@@ -15,7 +77,9 @@ pub struct LinearMemory {
     // The underlying allocation.
     mmap: Mmap,
 
-    // The current logical size in wasm pages of this linear memory.
+    // The current logical size in wasm pages of this linear memory. The bytes
+    // of the mapping beyond it are always zero, so the pages made accessible
+    // again by `grow` read as zero.
     current: u32,
 
     // The optional maximum size in wasm pages of this linear memory.
@@ -28,11 +92,22 @@ pub struct LinearMemory {
     // Records whether we're using a bounds-checking strategy which requires
     // handlers to catch trapping accesses.
     pub(crate) needs_signal_handlers: bool,
+
+    // The budget the current pages are taken from, if any.
+    budget: Option<Arc<MemoryBudget>>,
 }
 
 impl LinearMemory {
     /// Create a new linear memory instance with specified minimum and maximum number of wasm pages.
     pub fn new(plan: &MemoryPlan) -> Result<Self, String> {
+        Self::with_budget(plan, None)
+    }
+
+    /// Create a new linear memory like `new`, taking its pages from `budget`.
+    pub fn with_budget(
+        plan: &MemoryPlan,
+        budget: Option<Arc<MemoryBudget>>,
+    ) -> Result<Self, String> {
         // `maximum` cannot be set to more than `65536` pages.
         assert!(plan.memory.minimum <= WASM_MAX_PAGES);
         assert!(plan.memory.maximum.is_none() || plan.memory.maximum.unwrap() <= WASM_MAX_PAGES);
@@ -49,7 +124,10 @@ impl LinearMemory {
             };
 
         let minimum_pages = match plan.style {
-            MemoryStyle::Dynamic => plan.memory.minimum,
+            MemoryStyle::Dynamic => {
+                assert!(plan.reserved_pages >= plan.memory.minimum);
+                plan.reserved_pages
+            }
             MemoryStyle::Static { bound } => {
                 assert!(bound >= plan.memory.minimum);
                 bound
@@ -60,7 +138,25 @@ impl LinearMemory {
         let mapped_pages = plan.memory.minimum as usize;
         let mapped_bytes = mapped_pages * WASM_PAGE_SIZE as usize;
 
-        let mmap = Mmap::accessible_reserved(mapped_bytes, request_bytes)?;
+        if let Some(budget) = &budget {
+            if !budget.take(u64::from(plan.memory.minimum)) {
+                return Err(format!(
+                    "memory budget exhausted: {} pages used of {}, {} more needed",
+                    budget.used_pages(),
+                    budget.limit_pages(),
+                    plan.memory.minimum
+                ));
+            }
+        }
+        let mmap = match Mmap::accessible_reserved(mapped_bytes, request_bytes) {
+            Ok(mmap) => mmap,
+            Err(err) => {
+                if let Some(budget) = &budget {
+                    budget.give_back(u64::from(plan.memory.minimum));
+                }
+                return Err(err);
+            }
+        };
 
         Ok(Self {
             mmap,
@@ -68,6 +164,7 @@ impl LinearMemory {
             maximum: plan.memory.maximum,
             offset_guard_size: offset_guard_bytes,
             needs_signal_handlers,
+            budget,
         })
     }
 
@@ -76,32 +173,61 @@ impl LinearMemory {
         self.current
     }
 
-    /// Grow memory by the specified amount of wasm pages.
-    ///
-    /// Returns `None` if memory can't be grown by the specified amount
-    /// of wasm pages.
-    pub fn grow(&mut self, delta: u32) -> Option<u32> {
+    /// Returns the number of wasm pages the memory can grow to at most: its
+    /// declared maximum, already capped by the tunables at translation, and
+    /// below `WASM_MAX_PAGES` anyway, see `can_grow`.
+    pub fn maximum(&self) -> u32 {
+        let limit = WASM_MAX_PAGES - 1;
+        self.maximum.map_or(limit, |maximum| maximum.min(limit))
+    }
+
+    /// Whether growing by `delta` pages stays within the limits of the memory,
+    /// the allocation of the pages may still fail.
+    pub fn can_grow(&self, delta: u32) -> bool {
         let new_pages = match self.current.checked_add(delta) {
             Some(new_pages) => new_pages,
             // Linear memory size overflow.
-            None => return None,
+            None => return false,
         };
-        let prev_pages = self.current;
 
         if let Some(maximum) = self.maximum {
             if new_pages > maximum {
                 // Linear memory size would exceed the declared maximum.
-                return None;
+                return false;
             }
         }
 
         // Wasm linear memories are never allowed to grow beyond what is
         // indexable. If the memory has no maximum, enforce the greatest
         // limit here.
-        if new_pages >= WASM_MAX_PAGES {
-            // Linear memory size would exceed the index range.
+        new_pages < WASM_MAX_PAGES
+    }
+
+    /// Grow memory by the specified amount of wasm pages.
+    ///
+    /// Returns `None` if memory can't be grown by the specified amount
+    /// of wasm pages, or if they do not fit its budget.
+    pub fn grow(&mut self, delta: u32) -> Option<u32> {
+        if !self.can_grow(delta) {
             return None;
         }
+        if let Some(budget) = &self.budget {
+            if !budget.take(u64::from(delta)) {
+                return None;
+            }
+        }
+        let prev_pages = self.grow_pages(delta);
+        if prev_pages.is_none() {
+            if let Some(budget) = &self.budget {
+                budget.give_back(u64::from(delta));
+            }
+        }
+        prev_pages
+    }
+
+    fn grow_pages(&mut self, delta: u32) -> Option<u32> {
+        let prev_pages = self.current;
+        let new_pages = prev_pages + delta;
 
         let delta_bytes = usize::try_from(delta).unwrap() * WASM_PAGE_SIZE as usize;
         let prev_bytes = usize::try_from(prev_pages).unwrap() * WASM_PAGE_SIZE as usize;
@@ -113,14 +239,17 @@ impl LinearMemory {
             let guard_bytes = self.offset_guard_size;
             let request_bytes = new_bytes.checked_add(guard_bytes)?;
 
+            // A fresh anonymous mapping is zeroed. Only the current pages are
+            // copied: the rest of the old mapping may be inaccessible after a
+            // `restore` to fewer pages.
             let mut new_mmap = Mmap::accessible_reserved(new_bytes, request_bytes).ok()?;
-
-            let copy_len = self.mmap.len() - self.offset_guard_size;
-            new_mmap.as_mut_slice()[..copy_len].copy_from_slice(&self.mmap.as_slice()[..copy_len]);
+            new_mmap.as_mut_slice()[..prev_bytes]
+                .copy_from_slice(&self.mmap.as_slice()[..prev_bytes]);
 
             self.mmap = new_mmap;
         } else if delta_bytes > 0 {
-            // Make the newly allocated pages accessible.
+            // Make the newly allocated pages accessible, they are zero since
+            // they were never accessible or were cleared by `restore`.
             self.mmap.make_accessible(prev_bytes, delta_bytes).ok()?;
         }
 
@@ -129,6 +258,43 @@ impl LinearMemory {
         Some(prev_pages)
     }
 
+    /// Reset the memory to `pages` wasm pages holding `data`.
+    ///
+    /// Pages beyond `pages` are cleared and made inaccessible again, so that
+    /// they read as zero if the memory grows later.
+    pub fn restore(&mut self, pages: u32, data: &[u8]) -> Result<(), String> {
+        let page_size = WASM_PAGE_SIZE as usize;
+        assert_eq!(data.len(), usize::try_from(pages).unwrap() * page_size);
+
+        if pages > self.current {
+            self.grow(pages - self.current)
+                .ok_or_else(|| String::from("failed to grow memory to the snapshot size"))?;
+        } else if pages < self.current {
+            let start = usize::try_from(pages).unwrap() * page_size;
+            let len = usize::try_from(self.current - pages).unwrap() * page_size;
+            unsafe { core::ptr::write_bytes(self.mmap.as_mut_ptr().add(start), 0, len) };
+            self.mmap.make_inaccessible(start, len)?;
+            if let Some(budget) = &self.budget {
+                budget.give_back(u64::from(self.current - pages));
+            }
+            self.current = pages;
+        }
+
+        self.mmap.as_mut_slice()[..data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Offset in this memory of the native `address`, if it falls in its
+    /// reservation, guard pages included.
+    pub fn offset_of(&self, address: usize) -> Option<u64> {
+        let base = self.mmap.as_ptr() as usize;
+        if address >= base && address - base < self.mmap.len() {
+            Some((address - base) as u64)
+        } else {
+            None
+        }
+    }
+
     /// Return a `VMMemoryDefinition` for exposing the memory to compiled wasm code.
     pub fn vmmemory(&mut self) -> VMMemoryDefinition {
         VMMemoryDefinition {
@@ -138,6 +304,14 @@ impl LinearMemory {
     }
 }
 
+impl Drop for LinearMemory {
+    fn drop(&mut self) {
+        if let Some(budget) = &self.budget {
+            budget.give_back(u64::from(self.current));
+        }
+    }
+}
+
 impl Deref for LinearMemory {
     type Target = [u8];
 
@@ -151,3 +325,138 @@ impl DerefMut for LinearMemory {
         &mut self.mmap.as_mut_slice()[..(self.current * WASM_PAGE_SIZE) as usize]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_wasm::Memory;
+    use ontio_wasmjit_environ::Tunables;
+
+    fn grown_pages_read_as_zero(tunables: &Tunables) {
+        let memory = Memory {
+            minimum: 1,
+            maximum: Some(3),
+            shared: false,
+        };
+        let mut memory = LinearMemory::new(&MemoryPlan::for_memory(memory, tunables)).unwrap();
+        let page = WASM_PAGE_SIZE as usize;
+        for byte in memory.iter_mut() {
+            *byte = 0xaa;
+        }
+
+        assert_eq!(memory.grow(3), None);
+        assert_eq!(memory.grow(1), Some(1));
+        assert!(memory[..page].iter().all(|&b| b == 0xaa));
+        assert!(memory[page..].iter().all(|&b| b == 0));
+
+        // dirty the grown page, then shrink back and grow again
+        for byte in memory[page..].iter_mut() {
+            *byte = 0xbb;
+        }
+        let data = memory[..page].to_vec();
+        memory.restore(1, &data).unwrap();
+        assert_eq!(memory.grow(2), Some(1));
+        assert_eq!(memory.size(), 3);
+        assert!(memory[..page].iter().all(|&b| b == 0xaa));
+        assert!(memory[page..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn memory_budget() {
+        let budget = Arc::new(MemoryBudget::new(4));
+        let plan = |minimum| {
+            let memory = Memory {
+                minimum,
+                maximum: None,
+                shared: false,
+            };
+            MemoryPlan::for_memory(memory, &Tunables::default())
+        };
+
+        let mut first = LinearMemory::with_budget(&plan(1), Some(budget.clone())).unwrap();
+        let second = LinearMemory::with_budget(&plan(2), Some(budget.clone())).unwrap();
+        assert_eq!(budget.used_pages(), 3);
+        assert!(LinearMemory::with_budget(&plan(2), Some(budget.clone())).is_err());
+        assert_eq!(budget.used_pages(), 3);
+
+        assert_eq!(first.grow(2), None);
+        assert_eq!(first.grow(1), Some(1));
+        assert_eq!(budget.used_pages(), 4);
+        drop(second);
+        assert_eq!(budget.used_pages(), 2);
+        assert_eq!(first.grow(2), Some(2));
+
+        let data = first[..WASM_PAGE_SIZE as usize].to_vec();
+        first.restore(1, &data).unwrap();
+        assert_eq!(budget.used_pages(), 1);
+        drop(first);
+        assert_eq!(budget.used_pages(), 0);
+    }
+
+    #[test]
+    fn memory_budget_concurrent_takes() {
+        let budget = Arc::new(MemoryBudget::new(1000));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let budget = budget.clone();
+                std::thread::spawn(move || (0..500).filter(|_| budget.take(1)).count())
+            })
+            .collect();
+        let taken: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+        assert_eq!(taken, 1000);
+        assert_eq!(budget.used_pages(), 1000);
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let budget = budget.clone();
+                std::thread::spawn(move || (0..125).for_each(|_| budget.give_back(1)))
+            })
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        assert_eq!(budget.used_pages(), 0);
+    }
+
+    #[test]
+    fn dynamic_reservation_avoids_remaps() {
+        let remaps = |reserved_pages| {
+            let mut tunables = Tunables::default();
+            tunables.guard_pages = false;
+            tunables.dynamic_memory_reserved_pages = reserved_pages;
+            let memory = Memory {
+                minimum: 1,
+                maximum: None,
+                shared: false,
+            };
+            let plan = MemoryPlan::for_memory(memory, &tunables);
+            let mut memory = LinearMemory::new(&plan).unwrap();
+            assert_eq!(memory.len(), WASM_PAGE_SIZE as usize);
+
+            let mut remaps = 0;
+            for _ in 0..16 {
+                let base = memory.vmmemory().base;
+                memory.grow(1).unwrap();
+                if memory.vmmemory().base != base {
+                    remaps += 1;
+                }
+            }
+            assert_eq!(memory.size(), 17);
+            remaps
+        };
+
+        assert_eq!(remaps(0), 16);
+        assert_eq!(remaps(9), 8);
+        assert_eq!(remaps(17), 0);
+    }
+
+    #[test]
+    fn grown_pages_read_as_zero_static() {
+        grown_pages_read_as_zero(&Tunables::default());
+    }
+
+    #[test]
+    fn grown_pages_read_as_zero_dynamic() {
+        let mut tunables = Tunables::default();
+        tunables.guard_pages = false;
+        grown_pages_read_as_zero(&tunables);
+    }
+}
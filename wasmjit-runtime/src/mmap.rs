@@ -172,8 +172,7 @@ impl Mmap {
         let page_size = region::page::size();
         assert_eq!(start & (page_size - 1), 0);
         assert_eq!(len & (page_size - 1), 0);
-        assert!(len < self.len);
-        assert!(start < self.len - len);
+        assert!(start.checked_add(len).unwrap() <= self.len);
 
         // Commit the accessible size.
         unsafe { region::protect(self.ptr.add(start), len, region::Protection::ReadWrite) }
@@ -191,8 +190,7 @@ impl Mmap {
         let page_size = region::page::size();
         assert_eq!(start & (page_size - 1), 0);
         assert_eq!(len & (page_size - 1), 0);
-        assert!(len < self.len);
-        assert!(start < self.len - len);
+        assert!(start.checked_add(len).unwrap() <= self.len);
 
         // Commit the accessible size.
         if unsafe {
@@ -211,6 +209,40 @@ impl Mmap {
         Ok(())
     }
 
+    /// Make the memory starting at `start` and extending for `len` bytes inaccessible again.
+    /// `start` and `len` must be native page-size multiples and describe a range within
+    /// `self`'s reserved memory.
+    #[cfg(not(target_os = "windows"))]
+    pub fn make_inaccessible(&mut self, start: usize, len: usize) -> Result<(), String> {
+        let page_size = region::page::size();
+        assert_eq!(start & (page_size - 1), 0);
+        assert_eq!(len & (page_size - 1), 0);
+        assert!(start.checked_add(len).unwrap() <= self.len);
+
+        unsafe { region::protect(self.ptr.add(start), len, region::Protection::None) }
+            .map_err(|e| e.to_string())
+    }
+
+    /// Make the memory starting at `start` and extending for `len` bytes inaccessible again.
+    /// `start` and `len` must be native page-size multiples and describe a range within
+    /// `self`'s reserved memory.
+    #[cfg(target_os = "windows")]
+    pub fn make_inaccessible(&mut self, start: usize, len: usize) -> Result<(), String> {
+        use winapi::ctypes::c_void;
+        use winapi::um::memoryapi::VirtualFree;
+        use winapi::um::winnt::MEM_DECOMMIT;
+        let page_size = region::page::size();
+        assert_eq!(start & (page_size - 1), 0);
+        assert_eq!(len & (page_size - 1), 0);
+        assert!(start.checked_add(len).unwrap() <= self.len);
+
+        if unsafe { VirtualFree(self.ptr.add(start) as *mut c_void, len, MEM_DECOMMIT) } == 0 {
+            return Err(errno::errno().to_string());
+        }
+
+        Ok(())
+    }
+
     /// Return the allocated memory as a slice of u8.
     pub fn as_slice(&self) -> &[u8] {
         unsafe { slice::from_raw_parts(self.ptr, self.len) }
@@ -258,6 +290,29 @@ impl Drop for Mmap {
     }
 }
 
+/// Fault in the pages spanned by `bytes`, and lock them in memory if `lock`,
+/// so that their first access does not take a page fault. The lock lasts as
+/// long as the pages are mapped, prefaulting the same pages again is harmless.
+pub fn prefault(bytes: &[u8], lock: bool) -> Result<(), String> {
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    let page_size = region::page::size();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        unsafe { ptr::read_volatile(bytes.as_ptr().add(offset)) };
+        // the start of the next page, `bytes` may not be page aligned
+        let address = bytes.as_ptr() as usize + offset;
+        offset += page_size - address % page_size;
+    }
+    if lock {
+        let guard = region::lock(bytes.as_ptr(), bytes.len()).map_err(|e| e.to_string())?;
+        // dropping the guard would unlock the pages right away
+        core::mem::forget(guard);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
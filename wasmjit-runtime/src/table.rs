@@ -5,7 +5,8 @@
 use crate::vmcontext::{VMCallerCheckedAnyfunc, VMTableDefinition};
 use alloc::vec::Vec;
 use core::convert::{TryFrom, TryInto};
-use cranelift_wasm::{Table as WasmTable, TableElementType};
+use cranelift_wasm::TableElementType;
+use ontio_wasmjit_environ::{TablePlan, TableStyle};
 
 /// A table instance.
 #[derive(Debug)]
@@ -16,7 +17,8 @@ pub struct Table {
 
 impl Table {
     /// Create a new table instance with specified minimum and maximum number of elements.
-    pub fn new(table: &WasmTable) -> Self {
+    pub fn new(plan: &TablePlan) -> Self {
+        let table = &plan.table;
         match table.ty {
             TableElementType::Func => (),
             TableElementType::Val(ty) => {
@@ -25,8 +27,16 @@ impl Table {
             }
         };
 
+        let minimum = usize::try_from(table.minimum).unwrap();
+        let mut vec = match plan.style {
+            // Reserve the whole storage so that growing never moves the table.
+            TableStyle::Static { bound } => Vec::with_capacity(usize::try_from(bound).unwrap()),
+            TableStyle::Dynamic => Vec::with_capacity(minimum),
+        };
+        vec.resize(minimum, VMCallerCheckedAnyfunc::default());
+
         Self {
-            vec: vec![VMCallerCheckedAnyfunc::default(); usize::try_from(table.minimum).unwrap()],
+            vec,
             maximum: table.maximum,
         }
     }
@@ -61,6 +71,15 @@ impl Table {
         Some(new_len)
     }
 
+    /// Set the number of elements back to `size`, dropping the elements
+    /// beyond it or appending null elements up to it.
+    pub fn restore(&mut self, size: u32) {
+        self.vec.resize(
+            usize::try_from(size).unwrap(),
+            VMCallerCheckedAnyfunc::default(),
+        );
+    }
+
     /// Get reference to the specified element.
     ///
     /// Returns `None` if the index is out of bounds.
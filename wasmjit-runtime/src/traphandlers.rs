@@ -1,13 +1,18 @@
 //! WebAssembly trap handling, which is built on top of the lower-level
 //! signalhandling mechanisms.
 
+use crate::builtins::wasmjit_result_err_trap;
 use crate::trap_registry::get_trap_registry;
 use crate::trap_registry::TrapDescription;
 use crate::vmcontext::{VMContext, VMFunctionBody};
 use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 use core::cell::Cell;
+use core::fmt;
 use core::ptr;
 use cranelift_codegen::ir;
+use cranelift_entity::EntityRef;
+use cranelift_wasm::DefinedFuncIndex;
 
 extern "C" {
     fn WasmtimeCallTrampoline(
@@ -20,10 +25,14 @@ extern "C" {
 }
 
 thread_local! {
-    static RECORDED_TRAP: Cell<Option<TrapDescription>> = Cell::new(None);
+    static RECORDED_TRAP: Cell<Option<RecordedTrap>> = Cell::new(None);
     static JMP_BUF: Cell<*const u8> = Cell::new(ptr::null());
 }
 
+/// A trap description, with the address of the trapping instruction and the
+/// faulting data address, if known.
+type RecordedTrap = (TrapDescription, Option<usize>, Option<usize>);
+
 /// Check if there is a trap at given PC
 #[doc(hidden)]
 #[allow(non_snake_case)]
@@ -41,7 +50,7 @@ pub extern "C" fn CheckIfTrapAtAddress(_pc: *const u8) -> i8 {
 #[doc(hidden)]
 #[allow(non_snake_case)]
 #[no_mangle]
-pub extern "C" fn RecordTrap(pc: *const u8) {
+pub extern "C" fn RecordTrap(pc: *const u8, fault_addr: *const u8) {
     // TODO: please see explanation in CheckIfTrapAtAddress.
     let registry = get_trap_registry();
     let trap_desc = registry
@@ -52,7 +61,12 @@ pub extern "C" fn RecordTrap(pc: *const u8) {
             discription: None,
         });
     RECORDED_TRAP.with(|data| {
-        let old = data.replace(Some(trap_desc));
+        let fault_addr = if fault_addr.is_null() {
+            None
+        } else {
+            Some(fault_addr as usize)
+        };
+        let old = data.replace(Some((trap_desc, Some(pc as usize), fault_addr)));
         assert_eq!(
             old, None,
             "Only one trap per thread can be recorded at a moment!"
@@ -81,17 +95,264 @@ pub extern "C" fn LeaveScope(ptr: *const u8) {
     JMP_BUF.with(|buf| buf.set(ptr))
 }
 
-fn trap_message() -> String {
-    let trap_desc = RECORDED_TRAP
+/// The reason of a trap, raised either by wasm code or by the runtime and host
+/// functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TrapCode {
+    /// The call stack was exhausted.
+    StackOverflow,
+    /// Out of bounds linear memory access.
+    MemoryOutOfBounds,
+    /// Out of bounds table access.
+    TableOutOfBounds,
+    /// Out of bounds access, other than memory or table.
+    OutOfBounds,
+    /// Indirect call to an uninitialized table element, checked before the
+    /// signature.
+    IndirectCallToNull,
+    /// Indirect call signature mismatch: the signature of the element is not
+    /// the type of the `call_indirect`.
+    BadSignature,
+    /// Integer arithmetic overflow, also raised by the trapping float to int
+    /// truncations of a value out of the range of the integer, infinities
+    /// included.
+    IntegerOverflow,
+    /// Integer division by zero.
+    IntegerDivisionByZero,
+    /// Failed float to integer conversion: a trapping truncation of NaN.
+    BadConversionToInteger,
+    /// The `unreachable` instruction was executed. This is how a contract
+    /// compiled from Rust reports a `panic!` or an `abort`, it is never raised
+    /// for a fault of the engine or a memory bug.
+    Unreachable,
+    /// Execution interrupted.
+    Interrupt,
+    /// The wall-clock timeout of the execution expired.
+    Timeout,
+    /// The gas of the execution is exhausted.
+    OutOfGas,
+    /// A host function called back into an instance which is already
+    /// executing, while its reentrancy guard is on.
+    Reentrancy,
+    /// Trap raised by a host function or builtin, see its message.
+    Host,
+    /// The cancel flag of the call was set by the embedder.
+    Cancelled,
+}
+
+/// User trap codes of the runtime, carried in `ir::TrapCode::User`.
+const USER_TRAP_HOST: u16 = 0;
+const USER_TRAP_TIMEOUT: u16 = 1;
+const USER_TRAP_OUT_OF_GAS: u16 = 2;
+const USER_TRAP_REENTRANCY: u16 = 3;
+const USER_TRAP_CANCELLED: u16 = 4;
+
+impl TrapCode {
+    /// Convert a cranelift trap code.
+    pub fn from_ir(code: ir::TrapCode) -> Self {
+        match code {
+            ir::TrapCode::StackOverflow => TrapCode::StackOverflow,
+            ir::TrapCode::HeapOutOfBounds => TrapCode::MemoryOutOfBounds,
+            ir::TrapCode::TableOutOfBounds => TrapCode::TableOutOfBounds,
+            ir::TrapCode::OutOfBounds => TrapCode::OutOfBounds,
+            ir::TrapCode::IndirectCallToNull => TrapCode::IndirectCallToNull,
+            ir::TrapCode::BadSignature => TrapCode::BadSignature,
+            ir::TrapCode::IntegerOverflow => TrapCode::IntegerOverflow,
+            ir::TrapCode::IntegerDivisionByZero => TrapCode::IntegerDivisionByZero,
+            ir::TrapCode::BadConversionToInteger => TrapCode::BadConversionToInteger,
+            ir::TrapCode::UnreachableCodeReached => TrapCode::Unreachable,
+            ir::TrapCode::Interrupt => TrapCode::Interrupt,
+            ir::TrapCode::User(USER_TRAP_TIMEOUT) => TrapCode::Timeout,
+            ir::TrapCode::User(USER_TRAP_OUT_OF_GAS) => TrapCode::OutOfGas,
+            ir::TrapCode::User(USER_TRAP_REENTRANCY) => TrapCode::Reentrancy,
+            ir::TrapCode::User(USER_TRAP_CANCELLED) => TrapCode::Cancelled,
+            ir::TrapCode::User(_) => TrapCode::Host,
+        }
+    }
+
+    /// Convert to a cranelift trap code, the runtime codes become user codes.
+    pub fn to_ir(self) -> ir::TrapCode {
+        match self {
+            TrapCode::StackOverflow => ir::TrapCode::StackOverflow,
+            TrapCode::MemoryOutOfBounds => ir::TrapCode::HeapOutOfBounds,
+            TrapCode::TableOutOfBounds => ir::TrapCode::TableOutOfBounds,
+            TrapCode::OutOfBounds => ir::TrapCode::OutOfBounds,
+            TrapCode::IndirectCallToNull => ir::TrapCode::IndirectCallToNull,
+            TrapCode::BadSignature => ir::TrapCode::BadSignature,
+            TrapCode::IntegerOverflow => ir::TrapCode::IntegerOverflow,
+            TrapCode::IntegerDivisionByZero => ir::TrapCode::IntegerDivisionByZero,
+            TrapCode::BadConversionToInteger => ir::TrapCode::BadConversionToInteger,
+            TrapCode::Unreachable => ir::TrapCode::UnreachableCodeReached,
+            TrapCode::Interrupt => ir::TrapCode::Interrupt,
+            TrapCode::Timeout => ir::TrapCode::User(USER_TRAP_TIMEOUT),
+            TrapCode::OutOfGas => ir::TrapCode::User(USER_TRAP_OUT_OF_GAS),
+            TrapCode::Reentrancy => ir::TrapCode::User(USER_TRAP_REENTRANCY),
+            TrapCode::Host => ir::TrapCode::User(USER_TRAP_HOST),
+            TrapCode::Cancelled => ir::TrapCode::User(USER_TRAP_CANCELLED),
+        }
+    }
+
+    /// The stable encoding of the code recorded in transaction receipts.
+    ///
+    /// The values are owned by this crate and never reused nor renumbered,
+    /// whatever the order of the variants or the cranelift trap codes. `0`
+    /// is left unused for a successful execution.
+    pub fn to_consensus_code(self) -> u32 {
+        match self {
+            TrapCode::StackOverflow => 1,
+            TrapCode::MemoryOutOfBounds => 2,
+            TrapCode::TableOutOfBounds => 3,
+            TrapCode::OutOfBounds => 4,
+            TrapCode::IndirectCallToNull => 5,
+            TrapCode::BadSignature => 6,
+            TrapCode::IntegerOverflow => 7,
+            TrapCode::IntegerDivisionByZero => 8,
+            TrapCode::BadConversionToInteger => 9,
+            TrapCode::Unreachable => 10,
+            TrapCode::Interrupt => 11,
+            TrapCode::Timeout => 12,
+            TrapCode::OutOfGas => 13,
+            TrapCode::Reentrancy => 14,
+            TrapCode::Host => 15,
+            TrapCode::Cancelled => 16,
+        }
+    }
+
+    /// Decode a code encoded by `to_consensus_code`, `None` if it is unknown.
+    pub fn from_consensus_code(code: u32) -> Option<Self> {
+        let code = match code {
+            1 => TrapCode::StackOverflow,
+            2 => TrapCode::MemoryOutOfBounds,
+            3 => TrapCode::TableOutOfBounds,
+            4 => TrapCode::OutOfBounds,
+            5 => TrapCode::IndirectCallToNull,
+            6 => TrapCode::BadSignature,
+            7 => TrapCode::IntegerOverflow,
+            8 => TrapCode::IntegerDivisionByZero,
+            9 => TrapCode::BadConversionToInteger,
+            10 => TrapCode::Unreachable,
+            11 => TrapCode::Interrupt,
+            12 => TrapCode::Timeout,
+            13 => TrapCode::OutOfGas,
+            14 => TrapCode::Reentrancy,
+            15 => TrapCode::Host,
+            16 => TrapCode::Cancelled,
+            _ => return None,
+        };
+        Some(code)
+    }
+}
+
+/// The wasm frame of a trapping instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// The function executing in the frame.
+    pub func_index: DefinedFuncIndex,
+    /// Name of the function from the name section, if any.
+    pub func_name: Option<String>,
+    /// Offset in the module of the instruction executing in the frame, if known.
+    pub module_offset: Option<usize>,
+}
+
+impl fmt::Display for FrameInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.func_name {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "defined function {}", self.func_index.index())?,
+        }
+        match self.module_offset {
+            Some(offset) => write!(f, " at module offset {:#x}", offset),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The linear memory access which raised a `MemoryOutOfBounds` trap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// Offset in the linear memory of the faulting byte: the start of the
+    /// access, or the end of the memory for an access straddling it. `None`
+    /// when the access was stopped by an explicit bounds check rather than
+    /// by a guard page.
+    pub offset: Option<u64>,
+    /// Size in bytes of the access.
+    pub size: u32,
+}
+
+impl fmt::Display for MemoryAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "access of {} bytes", self.size)?;
+        match self.offset {
+            Some(offset) => write!(f, " faulting at memory offset {:#x}", offset),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A trap which ended a wasm call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trap {
+    /// Why the execution trapped.
+    pub code: TrapCode,
+    /// Human readable description, including the source location.
+    pub message: String,
+    /// Native address of the trapping instruction, `None` for the traps
+    /// raised by the runtime and host functions.
+    pub pc: Option<usize>,
+    /// Offset in the module of the trapping instruction, if known.
+    pub module_offset: Option<usize>,
+    /// Native data address whose access faulted, for the traps raised by a
+    /// guard page.
+    pub fault_address: Option<usize>,
+    /// The linear memory access of a `MemoryOutOfBounds` trap, if known.
+    /// Filled in by the embedder along with the frame.
+    pub memory_access: Option<MemoryAccess>,
+    /// The wasm frame of the trapping instruction, `None` for the traps
+    /// without a `pc`. The stack is not walked, the callers of the trapping
+    /// function are not known. Filled in by the embedder, which knows where
+    /// the functions of the module live.
+    pub frame: Option<FrameInfo>,
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)?;
+        if let Some(access) = &self.memory_access {
+            write!(f, ", {}", access)?;
+        }
+        if let Some(frame) = &self.frame {
+            write!(f, "\n    in {}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+fn recorded_trap() -> Trap {
+    let (trap_desc, pc, fault_address) = RECORDED_TRAP
         .with(|data| data.replace(None))
-        .expect("trap_message must be called after trap occurred");
+        .expect("recorded_trap must be called after trap occurred");
 
+    let code = TrapCode::from_ir(trap_desc.trap_code);
     let source = trap_desc.source_loc;
-    format!(
+    let module_offset = if source.is_default() {
+        None
+    } else {
+        Some(source.bits() as usize)
+    };
+    let message = format!(
         "wasm trap: {}, source location: {}",
         trap_code_to_expected_string(trap_desc),
         source,
-    )
+    );
+    Trap {
+        code,
+        message,
+        pc,
+        module_offset,
+        fault_address,
+        memory_access: None,
+        frame: None,
+    }
 }
 
 fn trap_code_to_expected_string(trap: TrapDescription) -> String {
@@ -120,12 +381,10 @@ pub unsafe fn wasmjit_call_trampoline(
     vmctx: *mut VMContext,
     callee: *const VMFunctionBody,
     values_vec: *mut u8,
-) -> Result<(), String> {
-    if WasmtimeCallTrampoline(vmctx as *mut u8, callee, values_vec) == 0 {
-        Err(trap_message())
-    } else {
-        Ok(())
-    }
+) -> Result<(), Trap> {
+    guarded_call(vmctx, || {
+        WasmtimeCallTrampoline(vmctx as *mut u8, callee, values_vec)
+    })
 }
 
 /// Call the wasm function pointed to by `callee`, which has no arguments or
@@ -134,9 +393,28 @@ pub unsafe fn wasmjit_call_trampoline(
 pub unsafe fn wasmjit_call(
     vmctx: *mut VMContext,
     callee: *const VMFunctionBody,
-) -> Result<(), String> {
-    if WasmtimeCall(vmctx as *mut u8, callee) == 0 {
-        Err(trap_message())
+) -> Result<(), Trap> {
+    guarded_call(vmctx, || WasmtimeCall(vmctx as *mut u8, callee))
+}
+
+/// Run `call` with the instance of `vmctx` marked as executing. Reentering an
+/// instance with the reentrancy guard on traps the outer call instead, so the
+/// host function attempting it never resumes.
+unsafe fn guarded_call(vmctx: *mut VMContext, call: impl FnOnce() -> i32) -> Result<(), Trap> {
+    let instance = (&mut *vmctx).instance();
+    let was_executing = instance.executing;
+    if was_executing && instance.reentrancy_guard {
+        instance.set_trap_kind(wasmjit_result_err_trap);
+        let msg = String::from("wasmjit: reentrant call into an executing instance");
+        wasmjit_trap(TrapCode::Reentrancy, msg)
+    }
+    instance.executing = true;
+    let returned = call();
+    // The call returns here even when it traps, so the flag is always reset.
+    (&mut *vmctx).instance().executing = was_executing;
+
+    if returned == 0 {
+        Err(recorded_trap())
     } else {
         Ok(())
     }
@@ -145,13 +423,18 @@ pub unsafe fn wasmjit_call(
 /// unwind current execution and cause a wasm Trap.
 #[no_mangle]
 pub unsafe fn wasmjit_unwind(msg: String) -> ! {
+    wasmjit_trap(TrapCode::Host, msg)
+}
+
+/// unwind current execution and cause a wasm Trap with the given code.
+pub unsafe fn wasmjit_trap(code: TrapCode, msg: String) -> ! {
     let trap_desc = TrapDescription {
         source_loc: ir::SourceLoc::default(),
-        trap_code: ir::TrapCode::User(0),
+        trap_code: code.to_ir(),
         discription: Some(msg),
     };
     RECORDED_TRAP.with(|data| {
-        let old = data.replace(Some(trap_desc));
+        let old = data.replace(Some((trap_desc, None, None)));
         assert_eq!(
             old, None,
             "Only one trap per thread can be recorded at a moment!"
@@ -160,3 +443,37 @@ pub unsafe fn wasmjit_unwind(msg: String) -> ! {
 
     Unwind();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consensus_codes() {
+        // these values are part of the consensus, they must never change
+        let codes = [
+            (TrapCode::StackOverflow, 1),
+            (TrapCode::MemoryOutOfBounds, 2),
+            (TrapCode::TableOutOfBounds, 3),
+            (TrapCode::OutOfBounds, 4),
+            (TrapCode::IndirectCallToNull, 5),
+            (TrapCode::BadSignature, 6),
+            (TrapCode::IntegerOverflow, 7),
+            (TrapCode::IntegerDivisionByZero, 8),
+            (TrapCode::BadConversionToInteger, 9),
+            (TrapCode::Unreachable, 10),
+            (TrapCode::Interrupt, 11),
+            (TrapCode::Timeout, 12),
+            (TrapCode::OutOfGas, 13),
+            (TrapCode::Reentrancy, 14),
+            (TrapCode::Host, 15),
+            (TrapCode::Cancelled, 16),
+        ];
+        for &(trap, code) in &codes {
+            assert_eq!(trap.to_consensus_code(), code);
+            assert_eq!(TrapCode::from_consensus_code(code), Some(trap));
+        }
+        assert_eq!(TrapCode::from_consensus_code(0), None);
+        assert_eq!(TrapCode::from_consensus_code(17), None);
+    }
+}
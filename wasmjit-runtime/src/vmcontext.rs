@@ -12,6 +12,11 @@ use ontio_wasmjit_environ::BuiltinFunctionIndex;
 pub struct VMFunctionImport {
     /// A pointer to the imported function body.
     pub body: *const VMFunctionBody,
+
+    /// A pointer to the `VMContext` the function is called with, the one of
+    /// the exporting instance. Host functions leave it null and get the
+    /// `VMContext` of the importing instance.
+    pub vmctx: *mut VMContext,
 }
 
 #[cfg(test)]
@@ -32,6 +37,10 @@ mod test_vmfunction_import {
             offset_of!(VMFunctionImport, body),
             usize::from(offsets.vmfunction_import_body())
         );
+        assert_eq!(
+            offset_of!(VMFunctionImport, vmctx),
+            usize::from(offsets.vmfunction_import_vmctx())
+        );
     }
 }
 
@@ -426,6 +435,10 @@ impl VMBuiltinFunctionsArray {
             wasmjit_check_gas as usize;
         ptrs[BuiltinFunctionIndex::get_check_depth_index().index() as usize] =
             wasmjit_check_depth as usize;
+        ptrs[BuiltinFunctionIndex::get_trace_host_call_index().index() as usize] =
+            wasmjit_trace_host_call as usize;
+        ptrs[BuiltinFunctionIndex::get_charge_host_call_index().index() as usize] =
+            wasmjit_charge_host_call as usize;
         Self { ptrs }
     }
 }